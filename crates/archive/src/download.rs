@@ -0,0 +1,210 @@
+//! Provides functions for starting archive downloads that can resume where an
+//! earlier, interrupted download of the same archive left off.
+
+use std::cell::Cell;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use failure;
+use headers_011::Headers011;
+use reqwest::header::{AUTHORIZATION, ETAG, IF_RANGE};
+use reqwest::hyper_011::header::{ByteRangeSpec, Range};
+use reqwest::{Response, StatusCode};
+
+/// An in-progress download of an archive into a partial file, which is moved to
+/// the cache file by `finish` once the whole archive has been read.
+pub(crate) struct Download {
+    /// The bytes downloaded by an earlier attempt, if this download resumes it.
+    pub(crate) resumed: Option<File>,
+    /// The number of bytes downloaded by an earlier attempt.
+    pub(crate) offset: u64,
+    /// The response carrying the rest of the archive.
+    pub(crate) response: Response,
+    /// The partial file, to which the rest of the archive should be written.
+    pub(crate) file: File,
+}
+
+/// Produces the path with the given suffix appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// The file an archive is downloaded into before it is complete.
+pub fn partial_file(cache_file: &Path) -> PathBuf {
+    with_suffix(cache_file, ".partial")
+}
+
+/// The file recording the `ETag` of a partially downloaded archive, which is used
+/// to check that the archive hasn't changed on the server before resuming.
+fn etag_file(cache_file: &Path) -> PathBuf {
+    with_suffix(cache_file, ".etag")
+}
+
+/// Sends a request for the archive at the given URL, asking for just the bytes
+/// from `offset` onward if it is nonzero.
+fn request(
+    url: &str,
+    offset: u64,
+    etag: Option<&str>,
+    authorization: Option<&str>,
+    timeout: Duration,
+) -> Result<Response, failure::Error> {
+    let client = super::http_client(timeout)?;
+    let mut request = client.get(url);
+
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+
+    if offset > 0 {
+        request = request.header_011(Range::Bytes(vec![ByteRangeSpec::AllFrom(offset)]));
+        if let Some(etag) = etag {
+            request = request.header(IF_RANGE, etag);
+        }
+    }
+
+    Ok(request.send()?)
+}
+
+/// Starts downloading the archive at the given URL. If an earlier download of the
+/// archive to the same cache file was interrupted, this asks the server for just
+/// the remaining bytes, as long as the archive hasn't changed since, according to
+/// the strong `ETag` recorded by the earlier download. If no strong `ETag` was
+/// recorded, the server doesn't support byte ranges, or the archive has changed,
+/// the download starts over. The value of `authorization`, if any, is sent as the
+/// HTTP `"Authorization"` header, and any request taking longer than `timeout`
+/// is abandoned.
+pub(crate) fn start(
+    url: &str,
+    cache_file: &Path,
+    authorization: Option<&str>,
+    timeout: Duration,
+) -> Result<Download, failure::Error> {
+    let partial_file = partial_file(cache_file);
+    let etag_file = etag_file(cache_file);
+
+    let mut offset = fs::metadata(&partial_file)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if offset < super::HEADER_LEN {
+        // too little was downloaded to check the archive's format, so start over
+        offset = 0;
+    }
+    // weak validators can't be used with `If-Range`
+    let etag = fs::read_to_string(&etag_file)
+        .ok()
+        .filter(|etag| !etag.starts_with("W/"));
+    if etag.is_none() {
+        // without a validator there is no telling whether the archive has changed
+        // since the partial file was written, so start over
+        offset = 0;
+    }
+
+    let etag = etag.as_ref().map(String::as_str);
+    let mut response = request(url, offset, etag, authorization, timeout)?;
+
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // the partial file is no shorter than the archive, so it can't be trusted
+        offset = 0;
+        response = request(url, offset, None, authorization, timeout)?;
+    }
+
+    if !response.status().is_success() {
+        Err(super::HttpError {
+            code: response.status(),
+        })?;
+    }
+
+    match response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+    {
+        Some(etag) => fs::write(&etag_file, etag)?,
+        None => {
+            let _ = fs::remove_file(&etag_file);
+        }
+    }
+
+    if offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT {
+        let resumed = File::open(&partial_file)?;
+        let file = OpenOptions::new().append(true).open(&partial_file)?;
+        return Ok(Download {
+            resumed: Some(resumed),
+            offset,
+            response,
+            file,
+        });
+    }
+
+    Ok(Download {
+        resumed: None,
+        offset: 0,
+        response,
+        file: File::create(&partial_file)?,
+    })
+}
+
+/// Moves a completely downloaded archive from its partial file to the cache file.
+pub(crate) fn finish(cache_file: &Path) -> Result<(), failure::Error> {
+    fs::rename(partial_file(cache_file), cache_file)?;
+    let _ = fs::remove_file(etag_file(cache_file));
+    Ok(())
+}
+
+/// Removes the partial file and `ETag` of a download whose bytes turned out not to
+/// be a valid archive, so that the next download starts over instead of resuming.
+pub(crate) fn discard(cache_file: &Path) {
+    let _ = fs::remove_file(partial_file(cache_file));
+    let _ = fs::remove_file(etag_file(cache_file));
+}
+
+/// Reads the response carrying a download, recording whether reading from it ever
+/// failed. A download interrupted by the network is kept to be resumed later, while
+/// one whose bytes can't be decoded is discarded.
+pub(crate) struct Interruptible<R> {
+    inner: R,
+    interrupted: Rc<Cell<bool>>,
+}
+
+impl<R: Read> Interruptible<R> {
+    /// Wraps a response, along with the flag to set if reading from it fails.
+    pub(crate) fn new(inner: R, interrupted: Rc<Cell<bool>>) -> Self {
+        Interruptible { inner, interrupted }
+    }
+}
+
+impl<R: Read> Read for Interruptible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|error| {
+            self.interrupted.set(true);
+            error
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{etag_file, partial_file};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_partial_files() {
+        let cache_file = PathBuf::from("inventory").join("node-v10.13.0-linux-x64.tar.gz");
+
+        assert_eq!(
+            partial_file(&cache_file),
+            PathBuf::from("inventory").join("node-v10.13.0-linux-x64.tar.gz.partial")
+        );
+        assert_eq!(
+            etag_file(&cache_file),
+            PathBuf::from("inventory").join("node-v10.13.0-linux-x64.tar.gz.etag")
+        );
+    }
+}
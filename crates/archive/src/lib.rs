@@ -1,8 +1,10 @@
 //! This crate provides types for fetching and unpacking compressed
-//! archives in tarball or zip format.
+//! archives in tarball (gzip or xz) or zip format.
 
 #![cfg_attr(feature = "universal-docs", feature(doc_cfg))]
 
+mod download;
+mod proxy;
 mod tarball;
 mod zip;
 
@@ -14,11 +16,95 @@ pub struct HttpError {
     pub code: ::reqwest::StatusCode,
 }
 
+/// Indicates that an archive's contents are not in the expected format, e.g. because
+/// a server responded with an HTML page instead of the archive.
+#[derive(Fail, Debug)]
+#[fail(display = "expected {} archive, found {}", expected, detected)]
+pub struct FormatError {
+    pub expected: String,
+    pub detected: String,
+}
+
+pub use crate::download::partial_file;
+pub use crate::proxy::{parse_proxy_url, proxy_var, ProxyError, HTTPS_PROXY, HTTP_PROXY, NO_PROXY};
 pub use crate::tarball::Tarball;
 pub use crate::zip::Zip;
 
 use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::time::Duration;
+
+/// The environment variable that sets the connect and read timeouts for HTTP
+/// requests, in seconds.
+pub const NETWORK_TIMEOUT: &'static str = "NOTION_NETWORK_TIMEOUT";
+
+/// The connect and read timeout for HTTP requests, in seconds, if `NETWORK_TIMEOUT`
+/// isn't set.
+pub const DEFAULT_NETWORK_TIMEOUT_SECS: u64 = 30;
+
+/// Builds an HTTP client that gives up on connecting, reading, or writing after
+/// `timeout`, and connects through the proxies named by `HTTP_PROXY` and
+/// `HTTPS_PROXY`, except for the hosts listed in `NO_PROXY`.
+pub fn http_client(timeout: Duration) -> Result<reqwest::Client, failure::Error> {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    for proxy in proxy::proxies()? {
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build()?)
+}
+
+/// The number of leading bytes needed to identify an archive format.
+const HEADER_LEN: u64 = 4;
+
+/// Identifies the format of an archive from its leading bytes.
+fn detect_format(header: &[u8]) -> &'static str {
+    if header.starts_with(&[0x1f, 0x8b]) {
+        "tar.gz"
+    } else if header.starts_with(&[0xfd, b'7', b'z', b'X']) {
+        "tar.xz"
+    } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        "zip"
+    } else if header.is_empty() {
+        "empty"
+    } else if header
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .map_or(false, |byte| *byte == b'<')
+    {
+        "HTML"
+    } else {
+        "unknown"
+    }
+}
+
+/// Checks that the leading bytes of an archive match the expected format.
+fn check_format(header: &[u8], expected: &str) -> Result<(), FormatError> {
+    let detected = detect_format(header);
+    if detected != expected {
+        return Err(FormatError {
+            expected: expected.to_string(),
+            detected: detected.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Reads the leading bytes of a data source, for passing to `check_format`.
+fn read_header<R: Read>(source: R) -> Result<Vec<u8>, failure::Error> {
+    let mut header = Vec::new();
+    source.take(HEADER_LEN).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Checks that the archive file is in the expected format, leaving the file positioned
+/// at its start.
+fn check_file_format(file: &mut File, expected: &str) -> Result<(), failure::Error> {
+    let header = read_header(&mut *file)?;
+    file.seek(SeekFrom::Start(0))?;
+    check_format(&header, expected)?;
+    Ok(())
+}
 
 /// Metadata describing whether an archive comes from a local or remote origin.
 #[derive(Copy, Clone)]
@@ -38,6 +124,10 @@ pub trait Archive {
         progress: &mut FnMut(&(), usize),
     ) -> Result<(), failure::Error>;
 
+    /// Reads the rest of the archive without unpacking it, so that a remote archive
+    /// is completely downloaded into its cache file. Does nothing for a local archive.
+    fn cache(self: Box<Self>) -> Result<(), failure::Error>;
+
     fn origin(&self) -> Origin;
 }
 
@@ -52,12 +142,22 @@ cfg_if::cfg_if! {
         }
 
         /// Fetch a remote archive in the native OS-preferred format from the specified
-        /// URL and store its results at the specified file path.
+        /// URL and store its results at the specified file path, giving up on any
+        /// request that takes longer than `timeout`.
         ///
         /// On Windows, the preferred format is zip. On Unixes, the preferred format
         /// is tarball.
-        pub fn fetch_native(url: &str, cache_file: &Path) -> Result<Box<Archive>, failure::Error> {
-            Tarball::fetch(url, cache_file)
+        pub fn fetch_native(url: &str, cache_file: &Path, timeout: Duration) -> Result<Box<Archive>, failure::Error> {
+            Tarball::fetch(url, cache_file, timeout)
+        }
+
+        /// Like `fetch_native`, but reports download progress to the `fetch_progress`
+        /// callback as `(downloaded, total)` byte counts.
+        pub fn fetch_native_with_progress<F>(url: &str, cache_file: &Path, timeout: Duration, fetch_progress: F) -> Result<Box<Archive>, failure::Error>
+        where
+            F: FnMut(u64, Option<u64>) + 'static,
+        {
+            Tarball::fetch_with_progress(url, cache_file, timeout, fetch_progress)
         }
     } else if #[cfg(windows)] {
         /// Load an archive in the native OS-preferred format from the specified file.
@@ -69,14 +169,48 @@ cfg_if::cfg_if! {
         }
 
         /// Fetch a remote archive in the native OS-preferred format from the specified
-        /// URL and store its results at the specified file path.
+        /// URL and store its results at the specified file path, giving up on any
+        /// request that takes longer than `timeout`.
         ///
         /// On Windows, the preferred format is zip. On Unixes, the preferred format
         /// is tarball.
-        pub fn fetch_native(url: &str, cache_file: &Path) -> Result<Box<Archive>, failure::Error> {
-            Zip::fetch(url, cache_file)
+        pub fn fetch_native(url: &str, cache_file: &Path, timeout: Duration) -> Result<Box<Archive>, failure::Error> {
+            Zip::fetch(url, cache_file, timeout)
+        }
+
+        /// Like `fetch_native`, but reports download progress to the `fetch_progress`
+        /// callback as `(downloaded, total)` byte counts.
+        pub fn fetch_native_with_progress<F>(url: &str, cache_file: &Path, timeout: Duration, fetch_progress: F) -> Result<Box<Archive>, failure::Error>
+        where
+            F: FnMut(u64, Option<u64>) + 'static,
+        {
+            Zip::fetch_with_progress(url, cache_file, timeout, fetch_progress)
         }
     } else {
         compile_error!("Unsupported OS (expected 'unix' or 'windows').");
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::{check_format, detect_format};
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(&[0x1f, 0x8b, 0x08, 0x00]), "tar.gz");
+        assert_eq!(detect_format(&[0xfd, b'7', b'z', b'X']), "tar.xz");
+        assert_eq!(detect_format(b"PK\x03\x04"), "zip");
+        assert_eq!(detect_format(b"\n<!D"), "HTML");
+        assert_eq!(detect_format(b""), "empty");
+        assert_eq!(detect_format(b"{\"er"), "unknown");
+    }
+
+    #[test]
+    fn test_check_format() {
+        assert!(check_format(&[0x1f, 0x8b, 0x08, 0x00], "tar.gz").is_ok());
+
+        let error = check_format(b"<htm", "zip").unwrap_err();
+        assert_eq!(error.expected, "zip");
+        assert_eq!(error.detected, "HTML");
+    }
+}
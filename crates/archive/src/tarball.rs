@@ -1,29 +1,62 @@
 //! Provides types and functions for fetching and unpacking a Node installation
-//! tarball in Unix operating systems.
+//! tarball, compressed with gzip or xz, in Unix operating systems.
 
+use std::cell::Cell;
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::Path;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
 use failure::{self, Fail};
 use flate2::read::GzDecoder;
 use headers_011::Headers011;
 use progress_read::ProgressRead;
-use reqwest;
+use reqwest::header::AUTHORIZATION;
 use reqwest::hyper_011::header::{AcceptRanges, ByteRangeSpec, ContentLength, Range, RangeUnit};
 use reqwest::Response;
 use tar;
 use tee::TeeReader;
+use xz2::read::XzDecoder;
 
-use super::Archive;
-use super::Origin;
+use super::{detect_format, download, read_header, Archive, FormatError, Origin};
+
+/// The compression format of a tarball.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Compression {
+    Gzip,
+    Xz,
+}
+
+impl Compression {
+    /// Determines the compression format of a tarball from its leading bytes.
+    fn detect(header: &[u8]) -> Result<Compression, FormatError> {
+        match detect_format(header) {
+            "tar.gz" => Ok(Compression::Gzip),
+            "tar.xz" => Ok(Compression::Xz),
+            detected => Err(FormatError {
+                expected: "tar.gz".to_string(),
+                detected: detected.to_string(),
+            }),
+        }
+    }
+}
 
 /// A Node installation tarball.
 pub struct Tarball {
+    compression: Compression,
     compressed_size: u64,
-    uncompressed_size: u64,
+    /// The size of the unpacked tarball, which can only be determined up front for
+    /// gzip compression.
+    uncompressed_size: Option<u64>,
     data: Box<Read>,
     origin: Origin,
+    /// The file the tarball is being downloaded to, if it is being fetched; the
+    /// download is only complete once the tarball has been unpacked.
+    cache_file: Option<PathBuf>,
+    /// Whether the download was interrupted by a failure to read from the network,
+    /// rather than by a failure to decode the tarball.
+    interrupted: Rc<Cell<bool>>,
 }
 
 #[derive(Fail, Debug)]
@@ -48,63 +81,226 @@ fn content_length(response: &Response) -> Result<u64, failure::Error> {
 }
 
 impl Tarball {
-    /// Loads a tarball from the specified file.
+    /// Loads a tarball from the specified file, detecting its compression format
+    /// from its contents.
     pub fn load(mut source: File) -> Result<Box<Archive>, failure::Error> {
-        let uncompressed_size = load_uncompressed_size(&mut source)?;
+        let header = read_header(&mut source)?;
+        source.seek(SeekFrom::Start(0))?;
+        let compression = Compression::detect(&header)?;
+        let uncompressed_size = match compression {
+            Compression::Gzip => Some(load_uncompressed_size(&mut source)?),
+            Compression::Xz => None,
+        };
         let compressed_size = source.metadata()?.len();
         Ok(Box::new(Tarball {
+            compression,
             uncompressed_size,
             compressed_size,
             data: Box::new(source),
             origin: Origin::Local,
+            cache_file: None,
+            interrupted: Rc::new(Cell::new(false)),
         }))
     }
 
     /// Initiate fetching of a tarball from the given URL, returning a
     /// tarball that can be streamed (and that tees its data to a local
-    /// file as it streams).
-    pub fn fetch(url: &str, cache_file: &Path) -> Result<Box<Archive>, failure::Error> {
-        let response = reqwest::get(url)?;
-
-        if !response.status().is_success() {
-            Err(super::HttpError {
-                code: response.status(),
-            })?;
-        }
-
-        let compressed_size = content_length(&response)?;
+    /// file as it streams). An interrupted download is resumed if possible,
+    /// and any request taking longer than `timeout` is abandoned.
+    pub fn fetch(
+        url: &str,
+        cache_file: &Path,
+        timeout: Duration,
+    ) -> Result<Box<Archive>, failure::Error> {
+        Tarball::fetch_with_progress(url, cache_file, timeout, |_, _| {})
+    }
 
-        ensure_accepts_byte_ranges(&response)?;
+    /// Like `fetch`, but sends the given value of the HTTP `"Authorization"`
+    /// header with each request, e.g. to download from a private registry.
+    pub fn fetch_authorized(
+        url: &str,
+        cache_file: &Path,
+        authorization: &str,
+        timeout: Duration,
+    ) -> Result<Box<Archive>, failure::Error> {
+        Tarball::fetch_from(url, cache_file, Some(authorization), timeout, |_, _| {})
+    }
 
-        let uncompressed_size = fetch_uncompressed_size(url, compressed_size)?;
+    /// Like `fetch`, but reports download progress to the `fetch_progress`
+    /// callback as the tarball is streamed. The callback is passed the number
+    /// of bytes downloaded so far and the total size of the download, taken
+    /// from the HTTP `"Content-Length"` header.
+    pub fn fetch_with_progress<F>(
+        url: &str,
+        cache_file: &Path,
+        timeout: Duration,
+        fetch_progress: F,
+    ) -> Result<Box<Archive>, failure::Error>
+    where
+        F: FnMut(u64, Option<u64>) + 'static,
+    {
+        Tarball::fetch_from(url, cache_file, None, timeout, fetch_progress)
+    }
 
-        let file = File::create(cache_file)?;
-        let data = Box::new(TeeReader::new(response, file));
+    /// Fetches a tarball, sending the value of `authorization`, if any, as the
+    /// HTTP `"Authorization"` header with each request.
+    fn fetch_from<F>(
+        url: &str,
+        cache_file: &Path,
+        authorization: Option<&str>,
+        timeout: Duration,
+        mut fetch_progress: F,
+    ) -> Result<Box<Archive>, failure::Error>
+    where
+        F: FnMut(u64, Option<u64>) + 'static,
+    {
+        let download = download::start(url, cache_file, authorization, timeout)?;
+        let compressed_size = download.offset + content_length(&download.response)?;
+
+        let interrupted = Rc::new(Cell::new(false));
+        let (compression, source) = match open_download(download, interrupted.clone()) {
+            Ok(opened) => opened,
+            Err(error) => {
+                download::discard(cache_file);
+                return Err(error);
+            }
+        };
+
+        let uncompressed_size = match compression {
+            Compression::Gzip => Some(fetch_uncompressed_size(
+                url,
+                compressed_size,
+                authorization,
+                timeout,
+            )?),
+            Compression::Xz => None,
+        };
+
+        let data = Box::new(ProgressRead::new(source, 0, move |total: &u64, read| {
+            let total = total + read as u64;
+            fetch_progress(total, Some(compressed_size));
+            total
+        }));
 
         Ok(Box::new(Tarball {
+            compression,
             uncompressed_size,
             compressed_size,
             data,
             origin: Origin::Remote,
+            cache_file: Some(cache_file.to_path_buf()),
+            interrupted,
         }))
     }
 }
 
+/// Detects the compression format of a tarball being downloaded, and produces the
+/// data source that streams it while saving it to the partial file: the bytes from
+/// an earlier download, if this one resumes it, followed by the response. Failures
+/// to read the response set `interrupted`.
+fn open_download(
+    download: download::Download,
+    interrupted: Rc<Cell<bool>>,
+) -> Result<(Compression, Box<Read>), failure::Error> {
+    let download::Download {
+        resumed,
+        mut response,
+        file,
+        ..
+    } = download;
+
+    match resumed {
+        // the server has already shown that it accepts byte ranges
+        Some(mut resumed) => {
+            let header = read_header(&mut resumed)?;
+            let compression = Compression::detect(&header)?;
+            resumed.seek(SeekFrom::Start(0))?;
+            let response = download::Interruptible::new(response, interrupted);
+            Ok((
+                compression,
+                Box::new(resumed.chain(TeeReader::new(response, file))),
+            ))
+        }
+        None => {
+            // the header is read before streaming begins, so it is replayed ahead
+            // of the rest of the response
+            let header = read_header(&mut response)?;
+            let compression = Compression::detect(&header)?;
+            ensure_accepts_byte_ranges(&response)?;
+            let response = download::Interruptible::new(response, interrupted);
+            let source = Cursor::new(header).chain(response);
+            Ok((compression, Box::new(TeeReader::new(source, file))))
+        }
+    }
+}
+
+/// Unpacks the tarball data to the specified destination folder. If it is being
+/// downloaded, the rest of the download is then read and moved into the cache.
+fn unpack_data(
+    compression: Compression,
+    data: Box<Read>,
+    cache_file: Option<&Path>,
+    dest: &Path,
+    progress: &mut FnMut(&(), usize),
+) -> Result<(), failure::Error> {
+    // progress is measured in the same units as the size the caller knows: the
+    // uncompressed size for gzip, and the compressed size for xz
+    let decoded: Box<Read + '_> = match compression {
+        Compression::Gzip => Box::new(ProgressRead::new(GzDecoder::new(data), (), progress)),
+        Compression::Xz => Box::new(XzDecoder::new(ProgressRead::new(data, (), progress))),
+    };
+    let mut tarball = tar::Archive::new(decoded);
+    tarball.unpack(dest)?;
+
+    if let Some(cache_file) = cache_file {
+        // unpacking can stop at the end of the tar entries, so the rest of the
+        // download is read before it is moved into the cache
+        io::copy(&mut tarball.into_inner(), &mut io::sink())?;
+        download::finish(cache_file)?;
+    }
+    Ok(())
+}
+
 impl Archive for Tarball {
     fn compressed_size(&self) -> u64 {
         self.compressed_size
     }
     fn uncompressed_size(&self) -> Option<u64> {
-        Some(self.uncompressed_size)
+        self.uncompressed_size
     }
     fn unpack(
         self: Box<Self>,
         dest: &Path,
         progress: &mut FnMut(&(), usize),
     ) -> Result<(), failure::Error> {
-        let decoded = GzDecoder::new(self.data);
-        let mut tarball = tar::Archive::new(ProgressRead::new(decoded, (), progress));
-        tarball.unpack(dest)?;
+        let Tarball {
+            compression,
+            data,
+            cache_file,
+            interrupted,
+            ..
+        } = *self;
+
+        let result = unpack_data(
+            compression,
+            data,
+            cache_file.as_ref().map(|f| f.as_path()),
+            dest,
+            progress,
+        );
+        if let (Err(_), Some(cache_file)) = (&result, &cache_file) {
+            if !interrupted.get() {
+                download::discard(cache_file);
+            }
+        }
+        result
+    }
+    fn cache(self: Box<Self>) -> Result<(), failure::Error> {
+        if let Some(cache_file) = self.cache_file {
+            let mut data = self.data;
+            io::copy(&mut data, &mut io::sink())?;
+            download::finish(&cache_file)?;
+        }
         Ok(())
     }
     fn origin(&self) -> Origin {
@@ -142,12 +338,20 @@ struct UnexpectedContentLengthError {
 /// of a gzip file from a URL. This makes two round-trips to the server but avoids
 /// downloading the entire gzip file. For very small files it's unlikely to be
 /// more efficient than simply downloading the entire file up front.
-fn fetch_isize(url: &str, len: u64) -> Result<[u8; 4], failure::Error> {
-    let client = reqwest::Client::new();
-    let mut response = client
+fn fetch_isize(
+    url: &str,
+    len: u64,
+    authorization: Option<&str>,
+    timeout: Duration,
+) -> Result<[u8; 4], failure::Error> {
+    let client = super::http_client(timeout)?;
+    let mut request = client
         .get(url)
-        .header_011(Range::Bytes(vec![ByteRangeSpec::FromTo(len - 4, len - 1)]))
-        .send()?;
+        .header_011(Range::Bytes(vec![ByteRangeSpec::FromTo(len - 4, len - 1)]));
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    let mut response = request.send()?;
 
     if !response.status().is_success() {
         Err(super::HttpError {
@@ -199,8 +403,13 @@ fn ensure_accepts_byte_ranges(response: &Response) -> Result<(), failure::Error>
 /// an extra round-trip to the server, so it's only more efficient than just
 /// downloading the file if the file is large enough that downloading it is
 /// slower than the extra round trips.
-fn fetch_uncompressed_size(url: &str, len: u64) -> Result<u64, failure::Error> {
-    let packed = fetch_isize(url, len)?;
+fn fetch_uncompressed_size(
+    url: &str,
+    len: u64,
+    authorization: Option<&str>,
+    timeout: Duration,
+) -> Result<u64, failure::Error> {
+    let packed = fetch_isize(url, len, authorization, timeout)?;
     Ok(unpack_isize(packed))
 }
 
@@ -214,8 +423,14 @@ fn load_uncompressed_size(file: &mut File) -> Result<u64, failure::Error> {
 pub mod tests {
 
     use crate::tarball::Tarball;
-    use std::fs::File;
+    use mockito::{self, mock, Matcher};
+    use std::cell::RefCell;
+    use std::fs::{self, File};
     use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
 
     fn fixture_path(fixture_dir: &str) -> PathBuf {
         let mut cargo_manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -234,4 +449,145 @@ pub mod tests {
         assert_eq!(tarball.uncompressed_size(), Some(10240));
         assert_eq!(tarball.compressed_size(), 402);
     }
+
+    #[test]
+    fn test_load_xz() {
+        let mut test_file_path = fixture_path("tarballs");
+        test_file_path.push("test-file.tar.xz");
+        let test_file = File::open(test_file_path).expect("Couldn't open test file");
+        let tarball = Tarball::load(test_file).expect("Failed to load tarball");
+
+        assert_eq!(tarball.uncompressed_size(), None);
+        assert_eq!(tarball.compressed_size(), 208);
+    }
+
+    #[test]
+    fn test_unpack_xz() {
+        let mut test_file_path = fixture_path("tarballs");
+        test_file_path.push("test-file.tar.xz");
+        let test_file = File::open(test_file_path).expect("Couldn't open test file");
+        let tarball = Tarball::load(test_file).expect("Failed to load tarball");
+
+        let dest = tempfile::tempdir().expect("Couldn't create temp dir");
+        tarball
+            .unpack(dest.path(), &mut |_, _| {})
+            .expect("Failed to unpack tarball");
+        assert!(dest.path().join("test-file.txt").is_file());
+    }
+
+    #[test]
+    fn test_fetch_resumes_partial_download() {
+        let fixture = fs::read(fixture_path("tarballs").join("test-file.tar.xz"))
+            .expect("Couldn't read test file");
+        let dir = tempfile::tempdir().expect("Couldn't create temp dir");
+        let cache_file = dir.path().join("test-file.tar.xz");
+        fs::write(dir.path().join("test-file.tar.xz.partial"), &fixture[..100])
+            .expect("Couldn't write partial file");
+        fs::write(dir.path().join("test-file.tar.xz.etag"), "\"v1\"")
+            .expect("Couldn't write ETag file");
+
+        let _mock = mock("GET", "/resume/test-file.tar.xz")
+            .match_header("Range", "bytes=100-")
+            .match_header("If-Range", "\"v1\"")
+            .with_status(206)
+            .with_header("ETag", "\"v1\"")
+            .with_body(&fixture[100..])
+            .create();
+
+        let url = format!("{}/resume/test-file.tar.xz", mockito::SERVER_URL);
+        let tarball = Tarball::fetch(&url, &cache_file, TIMEOUT).expect("Failed to fetch tarball");
+        assert_eq!(tarball.compressed_size(), fixture.len() as u64);
+
+        let dest = tempfile::tempdir().expect("Couldn't create temp dir");
+        tarball
+            .unpack(dest.path(), &mut |_, _| {})
+            .expect("Failed to unpack tarball");
+        assert!(dest.path().join("test-file.txt").is_file());
+        assert_eq!(fs::read(&cache_file).unwrap(), fixture);
+        assert!(!dir.path().join("test-file.tar.xz.partial").exists());
+        assert!(!dir.path().join("test-file.tar.xz.etag").exists());
+    }
+
+    #[test]
+    fn test_fetch_restarts_without_strong_etag() {
+        let fixture = fs::read(fixture_path("tarballs").join("test-file.tar.xz"))
+            .expect("Couldn't read test file");
+        let dir = tempfile::tempdir().expect("Couldn't create temp dir");
+        let cache_file = dir.path().join("test-file.tar.xz");
+        fs::write(dir.path().join("test-file.tar.xz.partial"), &fixture[..100])
+            .expect("Couldn't write partial file");
+        fs::write(dir.path().join("test-file.tar.xz.etag"), "W/\"v1\"")
+            .expect("Couldn't write ETag file");
+
+        let _mock = mock("GET", "/restart/test-file.tar.xz")
+            .match_header("Range", Matcher::Missing)
+            .with_header("Accept-Ranges", "bytes")
+            .with_body(&fixture)
+            .create();
+
+        let url = format!("{}/restart/test-file.tar.xz", mockito::SERVER_URL);
+        let dest = tempfile::tempdir().expect("Couldn't create temp dir");
+        Tarball::fetch(&url, &cache_file, TIMEOUT)
+            .expect("Failed to fetch tarball")
+            .unpack(dest.path(), &mut |_, _| {})
+            .expect("Failed to unpack tarball");
+        assert_eq!(fs::read(&cache_file).unwrap(), fixture);
+    }
+
+    #[test]
+    fn test_fetch_with_progress() {
+        let fixture = fs::read(fixture_path("tarballs").join("test-file.tar.xz"))
+            .expect("Couldn't read test file");
+        let dir = tempfile::tempdir().expect("Couldn't create temp dir");
+        let cache_file = dir.path().join("test-file.tar.xz");
+
+        let _mock = mock("GET", "/progress/test-file.tar.xz")
+            .with_body(&fixture)
+            .create();
+
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let progress = reports.clone();
+        let url = format!("{}/progress/test-file.tar.xz", mockito::SERVER_URL);
+        let dest = tempfile::tempdir().expect("Couldn't create temp dir");
+        Tarball::fetch_with_progress(&url, &cache_file, TIMEOUT, move |downloaded, total| {
+            progress.borrow_mut().push((downloaded, total));
+        })
+        .expect("Failed to fetch tarball")
+        .unpack(dest.path(), &mut |_, _| {})
+        .expect("Failed to unpack tarball");
+
+        let reports = reports.borrow();
+        let len = fixture.len() as u64;
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert!(reports.iter().all(|&(_, total)| total == Some(len)));
+        assert_eq!(reports.last(), Some(&(len, Some(len))));
+    }
+
+    #[test]
+    fn test_fetch_discards_undecodable_download() {
+        let mut corrupt = fs::read(fixture_path("tarballs").join("test-file.tar.xz"))
+            .expect("Couldn't read test file");
+        for byte in corrupt[12..].iter_mut() {
+            *byte = 0;
+        }
+        let dir = tempfile::tempdir().expect("Couldn't create temp dir");
+        let cache_file = dir.path().join("test-file.tar.xz");
+
+        let _mock = mock("GET", "/corrupt/test-file.tar.xz")
+            .match_header("Range", Matcher::Missing)
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("ETag", "\"v1\"")
+            .with_body(&corrupt)
+            .create();
+
+        let url = format!("{}/corrupt/test-file.tar.xz", mockito::SERVER_URL);
+        let dest = tempfile::tempdir().expect("Couldn't create temp dir");
+        let unpacked = Tarball::fetch(&url, &cache_file, TIMEOUT)
+            .expect("Failed to fetch tarball")
+            .unpack(dest.path(), &mut |_, _| {});
+        assert!(unpacked.is_err());
+        assert!(!cache_file.exists());
+        assert!(!dir.path().join("test-file.tar.xz.partial").exists());
+        assert!(!dir.path().join("test-file.tar.xz.etag").exists());
+    }
 }
@@ -1,53 +1,96 @@
 //! Provides types and functions for fetching and unpacking a Node installation
 //! zip file in Windows operating systems.
 
-use std::fs::{create_dir_all, File};
+use std::fs::{create_dir_all, remove_file, File};
 use std::io::copy;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use headers_011::Headers011;
 use progress_read::ProgressRead;
-use reqwest;
+use reqwest::hyper_011::header::ContentLength;
 use verbatim::PathExt;
 use zip_rs::ZipArchive;
 
 use failure;
 
-use super::Archive;
-use super::Origin;
+use super::{check_file_format, download, Archive, Origin};
 
 pub struct Zip {
     compressed_size: u64,
     data: File,
     origin: Origin,
+    /// The file the zip archive was downloaded to, if it was fetched, which is
+    /// removed if the archive can't be unpacked.
+    cache_file: Option<PathBuf>,
 }
 
 impl Zip {
     /// Loads a cached Node zip archive from the specified file.
-    pub fn load(source: File) -> Result<Box<Archive>, failure::Error> {
+    pub fn load(mut source: File) -> Result<Box<Archive>, failure::Error> {
+        check_file_format(&mut source, "zip")?;
         let compressed_size = source.metadata()?.len();
 
         Ok(Box::new(Zip {
             compressed_size,
             data: source,
             origin: Origin::Local,
+            cache_file: None,
         }))
     }
 
     /// Initiate fetching of a Node zip archive from the given URL, returning
-    /// a `Remote` data source.
-    pub fn fetch(url: &str, cache_file: &Path) -> Result<Box<Archive>, failure::Error> {
-        let mut response = reqwest::get(url)?;
-
-        if !response.status().is_success() {
-            Err(super::HttpError {
-                code: response.status(),
-            })?;
-        }
+    /// a `Remote` data source. An interrupted download is resumed if possible,
+    /// and any request taking longer than `timeout` is abandoned.
+    pub fn fetch(
+        url: &str,
+        cache_file: &Path,
+        timeout: Duration,
+    ) -> Result<Box<Archive>, failure::Error> {
+        Zip::fetch_with_progress(url, cache_file, timeout, |_, _| {})
+    }
 
+    /// Like `fetch`, but reports download progress to the `fetch_progress`
+    /// callback. The callback is passed the number of bytes downloaded so far
+    /// and the total size of the download, if the server sent a
+    /// `"Content-Length"` header.
+    pub fn fetch_with_progress<F>(
+        url: &str,
+        cache_file: &Path,
+        timeout: Duration,
+        mut fetch_progress: F,
+    ) -> Result<Box<Archive>, failure::Error>
+    where
+        F: FnMut(u64, Option<u64>) + 'static,
+    {
         {
-            let mut file = File::create(cache_file)?;
-            copy(&mut response, &mut file)?;
+            let download::Download {
+                offset,
+                response,
+                mut file,
+                ..
+            } = download::start(url, cache_file, None, timeout)?;
+
+            let content_length = response
+                .headers()
+                .get_011::<ContentLength>()
+                .map(|v| offset + v.0);
+
+            let mut downloaded = ProgressRead::new(response, offset, |total: &u64, read| {
+                let total = total + read as u64;
+                fetch_progress(total, content_length);
+                total
+            });
+            copy(&mut downloaded, &mut file)?;
+        }
+
+        let mut partial = File::open(download::partial_file(cache_file))?;
+        if let Err(error) = check_file_format(&mut partial, "zip") {
+            download::discard(cache_file);
+            return Err(error);
         }
+        drop(partial);
+        download::finish(cache_file)?;
 
         let file = File::open(cache_file)?;
         let compressed_size = file.metadata()?.len();
@@ -56,6 +99,7 @@ impl Zip {
             compressed_size,
             data: file,
             origin: Origin::Remote,
+            cache_file: Some(cache_file.to_path_buf()),
         }))
     }
 }
@@ -72,35 +116,17 @@ impl Archive for Zip {
         dest: &Path,
         progress: &mut FnMut(&(), usize),
     ) -> Result<(), failure::Error> {
-        // Use a verbatim path to avoid the legacy Windows 260 byte path limit.
-        let dest: &Path = &dest.to_verbatim();
-
-        let mut zip = ZipArchive::new(ProgressRead::new(self.data, (), progress))?;
-        for i in 0..zip.len() {
-            let mut entry = zip.by_index(i)?;
-
-            let (is_dir, subpath) = {
-                let name = entry.name();
-
-                // Verbatim paths aren't normalized so we have to use correct r"\" separators.
-                (
-                    name.ends_with('/'),
-                    Path::new(&name.replace('/', r"\")).to_path_buf(),
-                )
-            };
-
-            if is_dir {
-                create_dir_all(dest.join(subpath))?;
-            } else {
-                let mut file = {
-                    if let Some(basedir) = subpath.parent() {
-                        create_dir_all(dest.join(basedir))?;
-                    }
-                    File::create(dest.join(subpath))?
-                };
-                copy(&mut entry, &mut file)?;
-            }
+        let Zip {
+            data, cache_file, ..
+        } = *self;
+        let result = unpack_data(data, dest, progress);
+        if let (Err(_), Some(cache_file)) = (&result, &cache_file) {
+            let _ = remove_file(cache_file);
         }
+        result
+    }
+    fn cache(self: Box<Self>) -> Result<(), failure::Error> {
+        // a remote zip archive is completely downloaded before it is returned
         Ok(())
     }
     fn origin(&self) -> Origin {
@@ -108,12 +134,56 @@ impl Archive for Zip {
     }
 }
 
+/// Unpacks the zip archive data to the specified destination folder.
+fn unpack_data(
+    data: File,
+    dest: &Path,
+    progress: &mut FnMut(&(), usize),
+) -> Result<(), failure::Error> {
+    // Use a verbatim path to avoid the legacy Windows 260 byte path limit.
+    let dest: &Path = &dest.to_verbatim();
+
+    let mut zip = ZipArchive::new(ProgressRead::new(data, (), progress))?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+
+        let (is_dir, subpath) = {
+            let name = entry.name();
+
+            // Verbatim paths aren't normalized so we have to use correct r"\" separators.
+            (
+                name.ends_with('/'),
+                Path::new(&name.replace('/', r"\")).to_path_buf(),
+            )
+        };
+
+        if is_dir {
+            create_dir_all(dest.join(subpath))?;
+        } else {
+            let mut file = {
+                if let Some(basedir) = subpath.parent() {
+                    create_dir_all(dest.join(basedir))?;
+                }
+                File::create(dest.join(subpath))?
+            };
+            copy(&mut entry, &mut file)?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
 
     use crate::zip::Zip;
-    use std::fs::File;
+    use mockito::{self, mock};
+    use std::cell::RefCell;
+    use std::fs::{self, File};
     use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
 
     fn fixture_path(fixture_dir: &str) -> PathBuf {
         let mut cargo_manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -131,4 +201,29 @@ pub mod tests {
 
         assert_eq!(zip.compressed_size(), 214);
     }
+
+    #[test]
+    fn test_fetch_with_progress() {
+        let fixture =
+            fs::read(fixture_path("zips").join("test-file.zip")).expect("Couldn't read test file");
+        let dir = tempfile::tempdir().expect("Couldn't create temp dir");
+        let cache_file = dir.path().join("test-file.zip");
+
+        let _mock = mock("GET", "/progress/test-file.zip")
+            .with_body(&fixture)
+            .create();
+
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let progress = reports.clone();
+        let url = format!("{}/progress/test-file.zip", mockito::SERVER_URL);
+        let zip = Zip::fetch_with_progress(&url, &cache_file, TIMEOUT, move |downloaded, total| {
+            progress.borrow_mut().push((downloaded, total));
+        })
+        .expect("Failed to fetch zip file");
+        assert_eq!(zip.compressed_size(), 214);
+
+        let reports = reports.borrow();
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(reports.last(), Some(&(214, Some(214))));
+    }
 }
@@ -0,0 +1,261 @@
+//! Provides the `Config` type, which gathers the settings Notion reads from
+//! environment variables so that they are read and validated in one place.
+
+use std::env;
+use std::rc::Rc;
+use std::time::Duration;
+
+use lazycell::LazyCell;
+
+use crate::env::{ARCHIVE_FORMAT, ARCH_OVERRIDE, FORCE_FETCH, PRERELEASE, QUIET, SKIP_SMOKE_TEST};
+use crate::error::ErrorDetails;
+use crate::path::{SUPPORTED_ARCHIVE_FORMATS, SUPPORTED_ARCHS};
+use archive::{DEFAULT_NETWORK_TIMEOUT_SECS, NETWORK_TIMEOUT};
+use notion_fail::{throw, Fallible};
+
+/// The settings Notion reads from environment variables.
+#[derive(Debug)]
+pub struct Config {
+    network_timeout: Duration,
+    arch_override: Option<&'static str>,
+    archive_format: Option<&'static str>,
+    force_fetch: bool,
+    smoke_test: bool,
+    quiet: bool,
+    prerelease: bool,
+}
+
+impl Config {
+    /// Loads the settings from the environment of this process.
+    pub fn from_env() -> Fallible<Config> {
+        Config::from_vars(|name| {
+            env::var_os(name).map(|value| value.to_string_lossy().into_owned())
+        })
+    }
+
+    /// Loads the settings from the variables looked up by `var`, failing if any of
+    /// them is set to an invalid value.
+    pub fn from_vars<F>(var: F) -> Fallible<Config>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let network_timeout = match var(NETWORK_TIMEOUT) {
+            Some(value) => parse_network_timeout(&value)?,
+            None => Duration::from_secs(DEFAULT_NETWORK_TIMEOUT_SECS),
+        };
+
+        let arch_override = match var(ARCH_OVERRIDE) {
+            Some(arch) => match SUPPORTED_ARCHS.iter().find(|supported| **supported == arch) {
+                Some(supported) => Some(*supported),
+                None => throw!(ErrorDetails::UnsupportedArch { arch }),
+            },
+            None => None,
+        };
+
+        let archive_format = match var(ARCHIVE_FORMAT) {
+            Some(format) => match SUPPORTED_ARCHIVE_FORMATS
+                .iter()
+                .find(|supported| **supported == format)
+            {
+                Some(supported) => Some(*supported),
+                None => throw!(ErrorDetails::InvalidEnvironmentVar {
+                    name: ARCHIVE_FORMAT.to_string(),
+                    value: format,
+                    expected: format!("one of {}", SUPPORTED_ARCHIVE_FORMATS.join(", ")),
+                }),
+            },
+            None => None,
+        };
+
+        Ok(Config {
+            network_timeout,
+            arch_override,
+            archive_format,
+            force_fetch: var(FORCE_FETCH).is_some(),
+            smoke_test: var(SKIP_SMOKE_TEST).is_none(),
+            quiet: var(QUIET).is_some(),
+            prerelease: var(PRERELEASE).is_some(),
+        })
+    }
+
+    /// The connect and read timeout for HTTP requests.
+    pub fn network_timeout(&self) -> Duration {
+        self.network_timeout
+    }
+
+    /// The architecture to fetch Node distributions for, if overridden.
+    pub fn arch_override(&self) -> Option<&'static str> {
+        self.arch_override
+    }
+
+    /// The format of Node distribution archives to download, if overridden.
+    pub fn archive_format(&self) -> Option<&'static str> {
+        self.archive_format
+    }
+
+    /// Whether cached tool indexes should be bypassed and always re-downloaded.
+    pub fn force_fetch(&self) -> bool {
+        self.force_fetch
+    }
+
+    /// Whether a newly installed Node binary should be run once, to check that it
+    /// works on this machine.
+    pub fn smoke_test(&self) -> bool {
+        self.smoke_test
+    }
+
+    /// Whether progress and informational messages should be left out, so that only
+    /// errors are printed.
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Whether version ranges may be matched by prerelease versions, such as
+    /// `2.0.0-beta.1`, rather than only by releases.
+    pub fn prerelease(&self) -> bool {
+        self.prerelease
+    }
+}
+
+/// Parses the value of `NETWORK_TIMEOUT`, which must be a positive number of seconds.
+fn parse_network_timeout(value: &str) -> Fallible<Duration> {
+    match value.trim().parse::<u64>() {
+        Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => throw!(ErrorDetails::InvalidEnvironmentVar {
+            name: NETWORK_TIMEOUT.to_string(),
+            value: value.to_string(),
+            expected: "a positive number of seconds".to_string(),
+        }),
+    }
+}
+
+thread_local! {
+    // The environment is only read once per process, however many layers
+    // consult the settings.
+    static CONFIG: LazyCell<Rc<Config>> = LazyCell::new();
+}
+
+/// Loads the settings of this process, with `flags` given on the command line (each
+/// keyed by the environment variable it stands for) taking precedence over the
+/// environment. The flags are seen by every later `config()`, without being exported
+/// to the tools Notion launches. This must be called before the settings are first read.
+pub fn init_config(flags: &[(&str, String)]) -> Fallible<()> {
+    let config = Config::from_vars(|name| {
+        flags
+            .iter()
+            .find(|&&(flag, _)| flag == name)
+            .map(|&(_, ref value)| value.clone())
+            .or_else(|| env::var_os(name).map(|value| value.to_string_lossy().into_owned()))
+    })?;
+
+    CONFIG.with(|cell| {
+        if cell.fill(Rc::new(config)).is_err() {
+            panic!("settings were read before the command-line flags were applied");
+        }
+    });
+    Ok(())
+}
+
+/// Produces the settings of this process, loading them from the environment the
+/// first time.
+pub fn config() -> Fallible<Rc<Config>> {
+    CONFIG.with(|config| {
+        config
+            .try_borrow_with(|| Config::from_env().map(Rc::new))
+            .map(Rc::clone)
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config_from(vars: &[(&str, &str)]) -> Fallible<Config> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = config_from(&[]).expect("could not load config");
+        assert_eq!(
+            config.network_timeout(),
+            Duration::from_secs(DEFAULT_NETWORK_TIMEOUT_SECS)
+        );
+        assert_eq!(config.arch_override(), None);
+        assert_eq!(config.archive_format(), None);
+        assert!(!config.force_fetch());
+        assert!(config.smoke_test());
+        assert!(!config.quiet());
+        assert!(!config.prerelease());
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let config = config_from(&[
+            (ARCH_OVERRIDE, "arm64"),
+            (FORCE_FETCH, "1"),
+            (SKIP_SMOKE_TEST, "1"),
+            (QUIET, "1"),
+            (PRERELEASE, "1"),
+        ])
+        .expect("could not load config");
+        assert_eq!(config.arch_override(), Some("arm64"));
+        assert!(config.force_fetch());
+        assert!(!config.smoke_test());
+        assert!(config.quiet());
+        assert!(config.prerelease());
+    }
+
+    #[test]
+    fn test_init_config_flags() {
+        // each test runs on its own thread, so these settings aren't loaded yet
+        init_config(&[(FORCE_FETCH, "1".to_string())]).expect("could not load config");
+        assert!(config().expect("could not load config").force_fetch());
+        assert!(env::var_os(FORCE_FETCH).is_none());
+    }
+
+    #[test]
+    fn test_invalid_vars() {
+        assert!(config_from(&[(ARCH_OVERRIDE, "sparc")]).is_err());
+        assert!(config_from(&[(ARCHIVE_FORMAT, "rar")]).is_err());
+    }
+
+    #[test]
+    fn test_network_timeout() {
+        let config = config_from(&[(NETWORK_TIMEOUT, " 5 ")]).expect("could not load config");
+        assert_eq!(config.network_timeout(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_network_timeout_not_a_number() {
+        match config_from(&[(NETWORK_TIMEOUT, "soon")]) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::InvalidEnvironmentVar { name, value, .. }) => {
+                    assert_eq!(name, NETWORK_TIMEOUT);
+                    assert_eq!(value, "soon");
+                }
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("a non-numeric timeout should be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_network_timeout_zero() {
+        match config_from(&[(NETWORK_TIMEOUT, "0")]) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::InvalidEnvironmentVar { name, value, .. }) => {
+                    assert_eq!(name, NETWORK_TIMEOUT);
+                    assert_eq!(value, "0");
+                }
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("a zero timeout should be rejected"),
+        }
+    }
+}
@@ -3,22 +3,27 @@
 use std::fs::{read_to_string, rename, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::string::ToString;
 
 use archive::{self, Archive};
 use serde::Deserialize;
-use tempfile::tempdir_in;
 
-use super::{download_tool_error, Distro, Fetched};
-use crate::fs::ensure_containing_dir_exists;
+use super::{
+    copy_local_archive, download_tool_error, encode_url_component, local_file_path,
+    try_unpack_archive, unpack_archive, Distro,
+};
+use crate::config::config;
+use crate::error::ErrorDetails;
+use crate::fs::{ensure_containing_dir_exists, read_dir_eager, remove_dir_all_if_exists, trace_fs};
 use crate::hook::ToolHooks;
-use crate::inventory::NodeCollection;
+use crate::inventory::{NodeCollection, NodeEntry, NodeIndex};
 use crate::path;
-use crate::style::progress_bar;
 use crate::tool::ToolSpec;
+use crate::verify::check_version_output;
 use crate::version::VersionSpec;
 
-use notion_fail::{Fallible, ResultExt};
+use notion_fail::{throw, Fallible, ResultExt};
 use semver::Version;
 
 #[cfg(feature = "mock-network")]
@@ -39,33 +44,173 @@ cfg_if::cfg_if! {
 
 /// A provisioned Node distribution.
 pub struct NodeDistro {
-    archive: Box<dyn Archive>,
+    url: String,
     version: Version,
+    arch: &'static str,
+}
+
+/// A Node version resolved for fetching, along with the architecture of the build
+/// to fetch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeBuild {
+    pub version: Version,
+    pub arch: &'static str,
 }
 
 /// A full Node version including not just the version of Node itself
 /// but also the specific version of npm installed globally with that
-/// Node installation.
-#[derive(Eq, PartialEq, Clone, Debug)]
+/// Node installation. Versions are ordered by the Node version, then
+/// by the npm version.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Debug)]
 pub struct NodeVersion {
     /// The version of Node itself.
-    pub runtime: Version,
+    runtime: Version,
+    /// The npm version globally installed with the Node distro.
+    npm: Version,
+}
+
+impl NodeVersion {
+    /// Constructs a full Node version from the versions of Node and of the npm bundled
+    /// with it.
+    pub fn new(runtime: Version, npm: Version) -> Self {
+        NodeVersion { runtime, npm }
+    }
+
+    /// Splits the full version into the versions of Node and of its bundled npm.
+    pub fn into_parts(self) -> (Version, Version) {
+        (self.runtime, self.npm)
+    }
+
+    /// The version of Node itself.
+    pub fn runtime(&self) -> &Version {
+        &self.runtime
+    }
+
     /// The npm version globally installed with the Node distro.
-    pub npm: Version,
+    pub fn npm(&self) -> &Version {
+        &self.npm
+    }
+}
+
+/// Resolves a version specifier against an already-fetched index of Node versions,
+/// producing the matching Node version along with the npm version bundled with it.
+///
+/// NOTE: This assumes the index lists entries in sorted order from newest to oldest,
+///       as the public Node index does.
+pub fn resolve(spec: &VersionSpec, index: &NodeIndex) -> Fallible<NodeVersion> {
+    let entry = match spec {
+        VersionSpec::Latest => index.latest(),
+        VersionSpec::Lts => index.latest_lts(),
+        VersionSpec::Semver(requirement) => index.matching(requirement),
+        VersionSpec::Exact(version) => index.exact(version),
+        VersionSpec::None | VersionSpec::Tag(_) => None,
+    };
+
+    match entry {
+        Some(entry) => Ok(NodeVersion::new(entry.version.clone(), entry.npm.clone())),
+        None => throw!(ErrorDetails::NodeVersionNotFound {
+            matching: spec.to_string()
+        }),
+    }
+}
+
+/// Chooses the build of the given Node version to fetch for the current OS: the build
+/// for `path::arch()`, or the build for the fallback architecture (see
+/// `path::fallback_arch`) if the index lists only that one. Versions missing from the
+/// index are left for the download to reject.
+pub fn choose_build(version: Version, index: &NodeIndex) -> Fallible<NodeBuild> {
+    let arch = path::arch()?;
+    let chosen = match index.exact(&version) {
+        Some(entry) => match choose_build_arch(entry, path::OS, arch, path::fallback_arch()?) {
+            Some(chosen) => chosen,
+            None => throw!(ErrorDetails::UnsupportedArch {
+                arch: arch.to_string(),
+            }),
+        },
+        None => arch,
+    };
+    Ok(NodeBuild {
+        version,
+        arch: chosen,
+    })
+}
+
+/// Chooses the architecture of the build of a Node release to fetch for the given OS:
+/// `arch`, if the release has a build for it, or else `fallback`, if it has one for that.
+fn choose_build_arch(
+    entry: &NodeEntry,
+    os: &str,
+    arch: &'static str,
+    fallback: Option<&'static str>,
+) -> Option<&'static str> {
+    Some(arch)
+        .into_iter()
+        .chain(fallback)
+        .find(|arch| entry.has_build(&path::node_index_build_name_for(os, arch)))
 }
 
 /// Load the local npm version file to determine the default npm version for a given version of Node
+///
+/// If the npm version file is missing (e.g. for a Node archive provisioned from a custom
+/// distribution), the version is read from the unpacked Node image instead, and the npm
+/// version file is written for next time.
 pub fn load_default_npm_version(node: &Version) -> Fallible<Version> {
+    if let Some(npm) = read_default_npm_version(node)? {
+        return Ok(npm);
+    }
+
+    let npm = read_image_npm_version(node)?;
+    save_default_npm_version(node, &npm)?;
+    Ok(npm)
+}
+
+/// Reads the recorded version of npm bundled with the given version of Node, without
+/// recovering it from the image if the record is missing.
+pub(crate) fn read_default_npm_version(node: &Version) -> Fallible<Option<Version>> {
     let npm_version_file_path = path::node_npm_version_file(&node.to_string())?;
-    Ok(read_to_string(npm_version_file_path)
-        .unknown()?
-        .parse()
-        .unknown()?)
+    if !npm_version_file_path.is_file() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        read_to_string(npm_version_file_path)
+            .unknown()?
+            .parse()
+            .unknown()?,
+    ))
+}
+
+/// Checks whether the given version of Node has been unpacked into its image directory.
+/// An archive in the inventory may not have been, e.g. if it was fetched without
+/// unpacking or its image directory has since been removed.
+pub(crate) fn image_exists(node: &Version) -> Fallible<bool> {
+    Ok(path::node_image_version_dir(&node.to_string())?.is_dir())
+}
+
+/// Reads the version of npm bundled with an unpacked image of the given version of Node.
+fn read_image_npm_version(node: &Version) -> Fallible<Version> {
+    let node_dir = path::node_image_version_dir(&node.to_string())?;
+    let npm = read_dir_eager(&node_dir)
+        .ok()
+        .into_iter()
+        .flatten()
+        .filter(|(_, metadata)| metadata.is_dir())
+        .find_map(|(entry, _)| {
+            Manifest::version(&entry.path().join(path::node_npm_package_json_path())).ok()
+        });
+
+    match npm {
+        Some(npm) => Ok(npm),
+        None => throw!(ErrorDetails::PackageReadError {
+            error: format!("could not determine the npm version for Node v{}", node),
+        }),
+    }
 }
 
 /// Save the default npm version to the filesystem for a given version of Node
-fn save_default_npm_version(node: &Version, npm: &Version) -> Fallible<()> {
+pub(crate) fn save_default_npm_version(node: &Version, npm: &Version) -> Fallible<()> {
     let npm_version_file_path = path::node_npm_version_file(&node.to_string())?;
+    ensure_containing_dir_exists(&npm_version_file_path)?;
     let mut npm_version_file = File::create(npm_version_file_path).unknown()?;
     npm_version_file
         .write_all(npm.to_string().as_bytes())
@@ -106,53 +251,42 @@ impl Manifest {
 
 impl NodeDistro {
     /// Provision a Node distribution from the public Node distributor (`https://nodejs.org`).
-    fn public(version: Version) -> Fallible<Self> {
-        let distro_file_name = path::node_distro_file_name(&version.to_string());
+    fn public(build: NodeBuild) -> Fallible<Self> {
+        let version = build.version.to_string();
+        let distro_file_name = path::node_distro_file_name(&version, build.arch)?;
         let url = format!(
             "{}/v{}/{}",
             public_node_server_root(),
-            version,
-            &distro_file_name
+            encode_url_component(&version),
+            encode_url_component(&distro_file_name)
         );
-        NodeDistro::remote(version, &url)
+        Ok(NodeDistro::remote(build, url))
     }
 
     /// Provision a Node distribution from a remote distributor.
-    fn remote(version: Version, url: &str) -> Fallible<Self> {
-        let distro_file_name = path::node_distro_file_name(&version.to_string());
-        let distro_file = path::node_inventory_dir()?.join(&distro_file_name);
-
-        if distro_is_valid(&distro_file) {
-            return NodeDistro::local(version, File::open(distro_file).unknown()?);
+    fn remote(build: NodeBuild, url: String) -> Self {
+        NodeDistro {
+            url,
+            version: build.version,
+            arch: build.arch,
         }
-
-        ensure_containing_dir_exists(&distro_file)?;
-        Ok(NodeDistro {
-            archive: archive::fetch_native(url, &distro_file).with_context(download_tool_error(
-                ToolSpec::Node(VersionSpec::exact(&version)),
-                url,
-            ))?,
-            version: version,
-        })
     }
 
-    /// Provision a Node distribution from the filesystem.
-    fn local(version: Version, file: File) -> Fallible<Self> {
-        Ok(NodeDistro {
-            archive: archive::load_native(file).unknown()?,
-            version: version,
-        })
+    /// The path of this version's archive in the inventory.
+    fn distro_file(&self) -> Fallible<PathBuf> {
+        let distro_file_name = path::node_distro_file_name(&self.version.to_string(), self.arch)?;
+        Ok(path::node_inventory_dir()?.join(&distro_file_name))
     }
 }
 
 impl Distro for NodeDistro {
     type VersionDetails = NodeVersion;
-    type ResolvedVersion = Version;
+    type ResolvedVersion = NodeBuild;
 
     /// Provisions a new Distro based on the Version and possible Hooks
     fn new(
         _name: String,
-        version: Self::ResolvedVersion,
+        build: Self::ResolvedVersion,
         hooks: Option<&ToolHooks<Self>>,
     ) -> Fallible<Self> {
         match hooks {
@@ -160,11 +294,14 @@ impl Distro for NodeDistro {
                 distro: Some(ref hook),
                 ..
             }) => {
-                let url =
-                    hook.resolve(&version, &path::node_distro_file_name(&version.to_string()))?;
-                NodeDistro::remote(version, &url)
+                let url = hook.resolve_for_arch(
+                    &build.version,
+                    &path::node_distro_file_name(&build.version.to_string(), build.arch)?,
+                    build.arch,
+                )?;
+                Ok(NodeDistro::remote(build, url))
             }
-            _ => NodeDistro::public(version),
+            _ => NodeDistro::public(build),
         }
     }
 
@@ -173,59 +310,384 @@ impl Distro for NodeDistro {
         &self.version
     }
 
-    /// Fetches this version of Node. (It is left to the responsibility of the `NodeCollection`
-    /// to update its state after fetching succeeds.)
-    fn fetch(self, collection: &NodeCollection) -> Fallible<Fetched<NodeVersion>> {
-        if collection.contains(&self.version) {
-            let npm = load_default_npm_version(&self.version)?;
-
-            return Ok(Fetched::Already(NodeVersion {
-                runtime: self.version,
-                npm,
-            }));
+    /// Produces the full Node version, including the bundled npm version, if this
+    /// version of Node is already in the inventory and unpacked. An archive that was
+    /// never unpacked, or whose image directory has been removed, is unpacked by
+    /// `ensure_fetched`.
+    fn installed(&self, collection: &NodeCollection) -> Fallible<Option<NodeVersion>> {
+        if !collection.contains(&self.version) || !image_exists(&self.version)? {
+            return Ok(None);
+        }
+
+        let npm = load_default_npm_version(&self.version)?;
+        Ok(Some(NodeVersion::new(self.version.clone(), npm)))
+    }
+
+    /// Loads the Node archive from the inventory, or downloads it if there isn't a
+    /// valid copy there.
+    fn fetch(&self) -> Fallible<Box<dyn Archive>> {
+        let distro_file = self.distro_file()?;
+
+        if distro_is_valid(&distro_file) {
+            return archive::load_native(File::open(distro_file).unknown()?).unknown();
         }
 
-        let temp = tempdir_in(path::tmp_dir()?).unknown()?;
-        let bar = progress_bar(
-            self.archive.origin(),
+        ensure_containing_dir_exists(&distro_file)?;
+        let toolspec = ToolSpec::Node(VersionSpec::exact(&self.version));
+
+        if let Some(source) = local_file_path(&self.url) {
+            let file = copy_local_archive(&source, &distro_file, toolspec)?;
+            return archive::load_native(file).unknown();
+        }
+
+        let timeout = config()?.network_timeout();
+        archive::fetch_native(&self.url, &distro_file, timeout).with_context(download_tool_error(
+            toolspec,
+            &self.url,
+            &distro_file,
+        ))
+    }
+
+    /// Unpacks this version of Node into its image directory, recording the version of
+    /// npm bundled with it. (It is left to the responsibility of the `NodeCollection`
+    /// to update its state after fetching succeeds.)
+    fn unpack(self, archive: Box<dyn Archive>) -> Fallible<NodeVersion> {
+        let temp = unpack_archive(
+            archive,
             &format!("v{}", self.version),
-            self.archive
-                .uncompressed_size()
-                .unwrap_or(self.archive.compressed_size()),
-        );
+            &self.distro_file()?,
+            || self.fetch(),
+        )?;
 
-        self.archive
-            .unpack(temp.path(), &mut |_, read| {
-                bar.inc(read as u64);
-            })
-            .unknown()?;
+        let (image_dir, npm) = move_into_image(temp.path(), &self.version, self.arch)?;
 
-        let version_string = self.version.to_string();
+        if config()?.smoke_test() {
+            if let Err(error) = smoke_test(&self.version, &npm) {
+                // don't leave behind an image that looks installed but can't run
+                remove_dir_all_if_exists(&image_dir)?;
+                return Err(error);
+            }
+        }
 
-        let npm_package_json = temp
-            .path()
-            .join(path::node_archive_npm_package_json_path(&version_string));
+        Ok(NodeVersion::new(self.version, npm))
+    }
+}
 
-        let npm = Manifest::version(&npm_package_json)?;
+/// Runs `node --version` from a newly unpacked Node image, to check that the binary
+/// runs on this machine (e.g. that it wasn't built for another architecture) and
+/// reports the expected version.
+fn smoke_test(version: &Version, npm: &Version) -> Fallible<()> {
+    let node = path::node_image_bin_dir(&version.to_string(), &npm.to_string())?.join("node");
 
-        // Save the npm version number in the npm version file for this distro:
-        save_default_npm_version(&self.version, &npm)?;
+    let error = match Command::new(&node).arg("--version").output() {
+        Ok(ref output) if !output.status.success() => {
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        }
+        Ok(output) => {
+            let reported = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            match check_version_output(version, reported) {
+                None => return Ok(()),
+                Some(discrepancy) => discrepancy.to_string(),
+            }
+        }
+        Err(error) => error.to_string(),
+    };
+
+    throw!(ErrorDetails::NodeSmokeTestFailed {
+        version: version.to_string(),
+        error,
+    });
+}
+
+/// Unpacks the Node archive at the given path, a build for the given architecture,
+/// into the image directory for the given version, recording the version of npm
+/// bundled with it, and returns the path of the image directory.
+pub fn unpack(archive: &Path, version: &Version, arch: &str) -> Fallible<PathBuf> {
+    let archive = archive::load_native(File::open(archive).unknown()?).unknown()?;
+    let temp = try_unpack_archive(archive, &format!("v{}", version))?.unknown()?;
+
+    let (image_dir, _) = move_into_image(temp.path(), version, arch)?;
+    Ok(image_dir)
+}
+
+/// Moves the root directory of a Node archive for the given architecture unpacked into
+/// `unpack_dir` into the image directory for the given version, and returns that
+/// directory along with the version of npm bundled with it.
+fn move_into_image(
+    unpack_dir: &Path,
+    version: &Version,
+    arch: &str,
+) -> Fallible<(PathBuf, Version)> {
+    let version_string = version.to_string();
+    let root_dir = unpack_dir.join(path::node_archive_root_dir_name(&version_string, arch));
+    if !root_dir.is_dir() {
+        throw!(ErrorDetails::PackageUnpackError);
+    }
+
+    let npm = Manifest::version(&root_dir.join(path::node_npm_package_json_path()))?;
+
+    // Save the npm version number in the npm version file for this distro:
+    save_default_npm_version(version, &npm)?;
+
+    let dest = path::node_image_dir(&version_string, &npm.to_string())?;
 
-        let dest = path::node_image_dir(&version_string, &npm.to_string())?;
+    // clear out any partially unpacked image left from an earlier attempt
+    remove_dir_all_if_exists(&dest)?;
+    ensure_containing_dir_exists(&dest)?;
+
+    trace_fs("rename", &dest, rename(root_dir, &dest)).unknown()?;
+
+    Ok((dest, npm))
+}
 
-        ensure_containing_dir_exists(&dest)?;
+#[cfg(test)]
+pub mod tests {
+
+    use super::{
+        load_default_npm_version, move_into_image, resolve, unpack, NodeBuild, NodeDistro,
+        NodeVersion,
+    };
+    use crate::distro::Distro;
+    use crate::inventory::{serial, NodeIndex};
+    use crate::path;
+    use crate::path::tests::with_temp_home;
+    use crate::version::VersionSpec;
+    use semver::{Version, VersionReq};
+    use std::fs::{create_dir, create_dir_all, read_to_string, write};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    const INDEX_JSON: &'static str = r#"[
+  {"version":"v11.10.1","date":"2019-03-01","files":["linux-x64","osx-x64-tar","win-x64-zip"],"npm":"6.7.0","lts":false},
+  {"version":"v10.15.3","date":"2019-03-05","files":["linux-x64","osx-x64-tar","win-x64-zip"],"npm":"6.4.1","lts":"Dubnium"},
+  {"version":"v10.15.2","date":"2019-02-28","files":["linux-x64","osx-x64-tar","win-x64-zip"],"npm":"6.4.1","lts":"Dubnium"},
+  {"version":"v8.15.1","date":"2019-02-28","files":["linux-x64","osx-x64-tar","win-x64-zip"],"npm":"6.4.1","lts":"Carbon"}
+]"#;
+
+    fn index() -> NodeIndex {
+        serde_json::de::from_str::<serial::NodeIndex>(INDEX_JSON)
+            .expect("could not parse index JSON")
+            .into_index()
+            .expect("could not convert index")
+    }
 
-        rename(
-            temp.path()
-                .join(path::node_archive_root_dir_name(&version_string)),
-            dest,
+    fn node_version(runtime: &str, npm: &str) -> NodeVersion {
+        NodeVersion::new(
+            Version::parse(runtime).unwrap(),
+            Version::parse(npm).unwrap(),
         )
-        .unknown()?;
+    }
+
+    #[test]
+    fn test_resolve_latest() {
+        assert_eq!(
+            resolve(&VersionSpec::Latest, &index()).expect("could not resolve latest"),
+            node_version("11.10.1", "6.7.0")
+        );
+    }
+
+    #[test]
+    fn test_resolve_lts() {
+        assert_eq!(
+            resolve(&VersionSpec::Lts, &index()).expect("could not resolve lts"),
+            node_version("10.15.3", "6.4.1")
+        );
+    }
 
-        bar.finish_and_clear();
-        Ok(Fetched::Now(NodeVersion {
-            runtime: self.version,
-            npm,
-        }))
+    #[test]
+    fn test_resolve_range() {
+        assert_eq!(
+            resolve(&VersionSpec::parse("^10.0.0").unwrap(), &index())
+                .expect("could not resolve range"),
+            node_version("10.15.3", "6.4.1")
+        );
+        assert_eq!(
+            resolve(&VersionSpec::parse("<10").unwrap(), &index())
+                .expect("could not resolve range"),
+            node_version("8.15.1", "6.4.1")
+        );
+    }
+
+    #[test]
+    fn test_resolve_exact() {
+        assert_eq!(
+            resolve(&VersionSpec::parse("10.15.2").unwrap(), &index())
+                .expect("could not resolve exact version"),
+            node_version("10.15.2", "6.4.1")
+        );
+    }
+
+    #[test]
+    fn test_resolve_bounded_range() {
+        assert_eq!(
+            resolve(&VersionSpec::parse(">=8.0.0 <11.0.0").unwrap(), &index())
+                .expect("could not resolve bounded range"),
+            node_version("10.15.3", "6.4.1")
+        );
+    }
+
+    #[test]
+    fn test_resolve_no_match() {
+        assert!(resolve(&VersionSpec::parse("^12").unwrap(), &index()).is_err());
+        assert!(resolve(&VersionSpec::parse("10.15.1").unwrap(), &index()).is_err());
+    }
+
+    #[test]
+    fn test_parse_index_fixture() {
+        let mut fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        fixture.push("fixtures");
+        fixture.push("node_index");
+        fixture.push("index.json");
+        let json = read_to_string(fixture).expect("could not read index fixture");
+        let index = serde_json::de::from_str::<serial::NodeIndex>(&json)
+            .expect("could not parse index fixture")
+            .into_index()
+            .expect("could not convert index");
+
+        // releases without a bundled npm are skipped
+        assert_eq!(index.entries.len(), 3);
+
+        let latest = index.latest().expect("no latest entry");
+        assert_eq!(latest.version, Version::parse("11.11.0").unwrap());
+        assert_eq!(latest.npm, Version::parse("6.7.0").unwrap());
+        assert_eq!(latest.lts, None);
+        assert!(latest.has_build("linux-arm64"));
+        assert!(!latest.has_build("linux-x86"));
+
+        let lts = index.latest_lts().expect("no LTS entry");
+        assert_eq!(lts.version, Version::parse("10.15.3").unwrap());
+        assert_eq!(lts.lts, Some("Dubnium".to_string()));
+
+        let carbon = index
+            .matching(&VersionReq::parse("^8").unwrap())
+            .expect("no entry matching ^8");
+        assert_eq!(carbon.version, Version::parse("8.15.1").unwrap());
+        assert!(carbon.has_build("linux-x86"));
+    }
+
+    #[test]
+    fn test_choose_build_arch() {
+        let mut fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        fixture.push("fixtures");
+        fixture.push("node_index");
+        fixture.push("index.json");
+        let json = read_to_string(fixture).expect("could not read index fixture");
+        let index = serde_json::de::from_str::<serial::NodeIndex>(&json)
+            .expect("could not parse index fixture")
+            .into_index()
+            .expect("could not convert index");
+        let latest = index.latest().expect("no latest entry");
+
+        assert_eq!(
+            choose_build_arch(latest, "darwin", "x64", None),
+            Some("x64")
+        );
+        // there are no arm64 builds for macOS, but x64 builds run under Rosetta
+        assert_eq!(choose_build_arch(latest, "darwin", "arm64", None), None);
+        assert_eq!(
+            choose_build_arch(latest, "darwin", "arm64", Some("x64")),
+            Some("x64")
+        );
+        assert_eq!(
+            choose_build_arch(latest, "linux", "arm64", Some("x64")),
+            Some("arm64")
+        );
+        assert_eq!(choose_build_arch(latest, "linux", "x86", Some("x86")), None);
+    }
+
+    #[test]
+    fn test_distro_for_build_arch() {
+        with_temp_home(|_| {
+            // e.g. an x64 build chosen for an arm64 Mac running Notion under Rosetta
+            let build = NodeBuild {
+                version: Version::parse("11.11.0").unwrap(),
+                arch: "x64",
+            };
+            let distro =
+                NodeDistro::new("node".to_string(), build, None).expect("could not provision");
+
+            let file_name = path::node_distro_file_name("11.11.0", "x64").unwrap();
+            assert!(distro.url.ends_with(&file_name));
+            assert_eq!(
+                distro.distro_file().unwrap(),
+                path::node_inventory_dir().unwrap().join(file_name)
+            );
+        });
+    }
+
+    // the fixture archives are only built for x64, and for x86 on Windows
+    #[test]
+    #[cfg(any(target_arch = "x86_64", all(windows, target_arch = "x86")))]
+    fn test_unpack() {
+        with_temp_home(|_| {
+            create_dir(path::tmp_dir().unwrap()).unwrap();
+
+            let mut archive = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            archive.push("fixtures");
+            archive.push("node_archives");
+            let arch = path::arch().unwrap();
+            archive.push(path::node_distro_file_name("8.9.10", arch).unwrap());
+
+            let version = Version::parse("8.9.10").unwrap();
+            let image_dir = unpack(&archive, &version, arch).expect("could not unpack archive");
+
+            assert_eq!(image_dir, path::node_image_dir("8.9.10", "5.6.7").unwrap());
+            assert!(image_dir.join(path::node_npm_package_json_path()).is_file());
+            assert_eq!(
+                read_to_string(path::node_npm_version_file("8.9.10").unwrap()).unwrap(),
+                "5.6.7"
+            );
+        });
+    }
+
+    #[test]
+    fn test_load_default_npm_version_from_image() {
+        with_temp_home(|_| {
+            let node = Version::parse("10.15.3").unwrap();
+            assert!(load_default_npm_version(&node).is_err());
+
+            let package_json = path::node_image_dir("10.15.3", "6.4.1")
+                .unwrap()
+                .join(path::node_npm_package_json_path());
+            create_dir_all(package_json.parent().unwrap()).unwrap();
+            write(&package_json, r#"{"name":"npm","version":"6.4.1"}"#).unwrap();
+
+            let npm = Version::parse("6.4.1").unwrap();
+            assert_eq!(load_default_npm_version(&node).unwrap(), npm);
+            // the version is saved, so the image isn't read again
+            let npm_version_file = path::node_npm_version_file("10.15.3").unwrap();
+            assert_eq!(read_to_string(npm_version_file).unwrap(), "6.4.1");
+        });
+    }
+
+    #[test]
+    fn test_move_into_image_without_root_dir() {
+        let dir = tempdir().expect("could not create temp dir");
+        create_dir(dir.path().join("package")).unwrap();
+
+        let version = Version::parse("10.15.3").unwrap();
+        assert!(move_into_image(dir.path(), &version, path::ARCH).is_err());
+    }
+
+    #[test]
+    fn test_node_version_ordering() {
+        assert!(node_version("10.15.3", "6.4.1") < node_version("11.10.1", "6.4.1"));
+        assert!(node_version("10.15.3", "6.4.1") < node_version("10.15.3", "6.7.0"));
+        assert!(node_version("11.10.1", "6.4.1") > node_version("10.15.3", "6.7.0"));
+        assert_eq!(
+            node_version("10.15.3", "6.4.1"),
+            node_version("10.15.3", "6.4.1")
+        );
+    }
+
+    #[test]
+    fn test_node_version_into_parts() {
+        assert_eq!(
+            node_version("10.15.3", "6.4.1").into_parts(),
+            (
+                Version::parse("10.15.3").unwrap(),
+                Version::parse("6.4.1").unwrap()
+            )
+        );
     }
 }
@@ -1,8 +1,10 @@
 //! Provides types for installing packages to the user toolchain.
 
 use std::collections::HashMap;
+use std::env;
 use std::ffi::OsStr;
-use std::fs::{rename, File};
+use std::fmt;
+use std::fs::{remove_file, rename, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -12,24 +14,32 @@ use hex;
 use semver::Version;
 use sha1::{Digest, Sha1};
 
-use crate::distro::{download_tool_error, Distro, Fetched};
+use crate::config::config;
+use crate::distro::{
+    copy_local_archive, download_tool_error, local_file_path, unpack_archive, Distro,
+};
 use crate::error::ErrorDetails;
-use crate::fs::{ensure_containing_dir_exists, read_dir_eager, read_file_opt};
+use crate::fs::{
+    ensure_containing_dir_exists, read_dir_eager, read_file_opt, remove_dir_all_if_exists, trace_fs,
+};
 use crate::hook::ToolHooks;
-use crate::inventory::Collection;
+use crate::inventory::{package_registry_root, Collection};
 use crate::manifest::Manifest;
+use crate::npmrc;
 use crate::path;
 use crate::platform::{Image, PlatformSpec};
 use crate::session::Session;
 use crate::shim;
-use crate::style::progress_bar;
+use crate::style::NOTION_VERBOSE;
 use crate::tool::ToolSpec;
 use crate::version::VersionSpec;
 use archive::{Archive, Tarball};
-use tempfile::tempdir_in;
 
 use notion_fail::{throw, Fallible, ResultExt};
 
+/// How many lines from the end of a failed install command's error output to show.
+const INSTALL_STDERR_TAIL_LINES: usize = 20;
+
 fn install_error(error: &io::Error) -> ErrorDetails {
     if let Some(inner_err) = error.get_ref() {
         ErrorDetails::PackageInstallIoError {
@@ -42,6 +52,50 @@ fn install_error(error: &io::Error) -> ErrorDetails {
     }
 }
 
+/// The inventory file for the tarball of a package version. A standalone npm is kept
+/// in its own inventory directory, apart from the packages installed as user tools.
+fn distro_file(name: &str, version: &Version) -> Fallible<PathBuf> {
+    if name == "npm" {
+        path::npm_archive_file(&version.to_string())
+    } else {
+        path::package_distro_file(name, &version.to_string())
+    }
+}
+
+/// The inventory file holding the expected shasum of a package version's tarball.
+fn shasum_file(name: &str, version: &Version) -> Fallible<PathBuf> {
+    if name == "npm" {
+        path::npm_archive_shasum(&version.to_string())
+    } else {
+        path::package_distro_shasum(name, &version.to_string())
+    }
+}
+
+/// The image directory of a package version. A standalone npm is unpacked into its
+/// own image, which platforms put on the `PATH`, rather than installed as a user tool.
+fn image_dir(name: &str, version: &Version) -> Fallible<PathBuf> {
+    if name == "npm" {
+        path::npm_image_dir(&version.to_string())
+    } else {
+        path::package_image_dir(name, &version.to_string())
+    }
+}
+
+/// Parses a package's "engines" requirement for Node, if any, into a VersionSpec for
+/// matching against available Node versions.
+fn engines_spec(engines: Option<String>) -> Fallible<VersionSpec> {
+    // if nothing specified, can use any version of Node
+    let engines = engines.unwrap_or("*".to_string());
+    let spec = VersionSpec::parse_requirements(engines)?;
+    Ok(VersionSpec::Semver(spec))
+}
+
+/// The file name of a package tarball, e.g. `cowsay-1.4.0.tgz`, from its registry URL,
+/// which distro hooks use to locate the tarball elsewhere.
+fn tarball_file_name(tarball_url: &str) -> &str {
+    tarball_url.rsplit('/').next().unwrap_or(tarball_url)
+}
+
 /// A provisioned Package distribution.
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct PackageDistro {
@@ -52,6 +106,10 @@ pub struct PackageDistro {
     pub image_dir: PathBuf,
     pub shasum_file: PathBuf,
     pub distro_file: PathBuf,
+    /// The executables listed in the registry metadata, in sorted order
+    pub bins: Vec<String>,
+    /// The Node requirement listed in the registry metadata
+    pub engines: Option<String>,
 }
 
 /// A package version.
@@ -70,6 +128,29 @@ enum Installer {
     Yarn,
 }
 
+/// What installing a package would do, as reported by a dry run.
+pub struct InstallPlan {
+    /// The package name
+    pub name: String,
+    /// The resolved package version
+    pub version: Version,
+    /// The platform the package would be installed with
+    pub platform: PlatformSpec,
+    /// The executables that would be shimmed, in sorted order
+    pub bins: Vec<String>,
+    /// The install command that would be run
+    pub command: String,
+}
+
+impl fmt::Display for InstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "package: {} v{}", self.name, self.version)?;
+        writeln!(f, "{}", self.platform.display_versions())?;
+        writeln!(f, "executables: {}", self.bins.join(", "))?;
+        write!(f, "command: {}", self.command)
+    }
+}
+
 /// Configuration information about an installed package.
 ///
 /// This information will be stored in ~/.notion/tools/user/packages/<package>.json.
@@ -141,78 +222,77 @@ impl Distro for PackageDistro {
     fn new(
         name: String,
         entry: Self::ResolvedVersion,
-        _hooks: Option<&ToolHooks<Self>>,
+        hooks: Option<&ToolHooks<Self>>,
     ) -> Fallible<Self> {
         let version = entry.version;
+        let tarball_url = match hooks {
+            Some(&ToolHooks {
+                distro: Some(ref hook),
+                ..
+            }) => hook.resolve(&version, tarball_file_name(&entry.tarball))?,
+            _ => entry.tarball,
+        };
         Ok(PackageDistro {
             name: name.to_string(),
             shasum: entry.shasum,
             version: version.clone(),
-            tarball_url: entry.tarball,
-            image_dir: path::package_image_dir(&name, &version.to_string())?,
-            distro_file: path::package_distro_file(&name, &version.to_string())?,
-            shasum_file: path::package_distro_shasum(&name, &version.to_string())?,
+            tarball_url,
+            image_dir: image_dir(&name, &version)?,
+            distro_file: distro_file(&name, &version)?,
+            shasum_file: shasum_file(&name, &version)?,
+            bins: entry.bins,
+            engines: entry.engines,
         })
     }
 
-    fn fetch(self, _collection: &Collection<Self>) -> Fallible<Fetched<PackageVersion>> {
-        let archive = self.load_or_fetch_archive()?;
+    /// Packages are always unpacked afresh, so they are never already installed.
+    fn installed(&self, _collection: &Collection<Self>) -> Fallible<Option<PackageVersion>> {
+        Ok(None)
+    }
 
-        let bar = progress_bar(
-            archive.origin(),
-            &format!("{}-v{}", self.name, self.version),
-            archive
-                .uncompressed_size()
-                .unwrap_or(archive.compressed_size()),
-        );
+    /// Loads the package tarball from the inventory, or downloads it if there isn't a
+    /// copy there matching the expected shasum.
+    fn fetch(&self) -> Fallible<Box<dyn Archive>> {
+        self.load_or_fetch_archive()
+    }
 
-        let temp = tempdir_in(path::tmp_dir()?).unknown()?;
-        archive
-            .unpack(temp.path(), &mut |_, read| {
-                bar.inc(read as u64);
-            })
-            .unknown()?;
-        bar.finish();
+    fn unpack(self, archive: Box<dyn Archive>) -> Fallible<PackageVersion> {
+        let temp = unpack_archive(
+            archive,
+            &format!("{}-v{}", self.name, self.version),
+            &self.distro_file,
+            || self.fetch(),
+        )?;
 
+        // clear out any previously unpacked image for this version
+        remove_dir_all_if_exists(&self.image_dir)?;
         ensure_containing_dir_exists(&self.image_dir)?;
 
         let unpack_dir = find_unpack_dir(temp.path())?;
-        rename(unpack_dir, &self.image_dir).unknown()?;
-
-        // save the shasum in a file
-        let mut f = File::create(&self.shasum_file).unknown()?;
-        f.write_all(self.shasum.as_bytes()).unknown()?;
-        f.sync_all().unknown()?;
-
-        let pkg_info = Manifest::for_dir(&self.image_dir).with_context(|error| {
-            ErrorDetails::DepPackageReadError {
-                error: error.to_string(),
-            }
-        })?;
-        let bin_map = pkg_info.bin;
-        if bin_map.is_empty() {
-            throw!(ErrorDetails::NoPackageExecutables);
-        }
-
-        for (bin_name, _bin_path) in bin_map.iter() {
-            // check for conflicts with installed bins
-            // some packages may install bins with the same name
-            let bin_config_file = path::user_tool_bin_config(&bin_name)?;
-            if bin_config_file.exists() {
-                let bin_config = BinConfig::from_file(bin_config_file)?;
-                throw!(ErrorDetails::BinaryAlreadyInstalled {
-                    bin_name: bin_name.to_string(),
-                    package: bin_config.package,
-                    version: bin_config.version.to_string()
-                });
-            }
-        }
+        trace_fs(
+            "rename",
+            &self.image_dir,
+            rename(unpack_dir, &self.image_dir),
+        )
+        .unknown()?;
+
+        self.save_shasum()?;
+
+        let bins = executables(&self.image_dir)?;
+        check_bin_conflicts(&bins)?;
+
+        let bin_map = bins
+            .into_iter()
+            .map(|BinEntry { name, path }| (name, path))
+            .collect();
+        PackageVersion::new(self.name.clone(), self.version.clone(), bin_map)
+    }
 
-        Ok(Fetched::Now(PackageVersion::new(
-            self.name.clone(),
-            self.version.clone(),
-            bin_map,
-        )?))
+    /// Downloads the package tarball into the inventory without unpacking it. The
+    /// shasum is saved too, so that the tarball can be verified and used later.
+    fn cache(&self) -> Fallible<()> {
+        self.fetch()?.cache().unknown()?;
+        self.save_shasum()
     }
 
     fn version(&self) -> &Version {
@@ -221,17 +301,64 @@ impl Distro for PackageDistro {
 }
 
 impl PackageDistro {
-    /// Loads the package tarball from disk, or fetches from URL.
+    /// The Node versions this package can be installed with, from the registry metadata.
+    pub fn engines_spec(&self) -> Fallible<VersionSpec> {
+        engines_spec(self.engines.clone())
+    }
+
+    /// Works out how this package would be installed on the given platform from the
+    /// registry metadata, without fetching the package or running anything.
+    pub fn install_plan(&self, platform: &PlatformSpec) -> InstallPlan {
+        InstallPlan {
+            name: self.name.to_string(),
+            version: self.version.clone(),
+            platform: platform.clone(),
+            bins: self.bins.clone(),
+            command: Installer::for_platform(platform).to_string(),
+        }
+    }
+
+    /// Saves the expected shasum of the package tarball in a file, which is checked
+    /// against the tarball before it is used again.
+    fn save_shasum(&self) -> Fallible<()> {
+        ensure_containing_dir_exists(&self.shasum_file)?;
+        let mut f = File::create(&self.shasum_file).unknown()?;
+        f.write_all(self.shasum.as_bytes()).unknown()?;
+        f.sync_all().unknown()
+    }
+
+    /// Loads the package tarball from the inventory, or else copies it from the
+    /// directory of a `local` hook or fetches it from its URL.
     fn load_or_fetch_archive(&self) -> Fallible<Box<Archive>> {
         // try to use existing downloaded package
         if self.downloaded_pkg_is_ok() {
             Tarball::load(File::open(&self.distro_file).unknown()?).unknown()
         } else {
-            // otherwise have to download
             ensure_containing_dir_exists(&self.distro_file)?;
-            Tarball::fetch(&self.tarball_url, &self.distro_file).with_context(download_tool_error(
-                ToolSpec::Package(self.name.to_string(), VersionSpec::exact(&self.version)),
+            let toolspec =
+                ToolSpec::Package(self.name.to_string(), VersionSpec::exact(&self.version));
+
+            if let Some(source) = local_file_path(&self.tarball_url) {
+                let file = copy_local_archive(&source, &self.distro_file, toolspec)?;
+                return Tarball::load(file).unknown();
+            }
+
+            // otherwise have to download
+            let registry = package_registry_root(&self.name);
+            let timeout = config()?.network_timeout();
+            let fetched = match npmrc::authorization(&self.tarball_url, &registry)? {
+                Some(authorization) => Tarball::fetch_authorized(
+                    &self.tarball_url,
+                    &self.distro_file,
+                    &authorization,
+                    timeout,
+                ),
+                None => Tarball::fetch(&self.tarball_url, &self.distro_file, timeout),
+            };
+            fetched.with_context(download_tool_error(
+                toolspec,
                 self.tarball_url.to_string(),
+                &self.distro_file,
             ))
         }
     }
@@ -259,6 +386,70 @@ impl PackageDistro {
     }
 }
 
+/// An executable provided by a package.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct BinEntry {
+    /// The name of the executable
+    pub name: String,
+    /// The path to the executable, relative to the package directory
+    pub path: String,
+}
+
+/// Reads the executables provided by the unpacked package in the given directory
+/// from the `"bin"` field of its `package.json`, which can be either a map from
+/// names to paths or a single path (in which case the executable is named after
+/// the package). The entries are sorted by name.
+pub fn executables(package_dir: &Path) -> Fallible<Vec<BinEntry>> {
+    let pkg_info =
+        Manifest::for_dir(package_dir).with_context(|error| ErrorDetails::DepPackageReadError {
+            error: error.to_string(),
+        })?;
+
+    let mut bins: Vec<BinEntry> = pkg_info
+        .bin
+        .into_iter()
+        .map(|(name, path)| BinEntry { name, path })
+        .collect();
+    if bins.is_empty() {
+        throw!(ErrorDetails::NoPackageExecutables);
+    }
+
+    bins.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(bins)
+}
+
+/// Checks that none of the given executables are already installed by another
+/// package, reporting every conflict at once so they can all be fixed together.
+fn check_bin_conflicts(bins: &[BinEntry]) -> Fallible<()> {
+    let mut conflicts = Vec::new();
+
+    for BinEntry { name: bin_name, .. } in bins {
+        // some packages may install bins with the same name
+        let bin_config_file = path::user_tool_bin_config(&bin_name)?;
+        if bin_config_file.exists() {
+            let bin_config = BinConfig::from_file(bin_config_file)?;
+            conflicts.push((
+                bin_name.to_string(),
+                bin_config.package,
+                bin_config.version.to_string(),
+            ));
+        }
+    }
+
+    if conflicts.len() == 1 {
+        let (bin_name, package, version) = conflicts.remove(0);
+        throw!(ErrorDetails::BinaryAlreadyInstalled {
+            bin_name,
+            package,
+            version
+        });
+    } else if !conflicts.is_empty() {
+        throw!(ErrorDetails::BinaryConflicts { conflicts });
+    }
+
+    Ok(())
+}
+
 // Figure out the unpacked package directory name dynamically, because
 // packages typically extract to a "package" directory, but not always
 fn find_unpack_dir(in_dir: &Path) -> Fallible<PathBuf> {
@@ -276,7 +467,7 @@ fn find_unpack_dir(in_dir: &Path) -> Fallible<PathBuf> {
 
 impl PackageVersion {
     pub fn new(name: String, version: Version, bins: HashMap<String, String>) -> Fallible<Self> {
-        let image_dir = path::package_image_dir(&name, &version.to_string())?;
+        let image_dir = image_dir(&name, &version)?;
         Ok(PackageVersion {
             name,
             version,
@@ -288,34 +479,26 @@ impl PackageVersion {
     // parse the "engines" string to a VersionSpec, for matching against available Node versions
     pub fn engines_spec(&self) -> Fallible<VersionSpec> {
         let manifest = Manifest::for_dir(&self.image_dir)?;
-        // if nothing specified, can use any version of Node
-        let engines = manifest.engines().unwrap_or("*".to_string());
-        let spec = VersionSpec::parse_requirements(engines)?;
-        Ok(VersionSpec::Semver(spec))
+        engines_spec(manifest.engines())
     }
 
     pub fn install(&self, platform: &PlatformSpec, session: &mut Session) -> Fallible<()> {
-        let image = platform.checkout(session)?;
-        // use yarn if it is installed, otherwise default to npm
-        let mut install_cmd = if image.yarn.is_some() {
-            install_command_for(
-                Installer::Yarn,
-                &self.image_dir.clone().into_os_string(),
-                &image.path()?,
-            )
-        } else {
-            install_command_for(
-                Installer::Npm,
-                &self.image_dir.clone().into_os_string(),
-                &image.path()?,
-            )
-        };
+        let installer = Installer::for_platform(platform);
+        let command_line = installer.to_string();
+        let mut install_cmd = self.install_command(installer, platform, session)?;
+
+        // the install log is streamed in verbose mode, and otherwise only shown if the
+        // install fails
+        if env::var_os(NOTION_VERBOSE).is_none() {
+            install_cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
 
         let output = install_cmd.output().with_context(install_error)?;
         if !output.status.success() {
             throw!(ErrorDetails::PackageInstallFailed {
-                cmd: format!("{:?}", install_cmd),
-                status: output.status
+                cmd: command_line,
+                status: output.status,
+                stderr: tail_lines(&output.stderr, INSTALL_STDERR_TAIL_LINES),
             });
         }
 
@@ -324,6 +507,20 @@ impl PackageVersion {
         Ok(())
     }
 
+    fn install_command(
+        &self,
+        installer: Installer,
+        platform: &PlatformSpec,
+        session: &mut Session,
+    ) -> Fallible<Command> {
+        let image = platform.checkout(session)?;
+        Ok(install_command_for(
+            installer,
+            &self.image_dir.clone().into_os_string(),
+            &image.path()?,
+        ))
+    }
+
     fn package_config(&self, platform_spec: &PlatformSpec) -> PackageConfig {
         PackageConfig {
             name: self.name.to_string(),
@@ -366,20 +563,40 @@ impl PackageVersion {
 }
 
 impl Installer {
-    pub fn cmd(&self) -> Command {
+    /// Uses yarn if the platform has it, otherwise defaults to npm.
+    fn for_platform(platform: &PlatformSpec) -> Self {
+        if platform.yarn.is_some() {
+            Installer::Yarn
+        } else {
+            Installer::Npm
+        }
+    }
+
+    fn program(&self) -> &'static str {
         match self {
-            Installer::Npm => {
-                let mut command = Command::new("npm");
-                command.args(&["install", "--only=production"]);
-                command
-            }
-            Installer::Yarn => {
-                let mut command = Command::new("yarn");
-                command.args(&["install", "--production"]);
-                command
-            }
+            Installer::Npm => "npm",
+            Installer::Yarn => "yarn",
         }
     }
+
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            Installer::Npm => &["install", "--only=production"],
+            Installer::Yarn => &["install", "--production"],
+        }
+    }
+
+    pub fn cmd(&self) -> Command {
+        let mut command = Command::new(self.program());
+        command.args(self.args());
+        command
+    }
+}
+
+impl fmt::Display for Installer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.program(), self.args().join(" "))
+    }
 }
 
 /// Information about a user tool.
@@ -413,10 +630,41 @@ impl UserTool {
     }
 }
 
+/// Uninstalls a package from the user toolchain, removing its configuration along with
+/// the configuration and shims of the binaries it installed.
+pub fn uninstall(name: &str) -> Fallible<()> {
+    let package_config_file = path::user_package_config_file(name)?;
+    if !package_config_file.exists() {
+        throw!(ErrorDetails::PackageNotInstalled {
+            name: name.to_string(),
+        });
+    }
+
+    let package_config = PackageConfig::from_file(package_config_file.clone())?;
+    for bin_name in package_config.bins {
+        let bin_config_file = path::user_tool_bin_config(&bin_name)?;
+        if !bin_config_file.exists() {
+            continue;
+        }
+
+        // leave binaries that have since been installed by another package
+        let bin_config = BinConfig::from_file(bin_config_file.clone())?;
+        if bin_config.package == name {
+            remove_file(bin_config_file).unknown()?;
+            shim::remove(&bin_name)?;
+        }
+    }
+
+    remove_file(package_config_file).unknown()?;
+    Ok(())
+}
+
 /// Build a package install command using the specified directory and path
 ///
 /// Note: connects stdout and stderr to the current stdout and stderr for this process
-/// (so the user can see the install progress in real time)
+/// (so the user can see the install progress in real time). Outside of verbose mode,
+/// `PackageVersion::install` pipes them instead, and only shows the output if the
+/// install fails.
 fn install_command_for(installer: Installer, in_dir: &OsStr, path_var: &OsStr) -> Command {
     let mut command = installer.cmd();
     command
@@ -427,9 +675,19 @@ fn install_command_for(installer: Installer, in_dir: &OsStr, path_var: &OsStr) -
     command
 }
 
+/// Returns the last `count` lines of a command's output, trimmed.
+fn tail_lines(output: &[u8], count: usize) -> String {
+    let output = String::from_utf8_lossy(output);
+    let lines: Vec<&str> = output.trim_end().lines().collect();
+    let start = lines.len().saturating_sub(count);
+    lines[start..].join("\n")
+}
+
 /// Index of versions of a specific package.
 pub struct PackageIndex {
     pub latest: Version,
+    /// The versions of the package's other dist-tags, such as `next` or `beta`.
+    pub tags: HashMap<String, Version>,
     pub entries: Vec<PackageEntry>,
 }
 
@@ -438,4 +696,252 @@ pub struct PackageEntry {
     pub version: Version,
     pub tarball: String,
     pub shasum: String,
+    /// The executables this version provides, in sorted order
+    pub bins: Vec<String>,
+    /// The Node requirement from this version's "engines" field
+    pub engines: Option<String>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::path::tests::with_temp_home;
+    use tempfile::tempdir;
+
+    fn fixture_path(fixture_dir: &str) -> PathBuf {
+        let mut cargo_manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        cargo_manifest_dir.push("fixtures");
+        cargo_manifest_dir.push(fixture_dir);
+        cargo_manifest_dir
+    }
+
+    fn bin_entry(name: &str, path: &str) -> BinEntry {
+        BinEntry {
+            name: name.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    fn install_platform() -> PlatformSpec {
+        PlatformSpec {
+            node_runtime: Version::parse("10.15.3").unwrap(),
+            npm: Some(Version::parse("6.4.1").unwrap()),
+            yarn: None,
+        }
+    }
+
+    fn bin_config(name: &str, package: &str) -> BinConfig {
+        BinConfig {
+            name: name.to_string(),
+            package: package.to_string(),
+            version: Version::parse("1.4.0").unwrap(),
+            path: format!("./bin/{}", name),
+            platform: install_platform(),
+        }
+    }
+
+    #[test]
+    fn test_tail_lines() {
+        let stderr = b"npm ERR! one\nnpm ERR! two\nnpm ERR! three\n";
+        assert_eq!(tail_lines(stderr, 2), "npm ERR! two\nnpm ERR! three");
+        assert_eq!(
+            tail_lines(stderr, 5),
+            "npm ERR! one\nnpm ERR! two\nnpm ERR! three"
+        );
+        assert_eq!(tail_lines(b"", 2), "");
+    }
+
+    #[test]
+    fn test_tarball_file_name() {
+        assert_eq!(
+            tarball_file_name("https://registry.npmjs.org/cowsay/-/cowsay-1.4.0.tgz"),
+            "cowsay-1.4.0.tgz"
+        );
+        assert_eq!(
+            tarball_file_name("https://registry.npmjs.org/@types/node/-/node-11.9.4.tgz"),
+            "node-11.9.4.tgz"
+        );
+    }
+
+    #[test]
+    fn test_npm_inventory_files() {
+        with_temp_home(|_| {
+            let version = Version::parse("6.7.0").unwrap();
+            assert_eq!(
+                distro_file("npm", &version).unwrap(),
+                path::npm_archive_file("6.7.0").unwrap()
+            );
+            assert_eq!(
+                shasum_file("npm", &version).unwrap(),
+                path::npm_archive_shasum("6.7.0").unwrap()
+            );
+            assert_eq!(
+                distro_file("cowsay", &version).unwrap(),
+                path::package_distro_file("cowsay", "6.7.0").unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn test_saved_shasum_validates_cached_tarball() {
+        let dir = tempdir().unwrap();
+        let tarball = b"not really a tarball";
+        let mut distro = PackageDistro {
+            name: "cowsay".to_string(),
+            shasum: hex::encode(&Sha1::digest(tarball)),
+            tarball_url: "https://registry.npmjs.org/cowsay/-/cowsay-1.4.0.tgz".to_string(),
+            version: Version::parse("1.4.0").unwrap(),
+            image_dir: dir.path().join("image"),
+            shasum_file: dir.path().join("cowsay-1.4.0.shasum"),
+            distro_file: dir.path().join("cowsay-1.4.0.tgz"),
+            bins: vec!["cowsay".to_string()],
+            engines: None,
+        };
+        std::fs::write(&distro.distro_file, &tarball[..]).unwrap();
+
+        // a cached tarball isn't trusted until its expected shasum is saved
+        assert!(!distro.downloaded_pkg_is_ok());
+        distro.save_shasum().unwrap();
+        assert!(distro.downloaded_pkg_is_ok());
+
+        distro.shasum = "0000".to_string();
+        distro.save_shasum().unwrap();
+        assert!(!distro.downloaded_pkg_is_ok());
+    }
+
+    #[test]
+    fn test_check_bin_conflicts() {
+        with_temp_home(|_| {
+            let bins = vec![
+                bin_entry("tsc", "./bin/tsc"),
+                bin_entry("tsserver", "./bin/tsserver"),
+            ];
+            check_bin_conflicts(&bins).expect("reported a conflict with no bins installed");
+
+            bin_config("tsc", "typescript-nightly")
+                .to_serial()
+                .write()
+                .unwrap();
+            match check_bin_conflicts(&bins) {
+                Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                    Some(ErrorDetails::BinaryAlreadyInstalled {
+                        bin_name, package, ..
+                    }) => {
+                        assert_eq!(bin_name, "tsc");
+                        assert_eq!(package, "typescript-nightly");
+                    }
+                    other => panic!("unexpected error: {:?}", other),
+                },
+                Ok(_) => panic!("missed a conflicting bin"),
+            }
+
+            // every conflict is reported at once
+            bin_config("tsserver", "tsserver-shim")
+                .to_serial()
+                .write()
+                .unwrap();
+            match check_bin_conflicts(&bins) {
+                Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                    Some(ErrorDetails::BinaryConflicts { conflicts }) => {
+                        let names: Vec<&str> =
+                            conflicts.iter().map(|(name, _, _)| name.as_str()).collect();
+                        assert_eq!(names, vec!["tsc", "tsserver"]);
+                    }
+                    other => panic!("unexpected error: {:?}", other),
+                },
+                Ok(_) => panic!("missed conflicting bins"),
+            }
+        });
+    }
+
+    // creating links to files needs extra privileges on Windows
+    #[test]
+    #[cfg(unix)]
+    fn test_uninstall() {
+        with_temp_home(|_| {
+            PackageConfig {
+                name: "cowsay".to_string(),
+                version: Version::parse("1.4.0").unwrap(),
+                platform: install_platform(),
+                bins: vec!["cowsay".to_string(), "cowthink".to_string()],
+            }
+            .to_serial()
+            .write()
+            .unwrap();
+            // `cowthink` has since been installed by another package
+            for config in &[
+                bin_config("cowsay", "cowsay"),
+                bin_config("cowthink", "moo"),
+            ] {
+                config.to_serial().write().unwrap();
+                shim::create(&config.name).unwrap();
+            }
+
+            uninstall("cowsay").expect("could not uninstall package");
+            assert!(!path::user_package_config_file("cowsay").unwrap().exists());
+            assert!(!path::user_tool_bin_config("cowsay").unwrap().exists());
+            assert_eq!(shim::list().unwrap(), vec!["cowthink"]);
+            assert!(path::user_tool_bin_config("cowthink").unwrap().exists());
+
+            match uninstall("cowsay") {
+                Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                    Some(ErrorDetails::PackageNotInstalled { name }) => assert_eq!(name, "cowsay"),
+                    other => panic!("unexpected error: {:?}", other),
+                },
+                Ok(_) => panic!("uninstalled a package that isn't installed"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_installer_command_line() {
+        let mut platform = PlatformSpec {
+            node_runtime: Version::parse("10.15.3").unwrap(),
+            npm: Some(Version::parse("6.4.1").unwrap()),
+            yarn: None,
+        };
+        assert_eq!(
+            Installer::for_platform(&platform).to_string(),
+            "npm install --only=production"
+        );
+
+        platform.yarn = Some(Version::parse("1.13.0").unwrap());
+        assert_eq!(
+            Installer::for_platform(&platform).to_string(),
+            "yarn install --production"
+        );
+    }
+
+    #[test]
+    fn test_executables_map() {
+        let package_dir = fixture_path("basic/node_modules/typescript");
+        assert_eq!(
+            executables(&package_dir).unwrap(),
+            vec![
+                bin_entry("tsc", "./bin/tsc"),
+                bin_entry("tsserver", "./bin/tsserver"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_executables_string() {
+        let package_dir = fixture_path("basic/node_modules/rsvp");
+        assert_eq!(
+            executables(&package_dir).unwrap(),
+            vec![bin_entry("rsvp", "./bin/rsvp.js")]
+        );
+    }
+
+    #[test]
+    fn test_executables_none() {
+        let package_dir = fixture_path("basic/node_modules/@namespace/some-dep");
+        match executables(&package_dir) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::NoPackageExecutables) => {}
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(bins) => panic!("found executables: {:?}", bins),
+        }
+    }
 }
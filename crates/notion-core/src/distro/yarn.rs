@@ -5,19 +5,22 @@ use std::path::PathBuf;
 use std::string::ToString;
 
 use semver::Version;
-use tempfile::tempdir_in;
 
 use archive::{Archive, Tarball};
-use notion_fail::{Fallible, ResultExt};
+use notion_fail::{throw, Fallible, ResultExt};
 
-use super::{download_tool_error, Distro, Fetched};
-use crate::fs::ensure_containing_dir_exists;
+use super::{
+    copy_local_archive, download_tool_error, encode_url_component, local_file_path, unpack_archive,
+    Distro,
+};
+use crate::config::config;
+use crate::error::ErrorDetails;
+use crate::fs::{ensure_containing_dir_exists, remove_dir_all_if_exists, trace_fs};
 use crate::hook::ToolHooks;
-use crate::inventory::YarnCollection;
+use crate::inventory::{YarnCollection, YarnIndex};
 use crate::path;
-use crate::style::progress_bar;
 use crate::tool::ToolSpec;
-use crate::version::VersionSpec;
+use crate::version::{self, VersionSpec};
 
 #[cfg(feature = "mock-network")]
 use mockito;
@@ -36,7 +39,7 @@ cfg_if::cfg_if! {
 
 /// A provisioned Yarn distribution.
 pub struct YarnDistro {
-    archive: Box<dyn Archive>,
+    url: String,
     version: Version,
 }
 
@@ -55,45 +58,51 @@ fn distro_is_valid(file: &PathBuf) -> bool {
     false
 }
 
+/// Resolves a version specifier against an already-fetched index of Yarn releases.
+/// Yarn doesn't distinguish LTS releases, so `lts` resolves to the latest version.
+pub fn resolve(spec: &VersionSpec, index: &YarnIndex) -> Fallible<Version> {
+    let mut entries = index.entries.iter().rev();
+    let version = match spec {
+        VersionSpec::Latest | VersionSpec::Lts => entries.next(),
+        VersionSpec::Semver(requirement) => {
+            let include_prereleases = version::include_prereleases();
+            entries.find(|entry| version::matches(requirement, entry, include_prereleases))
+        }
+        VersionSpec::Exact(exact) => entries.find(|version| *version == exact),
+        VersionSpec::None | VersionSpec::Tag(_) => None,
+    };
+
+    match version {
+        Some(version) => Ok(version.clone()),
+        None => throw!(ErrorDetails::YarnVersionNotFound {
+            matching: spec.to_string()
+        }),
+    }
+}
+
 impl YarnDistro {
     /// Provision a Yarn distribution from the public distributor (`https://yarnpkg.com`).
-    fn public(version: Version) -> Fallible<Self> {
+    fn public(version: Version) -> Self {
         let version_str = version.to_string();
         let distro_file_name = path::yarn_distro_file_name(&version_str);
         let url = format!(
             "{}/v{}/{}",
             public_yarn_server_root(),
-            version_str,
-            distro_file_name
+            encode_url_component(&version_str),
+            encode_url_component(&distro_file_name)
         );
-        YarnDistro::remote(version, &url)
+        YarnDistro::remote(version, url)
     }
 
     /// Provision a Yarn distribution from a remote distributor.
-    fn remote(version: Version, url: &str) -> Fallible<Self> {
-        let distro_file_name = path::yarn_distro_file_name(&version.to_string());
-        let distro_file = path::yarn_inventory_dir()?.join(&distro_file_name);
-
-        if distro_is_valid(&distro_file) {
-            return YarnDistro::local(version, File::open(distro_file).unknown()?);
-        }
+    fn remote(version: Version, url: String) -> Self {
+        YarnDistro { url, version }
+    }
 
-        ensure_containing_dir_exists(&distro_file)?;
-        Ok(YarnDistro {
-            archive: Tarball::fetch(url, &distro_file).with_context(download_tool_error(
-                ToolSpec::Yarn(VersionSpec::exact(&version)),
-                url,
-            ))?,
-            version: version,
-        })
-    }
-
-    /// Provision a Yarn distribution from the filesystem.
-    fn local(version: Version, file: File) -> Fallible<Self> {
-        Ok(YarnDistro {
-            archive: Tarball::load(file).unknown()?,
-            version: version,
-        })
+    /// The path of this version's archive in the inventory.
+    fn distro_file(&self) -> Fallible<PathBuf> {
+        let distro_file_name = path::yarn_distro_file_name(&self.version.to_string());
+        Ok(path::yarn_inventory_dir()?.join(&distro_file_name))
     }
 }
 
@@ -114,9 +123,9 @@ impl Distro for YarnDistro {
             }) => {
                 let url =
                     hook.resolve(&version, &path::yarn_distro_file_name(&version.to_string()))?;
-                YarnDistro::remote(version, &url)
+                Ok(YarnDistro::remote(version, url))
             }
-            _ => YarnDistro::public(version),
+            _ => Ok(YarnDistro::public(version)),
         }
     }
 
@@ -125,42 +134,127 @@ impl Distro for YarnDistro {
         &self.version
     }
 
-    /// Fetches this version of Yarn. (It is left to the responsibility of the `YarnCollection`
-    /// to update its state after fetching succeeds.)
-    fn fetch(self, collection: &YarnCollection) -> Fallible<Fetched<Version>> {
-        if collection.contains(&self.version) {
-            return Ok(Fetched::Already(self.version));
+    /// Produces this Yarn version if it is already in the inventory.
+    fn installed(&self, collection: &YarnCollection) -> Fallible<Option<Version>> {
+        // an archive whose image directory has been removed needs to be unpacked again
+        if collection.contains(&self.version)
+            && path::yarn_image_dir(&self.version.to_string())?.is_dir()
+        {
+            Ok(Some(self.version.clone()))
+        } else {
+            Ok(None)
         }
+    }
 
-        let temp = tempdir_in(path::tmp_dir()?).unknown()?;
-        let bar = progress_bar(
-            self.archive.origin(),
-            &format!("v{}", self.version),
-            self.archive
-                .uncompressed_size()
-                .unwrap_or(self.archive.compressed_size()),
-        );
+    /// Loads the Yarn archive from the inventory, or downloads it if there isn't a
+    /// valid copy there.
+    fn fetch(&self) -> Fallible<Box<dyn Archive>> {
+        let distro_file = self.distro_file()?;
+
+        if distro_is_valid(&distro_file) {
+            return Tarball::load(File::open(distro_file).unknown()?).unknown();
+        }
+
+        ensure_containing_dir_exists(&distro_file)?;
+        let toolspec = ToolSpec::Yarn(VersionSpec::exact(&self.version));
+
+        if let Some(source) = local_file_path(&self.url) {
+            let file = copy_local_archive(&source, &distro_file, toolspec)?;
+            return Tarball::load(file).unknown();
+        }
 
-        self.archive
-            .unpack(temp.path(), &mut |_, read| {
-                bar.inc(read as u64);
-            })
-            .unknown()?;
+        let timeout = config()?.network_timeout();
+        Tarball::fetch(&self.url, &distro_file, timeout).with_context(download_tool_error(
+            toolspec,
+            &self.url,
+            &distro_file,
+        ))
+    }
 
+    /// Unpacks this version of Yarn into its image directory. (It is left to the
+    /// responsibility of the `YarnCollection` to update its state after fetching succeeds.)
+    fn unpack(self, archive: Box<dyn Archive>) -> Fallible<Version> {
         let version_string = self.version.to_string();
+        let temp = unpack_archive(
+            archive,
+            &format!("v{}", version_string),
+            &self.distro_file()?,
+            || self.fetch(),
+        )?;
 
         let dest = path::yarn_image_dir(&version_string)?;
 
+        // clear out any partially unpacked image left from an earlier attempt
+        remove_dir_all_if_exists(&dest)?;
         ensure_containing_dir_exists(&dest)?;
 
-        rename(
-            temp.path()
-                .join(path::yarn_archive_root_dir_name(&version_string)),
-            dest,
-        )
-        .unknown()?;
+        let unpack_dir = temp
+            .path()
+            .join(path::yarn_archive_root_dir_name(&version_string));
+        trace_fs("rename", &dest, rename(unpack_dir, &dest)).unknown()?;
+
+        Ok(self.version)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::resolve;
+    use crate::inventory::{serial, YarnIndex};
+    use crate::version::VersionSpec;
+    use semver::Version;
+
+    // 1.13.0 is missing its tarball, so it isn't a full release
+    const INDEX_JSON: &'static str = r#"[
+  {"tag_name":"v1.14.0","assets":[{"name":"yarn-v1.14.0.tar.gz"},{"name":"yarn-1.14.0.msi"}]},
+  {"tag_name":"v1.13.0","assets":[{"name":"yarn-1.13.0.msi"}]},
+  {"tag_name":"v1.12.3","assets":[{"name":"yarn-v1.12.3.tar.gz"}]},
+  {"tag_name":"v1.12.1","assets":[{"name":"yarn-v1.12.1.tar.gz"}]},
+  {"tag_name":"v1.9.4","assets":[{"name":"yarn-v1.9.4.tar.gz"}]}
+]"#;
+
+    fn index() -> YarnIndex {
+        serde_json::de::from_str::<serial::YarnIndex>(INDEX_JSON)
+            .expect("could not parse index JSON")
+            .into_index()
+            .expect("could not convert index")
+    }
+
+    #[test]
+    fn test_resolve_latest() {
+        assert_eq!(
+            resolve(&VersionSpec::Latest, &index()).expect("could not resolve latest"),
+            Version::parse("1.14.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resolve_range() {
+        assert_eq!(
+            resolve(&VersionSpec::parse("^1.12.0").unwrap(), &index())
+                .expect("could not resolve range"),
+            Version::parse("1.14.0").unwrap()
+        );
+        assert_eq!(
+            resolve(&VersionSpec::parse("<1.14").unwrap(), &index())
+                .expect("could not resolve range"),
+            Version::parse("1.12.3").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resolve_exact() {
+        assert_eq!(
+            resolve(&VersionSpec::parse("1.12.1").unwrap(), &index())
+                .expect("could not resolve exact version"),
+            Version::parse("1.12.1").unwrap()
+        );
+    }
 
-        bar.finish_and_clear();
-        Ok(Fetched::Now(self.version))
+    #[test]
+    fn test_resolve_no_match() {
+        assert!(resolve(&VersionSpec::parse("^2").unwrap(), &index()).is_err());
+        assert!(resolve(&VersionSpec::parse("1.13.0").unwrap(), &index()).is_err());
     }
 }
@@ -5,6 +5,28 @@ use std::path::{Path, PathBuf};
 
 pub const UNSAFE_GLOBAL: &'static str = "NOTION_UNSAFE_GLOBAL";
 
+pub const FORCE_FETCH: &'static str = "NOTION_FORCE_FETCH";
+
+pub const ARCH_OVERRIDE: &'static str = "NOTION_ARCH";
+
+pub const ARCHIVE_FORMAT: &'static str = "NOTION_ARCHIVE_FORMAT";
+
+pub const NOTION_HOME: &'static str = "NOTION_HOME";
+
+pub const SKIP_SMOKE_TEST: &'static str = "NOTION_SKIP_SMOKE_TEST";
+
+pub const QUIET: &'static str = "NOTION_QUIET";
+
+pub const PRERELEASE: &'static str = "NOTION_PRERELEASE";
+
+pub const NODE_VERSION: &'static str = "NOTION_NODE";
+
+pub const NPM_VERSION: &'static str = "NOTION_NPM";
+
+pub const YARN_VERSION: &'static str = "NOTION_YARN";
+
+pub const NPM_TOKEN: &'static str = "NPM_TOKEN";
+
 pub(crate) fn shell_name() -> Option<String> {
     env::var_os("NOTION_SHELL").map(|s| s.to_string_lossy().into_owned())
 }
@@ -15,6 +37,16 @@ pub fn postscript_path() -> Option<PathBuf> {
         .map(|ref s| Path::new(s).to_path_buf())
 }
 
+/// Reads a tool version from the environment, if set.
+pub(crate) fn tool_version(var: &str) -> Option<String> {
+    env::var_os(var).map(|s| s.to_string_lossy().into_owned())
+}
+
+/// The npm registry auth token to use when `.npmrc` doesn't configure one.
+pub(crate) fn npm_token() -> Option<String> {
+    env::var_os(NPM_TOKEN).map(|s| s.to_string_lossy().into_owned())
+}
+
 #[cfg(test)]
 pub mod tests {
 
@@ -1,14 +1,23 @@
 use std::fmt;
+use std::io;
 use std::process::ExitStatus;
 
 use failure::Fail;
 use notion_fail::{ExitCode, NotionFail};
 
+use crate::path::SUPPORTED_ARCHS;
 use crate::tool::ToolSpec;
 use crate::version::VersionSpec;
 
 #[derive(Debug, Fail)]
 pub enum ErrorDetails {
+    /// Thrown when a downloaded archive is not in the expected format, e.g. because
+    /// a mirror served an HTML error page.
+    ArchiveFormatUnsupported {
+        expected: String,
+        detected: String,
+    },
+
     /// Thrown when package tries to install a binary that is already installed.
     BinaryAlreadyInstalled {
         bin_name: String,
@@ -16,6 +25,12 @@ pub enum ErrorDetails {
         version: String,
     },
 
+    /// Thrown when package tries to install several binaries that are already installed.
+    /// Each conflict is the binary name, and the package and version that installed it.
+    BinaryConflicts {
+        conflicts: Vec<(String, String, String)>,
+    },
+
     BinaryExecError {
         error: String,
     },
@@ -29,11 +44,31 @@ pub enum ErrorDetails {
         command_name: String,
     },
 
+    /// Thrown when an archive could not be unpacked, even after downloading it again.
+    /// The path is that of the file the last attempt read, which has been removed.
+    CorruptInventoryArchive {
+        path: String,
+    },
+
     CouldNotDetermineTool,
 
+    /// Thrown when a directory could not be created.
     CreateDirError {
         dir: String,
         error: String,
+        kind: io::ErrorKind,
+    },
+
+    /// Thrown when a directory tree could not be removed.
+    DeleteDirError {
+        dir: String,
+        error: String,
+    },
+
+    /// Thrown when a file could not be removed.
+    DeleteFileError {
+        file: String,
+        error: String,
     },
 
     DepPackageReadError {
@@ -45,6 +80,11 @@ pub enum ErrorDetails {
         advice: String,
     },
 
+    /// Thrown when a download or unpack fails because the disk is full.
+    DiskSpaceError {
+        path: String,
+    },
+
     DownloadToolNetworkError {
         tool: ToolSpec,
         from_url: String,
@@ -55,20 +95,66 @@ pub enum ErrorDetails {
         tool: ToolSpec,
     },
 
+    /// Thrown when `HOME` is set, but not to an existing directory.
+    HomeNotADirectory {
+        path: String,
+    },
+
+    /// Thrown when a hook command runs but exits with a failure status.
+    HookExecutionFailed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+
+    /// Thrown when an environment variable read by Notion is set to an invalid value.
+    InvalidEnvironmentVar {
+        name: String,
+        value: String,
+        expected: String,
+    },
+
     InvalidHookCommand {
         command: String,
     },
 
+    /// Thrown when an index file named by a `local` hook can't be read.
+    LocalIndexReadError {
+        path: String,
+        error: String,
+    },
+
+    /// Thrown when an HTTP request takes longer than the network timeout.
+    NetworkTimeout {
+        seconds: u64,
+    },
+
     /// Thrown when BinConfig (read from file) does not contain Platform info.
     NoBinPlatform {
         binary: String,
     },
 
+    /// Thrown when the pinned Node version has no image directory, e.g. because it
+    /// was never fetched or has been removed.
+    NodeNotFetched {
+        version: String,
+    },
+
+    /// Thrown when a newly unpacked Node binary can't be run on this machine, e.g.
+    /// because it was built for a different architecture.
+    NodeSmokeTestFailed {
+        version: String,
+        error: String,
+    },
+
     /// Thrown when there is no Node version matching a requested semver specifier.
     NodeVersionNotFound {
         matching: String,
     },
 
+    /// Thrown when the npm or Yarn version is set in the environment without a Node version.
+    NoEnvNodeVersion,
+
     NoGlobalInstalls,
 
     NoHomeEnvironmentVar,
@@ -87,10 +173,23 @@ pub enum ErrorDetails {
     /// Thrown when a user tries to pin a Yarn version before pinning a Node version.
     NoPinnedNodeVersion,
 
+    /// Thrown when there is no backup of the user platform file to restore.
+    NoPlatformBackup {
+        path: String,
+    },
+
+    /// Thrown when there is no earlier user platform to undo to.
+    NoPlatformHistory {
+        path: String,
+    },
+
     NoSuchTool {
         tool: String,
     },
 
+    /// Thrown when a user tries to install Yarn or npm before installing a Node version.
+    NoUserNodeVersion,
+
     /// Thrown when the user tries to pin Node or Yarn versions outside of a package.
     NotInPackage,
 
@@ -100,14 +199,21 @@ pub enum ErrorDetails {
 
     NoVersionsFound,
 
+    /// Thrown when there is no npm version matching a requested semver specifier.
+    NpmVersionNotFound {
+        matching: String,
+    },
+
     NpxNotAvailable {
         version: String,
     },
 
-    /// Thrown when package install command is not successful.
+    /// Thrown when package install command is not successful. The `stderr` holds the
+    /// end of the command's error output, if it was captured.
     PackageInstallFailed {
         cmd: String,
         status: ExitStatus,
+        stderr: String,
     },
 
     /// Thrown when package install command fails to execute.
@@ -115,6 +221,11 @@ pub enum ErrorDetails {
         error: String,
     },
 
+    /// Thrown when the user tries to uninstall a package that is not installed.
+    PackageNotInstalled {
+        name: String,
+    },
+
     PackageReadError {
         error: String,
     },
@@ -124,15 +235,44 @@ pub enum ErrorDetails {
 
     PathError,
 
+    /// Thrown when the user platform file exists but is not a regular file.
+    PlatformFileNotRegular {
+        path: String,
+    },
+
+    /// Thrown when the user platform file was written by a newer version of Notion.
+    PlatformSchemaUnsupported {
+        schema: u32,
+    },
+
+    /// Thrown when a package registry rejects the request as unauthorized.
+    RegistryAuthFailed {
+        registry: String,
+    },
+
     /// Thrown when the public registry for Node or Yarn could not be downloaded.
     RegistryFetchError {
         error: String,
     },
 
+    /// Thrown when a registry responds with an HTML page instead of JSON, which is
+    /// usually the login page of an authenticating proxy.
+    RegistryReturnedHtml {
+        url: String,
+    },
+
+    /// Thrown when the shim executable is run directly, rather than through a tool's name.
+    ShimInvokedDirectly,
+
     SymlinkError {
         error: String,
     },
 
+    /// Thrown when the lock on the user toolchain could not be acquired in time.
+    ToolchainLocked {
+        path: String,
+    },
+
     ToolNotImplemented,
 
     /// Thrown when the shell name specified in the Notion environment is not supported.
@@ -146,7 +286,25 @@ pub enum ErrorDetails {
     /// Thrown when the shell name was not specified in the Notion environment.
     UnspecifiedShell,
 
+    /// Thrown when the requested architecture override is not a known Node architecture.
+    UnsupportedArch {
+        arch: String,
+    },
+
+    /// Thrown when an installed image is to be verified, but the tool's version isn't exact.
+    VerifyNeedsExactVersion {
+        tool: String,
+    },
+
+    /// Thrown when a version is resolved without one being specified, and the
+    /// command gives no default for it.
+    VersionNotSpecified {
+        tool: String,
+    },
+
+    /// Thrown when a version or version requirement can't be parsed.
     VersionParseError {
+        input: String,
         error: String,
     },
 
@@ -159,23 +317,48 @@ pub enum ErrorDetails {
 impl fmt::Display for ErrorDetails {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            ErrorDetails::ArchiveFormatUnsupported { expected, detected } => write!(f, r#"
+Expected a {} archive, but the downloaded file is {}.
+
+Please check that the configured download server or mirror provides the correct archive."#, expected, detected),
             ErrorDetails::BinaryAlreadyInstalled { bin_name, package, version } => write!(f, "Conflict with bin '{}' already installed by '{}' version {}", bin_name, package, version),
+            ErrorDetails::BinaryConflicts { conflicts } => {
+                write!(f, "Conflicts with bins already installed:")?;
+                for (bin_name, package, version) in conflicts {
+                    write!(f, "\n    '{}' installed by '{}' version {}", bin_name, package, version)?;
+                }
+                Ok(())
+            }
             ErrorDetails::BinaryExecError { error } => write!(f, "{}", error),
             ErrorDetails::CannotPinPackage => {
                 write!(f, "Only node, yarn, and npm can be pinned in a project")
             }
             ErrorDetails::CliParseError => write!(f, "There was a problem parsing the command line input"),
             ErrorDetails::CommandNotImplemented { command_name } => write!(f, "command `{}` is not yet implemented", command_name),
+            ErrorDetails::CorruptInventoryArchive { path } => write!(f, r#"
+The archive at {} is corrupt and could not be unpacked, so it has been removed.
+
+Please try fetching the tool again."#, path),
             ErrorDetails::CouldNotDetermineTool => write!(f, "Tool name could not be determined"),
-            ErrorDetails::CreateDirError { dir, error } => {
+            ErrorDetails::CreateDirError { dir, error, .. } => {
                 write!(f, "Could not create directory {}: {}", dir, error)
             }
+            ErrorDetails::DeleteDirError { dir, error } => {
+                write!(f, "Could not remove directory {}: {}", dir, error)
+            }
+            ErrorDetails::DeleteFileError { file, error } => {
+                write!(f, "Could not remove file {}: {}", file, error)
+            }
             ErrorDetails::DepPackageReadError { error } => {
                 write!(f, "Could not read dependent package info: {}", error)
             }
             ErrorDetails::DeprecatedCommandError { command, advice } => {
                 write!(f, "The subcommand `{}` is deprecated.\n{}", command, advice)
             }
+            ErrorDetails::DiskSpaceError { path } => write!(f, r#"
+Not enough disk space to write to {}
+
+Please free up some disk space and try again."#, path),
             ErrorDetails::DownloadToolNetworkError {
                 tool,
                 from_url,
@@ -186,13 +369,50 @@ impl fmt::Display for ErrorDetails {
                 tool, from_url, error
             ),
             ErrorDetails::DownloadToolNotFound { tool } => write!(f, "{} not found", tool),
+            ErrorDetails::HomeNotADirectory { path } => write!(f, r#"
+environment variable 'HOME' is set to '{}', which is not a directory.
+
+Please set it to your home directory."#, path),
+            ErrorDetails::HookExecutionFailed {
+                command,
+                status,
+                stderr,
+            } => write!(
+                f,
+                "Hook command '{}' failed with status {}\n{}",
+                command, status, stderr
+            ),
+            ErrorDetails::InvalidEnvironmentVar { name, value, expected } => write!(f, r#"
+Invalid value for {}: '{}'
+
+Please set it to {}, or unset it to use the default."#, name, value, expected),
             ErrorDetails::InvalidHookCommand { command } => write!(f, "Invalid hook command: '{}'", command),
+            ErrorDetails::LocalIndexReadError { path, error } => {
+                write!(f, "Could not read the index file at {}\n{}", path, error)
+            }
+            ErrorDetails::NetworkTimeout { seconds } => write!(f, r#"
+Network request timed out after {} seconds
+
+To wait longer, set the NOTION_NETWORK_TIMEOUT environment variable to a number of seconds."#, seconds),
             ErrorDetails::NoBinPlatform { binary } => {
                 write!(f, "Platform info for executable `{}` is missing", binary)
             }
+            ErrorDetails::NodeNotFetched { version } => write!(f, r#"
+Node version {} has not been fetched.
+
+Please run `notion fetch node {}` and try again."#, version, version),
+            ErrorDetails::NodeSmokeTestFailed { version, error } => write!(f, r#"
+Node v{} was installed, but could not be run on this machine:
+{}
+
+Please check that the Node distribution matches this machine's operating system and architecture."#, version, error),
             ErrorDetails::NodeVersionNotFound { matching } => {
                 write!(f, "No Node version found for {}", matching)
             }
+            ErrorDetails::NoEnvNodeVersion => write!(f, r#"
+NOTION_NPM or NOTION_YARN is set, but NOTION_NODE is not.
+
+Please also set NOTION_NODE to the version of Node to use."#),
             ErrorDetails::NoGlobalInstalls => write!(f, r#"
 Global package installs are not recommended.
 
@@ -206,39 +426,104 @@ Consider using `notion install` to add a package to your toolchain (see `notion
             ErrorDetails::NoPinnedNodeVersion => {
                 write!(f, "There is no pinned node version for this project")
             }
+            ErrorDetails::NoPlatformBackup { path } => {
+                write!(f, "There is no backup of the user toolchain to restore at {}", path)
+            }
+            ErrorDetails::NoPlatformHistory { path } => {
+                write!(f, "There is no earlier user toolchain to undo to in {}", path)
+            }
             ErrorDetails::NoSuchTool { tool } => write!(f, r#"
 No {} version selected.
 
 See `notion help pin` for help adding {} to a project toolchain.
 
 See `notion help install` for help adding {} to your personal toolchain."#, tool, tool, tool),
+            ErrorDetails::NoUserNodeVersion => write!(f, r#"
+There is no Node version in your personal toolchain.
+
+Please install one first with `notion install node`."#),
             ErrorDetails::NotInPackage => write!(f, "Not in a node package"),
             ErrorDetails::NoToolChain { shim_name } => {
                 write!(f, "No toolchain available to run {}", shim_name)
             }
             ErrorDetails::NoVersionsFound => write!(f, "no versions found"),
+            ErrorDetails::NpmVersionNotFound { matching } => {
+                write!(f, "No npm version found for {}", matching)
+            }
             ErrorDetails::NpxNotAvailable { version } => write!(f, r#"
 'npx' is only available with npm >= 5.2.0
 
 This project is configured to use version {} of npm."#, version),
-            ErrorDetails::PackageInstallFailed { cmd, status } => write!(f, "Command `{}` failed with status {}", cmd, status),
+            ErrorDetails::PackageInstallFailed { cmd, status, stderr } => {
+                write!(f, "Command `{}` failed with status {}", cmd, status)?;
+                if !stderr.is_empty() {
+                    write!(f, "\n\n{}", stderr)?;
+                }
+                Ok(())
+            }
             ErrorDetails::PackageInstallIoError { error } => write!(f, "Error executing package install command: {}", error),
+            ErrorDetails::PackageNotInstalled { name } => write!(f, r#"
+Package '{}' is not installed in your toolchain.
+
+Use `notion list` to see the installed packages."#, name),
             ErrorDetails::PackageReadError { error } => {
                 write!(f, "Could not read package info: {}", error)
             }
             ErrorDetails::PackageUnpackError => write!(f, "Package unpack error: Could not determine unpack directory name"),
             ErrorDetails::PathError => write!(f, "`path` internal error"),
+            ErrorDetails::PlatformFileNotRegular { path } => write!(f, r#"
+The toolchain file at {} is not a regular file.
+
+Please remove it (e.g. if it is a directory) and try again."#, path),
+            ErrorDetails::PlatformSchemaUnsupported { schema } => write!(f, r#"
+The toolchain file uses format version {}, which this version of Notion does not support.
+
+Please upgrade Notion to the latest version."#, schema),
+            ErrorDetails::RegistryAuthFailed { registry } => write!(f, r#"
+Could not authenticate with the registry at {}
+
+Please set an auth token for this registry in your .npmrc or the NPM_TOKEN environment variable."#, registry),
             ErrorDetails::RegistryFetchError { error } => {
                 write!(f, "Could not fetch public registry\n{}", error)
             }
+            ErrorDetails::RegistryReturnedHtml { url } => write!(f, r#"
+The registry at {} returned an HTML page instead of JSON.
+
+You may be behind a proxy that requires you to log in. Please sign in to your network (e.g. by opening a web page in a browser) and try again."#, url),
+            ErrorDetails::ShimInvokedDirectly => write!(f, r#"
+The Notion shim can't be run directly.
+
+Notion installs each tool as a link to the shim, named after the tool (e.g. `node`
+or `yarn`). When the shim runs, it uses the name it was invoked with to decide which
+tool to run, and then runs the version of that tool for the current project.
+
+Please run the tool by its name instead."#),
             ErrorDetails::SymlinkError { error } => write!(f, "{}", error),
+            ErrorDetails::ToolchainLocked { path } => write!(f, r#"
+Could not acquire the toolchain lock at {}
+
+Another Notion process may be modifying your toolchain. Please try again once it has finished."#, path),
             ErrorDetails::ToolNotImplemented => write!(f, "this tool is not yet implemented"),
             ErrorDetails::UnrecognizedShell { name } => write!(f, "Unrecognized shell: {}", name),
             ErrorDetails::UnspecifiedPostscript => {
                 write!(f, "Notion postscript file not specified")
             }
             ErrorDetails::UnspecifiedShell => write!(f, "Notion shell not specified"),
-            ErrorDetails::VersionParseError { error } => write!(f, "{}", error),
+            ErrorDetails::UnsupportedArch { arch } => write!(f, r#"
+Unsupported architecture: '{}'
+
+Supported architectures are: {}"#, arch, SUPPORTED_ARCHS.join(", ")),
+            ErrorDetails::VerifyNeedsExactVersion { tool } => write!(f, r#"
+Could not verify {}: only an exact version can be verified.
+
+Please specify the installed version to check, e.g. `node@10.15.3`."#, tool),
+            ErrorDetails::VersionNotSpecified { tool } => write!(f, r#"
+No version of {} was specified.
+
+Please specify a version, e.g. `{} latest` or `{} 1.2.3`."#, tool, tool, tool),
+            ErrorDetails::VersionParseError { input, error } => {
+                write!(f, "Could not parse version '{}': {}", input, error)
+            }
             ErrorDetails::YarnVersionNotFound { matching } => {
                 write!(f, "No Yarn version found for {}", matching)
             }
@@ -249,42 +534,70 @@ This project is configured to use version {} of npm."#, version),
 impl NotionFail for ErrorDetails {
     fn exit_code(&self) -> ExitCode {
         match self {
+            ErrorDetails::ArchiveFormatUnsupported { .. } => ExitCode::ConfigurationError,
             ErrorDetails::BinaryAlreadyInstalled { .. } => ExitCode::FileSystemError,
+            ErrorDetails::BinaryConflicts { .. } => ExitCode::FileSystemError,
             ErrorDetails::BinaryExecError { .. } => ExitCode::ExecutionFailure,
             ErrorDetails::CannotPinPackage => ExitCode::InvalidArguments,
             ErrorDetails::CliParseError => ExitCode::UnknownError,
             ErrorDetails::CommandNotImplemented { .. } => ExitCode::NotYetImplemented,
+            ErrorDetails::CorruptInventoryArchive { .. } => ExitCode::IntegrityError,
             ErrorDetails::CouldNotDetermineTool => ExitCode::UnknownError,
             ErrorDetails::CreateDirError { .. } => ExitCode::FileSystemError,
+            ErrorDetails::DeleteDirError { .. } => ExitCode::FileSystemError,
+            ErrorDetails::DeleteFileError { .. } => ExitCode::FileSystemError,
             ErrorDetails::DepPackageReadError { .. } => ExitCode::FileSystemError,
             ErrorDetails::DeprecatedCommandError { .. } => ExitCode::InvalidArguments,
+            ErrorDetails::DiskSpaceError { .. } => ExitCode::FileSystemError,
             ErrorDetails::DownloadToolNetworkError { .. } => ExitCode::NetworkError,
             ErrorDetails::DownloadToolNotFound { .. } => ExitCode::NoVersionMatch,
+            ErrorDetails::HomeNotADirectory { .. } => ExitCode::EnvironmentError,
+            ErrorDetails::HookExecutionFailed { .. } => ExitCode::ExecutionFailure,
+            ErrorDetails::InvalidEnvironmentVar { .. } => ExitCode::EnvironmentError,
             ErrorDetails::InvalidHookCommand { .. } => ExitCode::UnknownError,
+            ErrorDetails::LocalIndexReadError { .. } => ExitCode::FileSystemError,
+            ErrorDetails::NetworkTimeout { .. } => ExitCode::NetworkError,
             ErrorDetails::NoBinPlatform { .. } => ExitCode::ExecutionFailure,
+            ErrorDetails::NodeNotFetched { .. } => ExitCode::ExecutionFailure,
+            ErrorDetails::NodeSmokeTestFailed { .. } => ExitCode::ExecutionFailure,
             ErrorDetails::NodeVersionNotFound { .. } => ExitCode::NoVersionMatch,
+            ErrorDetails::NoEnvNodeVersion => ExitCode::EnvironmentError,
             ErrorDetails::NoGlobalInstalls => ExitCode::InvalidArguments,
             ErrorDetails::NoHomeEnvironmentVar => ExitCode::EnvironmentError,
             ErrorDetails::NoLocalDataDir => ExitCode::EnvironmentError,
             ErrorDetails::NoPackageExecutables { .. } => ExitCode::InvalidArguments,
             ErrorDetails::NoPackageFound { .. } => ExitCode::NoVersionMatch,
             ErrorDetails::NoPinnedNodeVersion => ExitCode::ConfigurationError,
+            ErrorDetails::NoPlatformBackup { .. } => ExitCode::FileSystemError,
+            ErrorDetails::NoPlatformHistory { .. } => ExitCode::FileSystemError,
             ErrorDetails::NoSuchTool { .. } => ExitCode::NoVersionMatch,
+            ErrorDetails::NoUserNodeVersion => ExitCode::ConfigurationError,
             ErrorDetails::NotInPackage => ExitCode::ConfigurationError,
             ErrorDetails::NoToolChain { .. } => ExitCode::ExecutionFailure,
             ErrorDetails::NoVersionsFound => ExitCode::NoVersionMatch,
+            ErrorDetails::NpmVersionNotFound { .. } => ExitCode::NoVersionMatch,
             ErrorDetails::NpxNotAvailable { .. } => ExitCode::ExecutableNotFound,
             ErrorDetails::PackageInstallFailed { .. } => ExitCode::FileSystemError,
             ErrorDetails::PackageInstallIoError { .. } => ExitCode::FileSystemError,
+            ErrorDetails::PackageNotInstalled { .. } => ExitCode::InvalidArguments,
             ErrorDetails::PackageReadError { .. } => ExitCode::FileSystemError,
-            ErrorDetails::PackageUnpackError => ExitCode::ConfigurationError,
+            ErrorDetails::PackageUnpackError => ExitCode::IntegrityError,
             ErrorDetails::PathError => ExitCode::UnknownError,
+            ErrorDetails::PlatformFileNotRegular { .. } => ExitCode::FileSystemError,
+            ErrorDetails::PlatformSchemaUnsupported { .. } => ExitCode::ConfigurationError,
+            ErrorDetails::RegistryAuthFailed { .. } => ExitCode::NetworkError,
             ErrorDetails::RegistryFetchError { .. } => ExitCode::NetworkError,
+            ErrorDetails::RegistryReturnedHtml { .. } => ExitCode::NetworkError,
+            ErrorDetails::ShimInvokedDirectly => ExitCode::InvalidArguments,
             ErrorDetails::SymlinkError { .. } => ExitCode::FileSystemError,
+            ErrorDetails::ToolchainLocked { .. } => ExitCode::Locked,
             ErrorDetails::ToolNotImplemented => ExitCode::ExecutableNotFound,
             ErrorDetails::UnrecognizedShell { .. } => ExitCode::EnvironmentError,
             ErrorDetails::UnspecifiedPostscript => ExitCode::EnvironmentError,
             ErrorDetails::UnspecifiedShell => ExitCode::EnvironmentError,
+            ErrorDetails::UnsupportedArch { .. } => ExitCode::InvalidArguments,
+            ErrorDetails::VerifyNeedsExactVersion { .. } => ExitCode::InvalidArguments,
+            ErrorDetails::VersionNotSpecified { .. } => ExitCode::InvalidArguments,
             ErrorDetails::VersionParseError { .. } => ExitCode::NoVersionMatch,
             ErrorDetails::YarnVersionNotFound { .. } => ExitCode::NoVersionMatch,
         }
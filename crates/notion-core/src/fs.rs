@@ -1,17 +1,90 @@
 //! Provides utilities for operating on the filesystem.
 
-use std::fs::{self, create_dir_all, read_dir, DirEntry, File, Metadata};
-use std::io::{self, ErrorKind};
+use std::fmt::Display;
+use std::fs::{self, read_dir, DirEntry, File, Metadata};
+use std::io::{self, ErrorKind, Write};
 use std::path::{Path, PathBuf};
 
 use crate::error::ErrorDetails;
 use notion_fail::{Fallible, ResultExt};
 
+/// A logger that writes the filesystem trace to stderr. Only Notion's own records
+/// are written, so that the debug logging of the HTTP libraries isn't.
+#[cfg(feature = "trace-fs")]
+struct TraceLogger;
+
+#[cfg(feature = "trace-fs")]
+impl log::Log for TraceLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("notion_core")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(feature = "trace-fs")]
+static TRACE_LOGGER: TraceLogger = TraceLogger;
+
+/// Installs the logger that writes the filesystem trace to stderr. Without it, the
+/// records from `trace_fs` go nowhere. Only the first call has any effect.
+#[cfg(feature = "trace-fs")]
+pub fn init_trace_logger() {
+    if log::set_logger(&TRACE_LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Debug);
+    }
+}
+
+/// Records the outcome of a filesystem mutation in the log when the `trace-fs`
+/// feature is enabled, and passes the result through unchanged. Only the operation,
+/// the path, and any error message are logged, and logging never affects the result.
+#[cfg(feature = "trace-fs")]
+pub(crate) fn trace_fs<T, E: Display>(
+    operation: &str,
+    path: &Path,
+    result: Result<T, E>,
+) -> Result<T, E> {
+    match result {
+        Ok(_) => log::debug!("fs: {} {}: ok", operation, path.display()),
+        Err(ref error) => log::debug!("fs: {} {}: {}", operation, path.display(), error),
+    }
+    result
+}
+
+/// Passes the result of a filesystem mutation through unchanged. (The `trace-fs`
+/// feature enables logging of each mutation.)
+#[cfg(not(feature = "trace-fs"))]
+#[inline]
+pub(crate) fn trace_fs<T, E: Display>(
+    _operation: &str,
+    _path: &Path,
+    result: Result<T, E>,
+) -> Result<T, E> {
+    result
+}
+
 pub fn touch(path: &Path) -> Fallible<File> {
     if !path.is_file() {
-        let basedir = path.parent().unwrap();
-        create_dir_all(basedir).unknown()?;
-        File::create(path).unknown()?;
+        ensure_containing_dir_exists(&path)?;
+        trace_fs("create", path, File::create(path)).unknown()?;
+    }
+    File::open(path).unknown()
+}
+
+/// Like `touch`, but initializes a newly created file with the `default` contents.
+/// Existing files are left untouched.
+pub fn touch_with_default(path: &Path, default: &[u8]) -> Fallible<File> {
+    if !path.is_file() {
+        ensure_containing_dir_exists(&path)?;
+        trace_fs("create", path, File::create(path))
+            .unknown()?
+            .write_all(default)
+            .unknown()?;
     }
     File::open(path).unknown()
 }
@@ -20,17 +93,69 @@ fn error_for_dir(dir: String) -> impl FnOnce(&io::Error) -> ErrorDetails {
     move |error| ErrorDetails::CreateDirError {
         dir,
         error: error.to_string(),
+        kind: error.kind(),
     }
 }
 
-/// This creates the parent directory of the input path, assuming the input path is a file.
+/// This creates the parent directory of the input path, assuming the input path is a file,
+/// along with any missing ancestors. Call it before writing a file that may be the first
+/// thing written into its directory, e.g. on a fresh install.
+///
+/// If another process creates the directory concurrently, the resulting `AlreadyExists`
+/// error is not treated as a failure.
 pub fn ensure_containing_dir_exists<P: AsRef<Path>>(path: &P) -> Fallible<()> {
     path.as_ref()
         .parent()
         .ok_or(ErrorDetails::PathError.into())
-        .and_then(|dir| {
-            fs::create_dir_all(dir).with_context(error_for_dir(dir.to_string_lossy().to_string()))
-        })
+        .and_then(ensure_dir_exists)
+}
+
+/// Creates the input directory and any missing ancestors, if it doesn't already exist.
+/// This is idempotent, so it's safe to call before any operation that writes into
+/// the directory.
+pub fn ensure_dir_exists(dir: &Path) -> Fallible<()> {
+    match trace_fs("create_dir_all", dir, fs::create_dir_all(dir)) {
+        Err(ref error) if error.kind() == ErrorKind::AlreadyExists && dir.is_dir() => Ok(()),
+        result => result.with_context(error_for_dir(dir.to_string_lossy().to_string())),
+    }
+}
+
+/// Removes a directory and all of its contents, if it exists. Does nothing if there
+/// is no directory at the input path.
+///
+/// On Windows, read-only files can't be removed, so they are made writable first.
+pub fn remove_dir_all_if_exists(path: &Path) -> Fallible<()> {
+    if !path.is_dir() {
+        return Ok(());
+    }
+
+    let error_for_delete_dir = |error: &io::Error| ErrorDetails::DeleteDirError {
+        dir: path.to_string_lossy().to_string(),
+        error: error.to_string(),
+    };
+
+    #[cfg(windows)]
+    make_writable(path).with_context(error_for_delete_dir)?;
+
+    trace_fs("remove", path, fs::remove_dir_all(path)).with_context(error_for_delete_dir)
+}
+
+/// Clears the read-only flag of a file or directory tree, so it can be removed.
+#[cfg(windows)]
+fn make_writable(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in read_dir(path)? {
+            make_writable(&entry?.path())?;
+        }
+    }
+
+    let mut permissions = metadata.permissions();
+    if permissions.readonly() {
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
 }
 
 /// Reads a file, if it exists.
@@ -67,3 +192,78 @@ pub fn read_dir_eager(dir: &Path) -> Fallible<impl Iterator<Item = (DirEntry, Me
         .collect::<Fallible<Vec<(DirEntry, Metadata)>>>()?
         .into_iter())
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::{
+        ensure_containing_dir_exists, ensure_dir_exists, remove_dir_all_if_exists,
+        touch_with_default,
+    };
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_touch_with_default_creates_with_contents() {
+        let dir = tempdir().expect("could not create temp dir");
+        let path = dir.path().join("nested").join("platform.json");
+
+        touch_with_default(&path, b"{}").expect("could not touch file");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_touch_with_default_keeps_existing_contents() {
+        let dir = tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+        fs::write(&path, "{\"yarn\":\"1.2.3\"}").unwrap();
+
+        touch_with_default(&path, b"{}").expect("could not touch file");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"yarn\":\"1.2.3\"}");
+    }
+
+    #[test]
+    fn test_ensure_dir_exists() {
+        let dir = tempdir().expect("could not create temp dir");
+        let bin_dir = dir.path().join("nested").join("bin");
+
+        ensure_dir_exists(&bin_dir).expect("could not create directory");
+        assert!(bin_dir.is_dir());
+
+        // ensuring an existing directory is a no-op
+        ensure_dir_exists(&bin_dir).expect("could not ensure existing directory");
+
+        // a file in the way can't be replaced with a directory
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        assert!(ensure_dir_exists(&file).is_err());
+    }
+
+    #[test]
+    fn test_ensure_containing_dir_exists() {
+        let dir = tempdir().expect("could not create temp dir");
+        let file = dir.path().join("inventory/node/node.tar.gz");
+
+        ensure_containing_dir_exists(&file).expect("could not create containing directory");
+        assert!(file.parent().unwrap().is_dir());
+        assert!(!file.exists());
+
+        // a file in the way of an ancestor is reported, rather than a missing file later
+        let blocked = dir.path().join("file").join("platform.json");
+        fs::write(dir.path().join("file"), "").unwrap();
+        assert!(ensure_containing_dir_exists(&blocked).is_err());
+    }
+
+    #[test]
+    fn test_remove_dir_all_if_exists() {
+        let dir = tempdir().expect("could not create temp dir");
+        let image_dir = dir.path().join("image");
+        fs::create_dir_all(image_dir.join("bin")).unwrap();
+        fs::write(image_dir.join("bin").join("node"), "").unwrap();
+
+        remove_dir_all_if_exists(&image_dir).expect("could not remove directory");
+        assert!(!image_dir.exists());
+
+        // removing a missing directory is a no-op
+        remove_dir_all_if_exists(&image_dir).expect("could not remove missing directory");
+    }
+}
@@ -1,6 +1,9 @@
 //! Provides types for working with Notion hooks.
 
+use std::env;
+use std::fs::read_to_string;
 use std::marker::PhantomData;
+use std::path::Path;
 use std::str::FromStr;
 
 use lazycell::LazyCell;
@@ -10,8 +13,10 @@ use crate::distro::node::NodeDistro;
 use crate::distro::package::PackageDistro;
 use crate::distro::yarn::YarnDistro;
 use crate::distro::Distro;
+use crate::error::ErrorDetails;
 use crate::fs::touch;
-use crate::path::user_hooks_file;
+use crate::path::{find_project_hooks_file, user_hooks_file};
+use crate::tool::ToolSpec;
 use notion_fail::{Fallible, NotionError, ResultExt};
 use readext::ReadExt;
 
@@ -51,6 +56,9 @@ impl LazyHookConfig {
 pub struct HookConfig {
     pub node: Option<ToolHooks<NodeDistro>>,
     pub yarn: Option<ToolHooks<YarnDistro>>,
+    /// Npm is fetched as a package, but has its own hooks so that it can come
+    /// from a different mirror than other packages.
+    pub npm: Option<ToolHooks<PackageDistro>>,
     pub package: Option<ToolHooks<PackageDistro>>,
     pub events: Option<EventHooks>,
 }
@@ -67,12 +75,95 @@ pub struct ToolHooks<D: Distro> {
     pub phantom: PhantomData<D>,
 }
 
+impl<D: Distro> ToolHooks<D> {
+    /// Combines two sets of tool hooks, preferring the hooks in `self`
+    /// wherever both are configured.
+    fn merge(self, other: Self) -> Self {
+        ToolHooks {
+            distro: self.distro.or(other.distro),
+            latest: self.latest.or(other.latest),
+            index: self.index.or(other.index),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Combines two optional sets of hooks, preferring `first` where both exist.
+fn merge_hooks<D: Distro>(
+    first: Option<ToolHooks<D>>,
+    second: Option<ToolHooks<D>>,
+) -> Option<ToolHooks<D>> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(first.merge(second)),
+        (first, second) => first.or(second),
+    }
+}
+
 impl HookConfig {
-    /// Returns the current hooks, loaded from the filesystem.
+    /// Returns the current hooks, loaded from the filesystem. Hooks from the
+    /// current project, if it has any, take precedence over the user-level
+    /// `hooks.toml`.
     fn current() -> Fallible<Self> {
         let path = user_hooks_file()?;
         let src = touch(&path)?.read_into_string().unknown()?;
-        src.parse()
+        let user: HookConfig = src.parse()?;
+
+        match Self::for_project()? {
+            Some(project) => Ok(project.merge(user)),
+            None => Ok(user),
+        }
+    }
+
+    /// Returns the hooks of the current project, from the nearest `hooks.toml` found
+    /// by walking up from the current directory to the package root, if there is one.
+    fn for_project() -> Fallible<Option<Self>> {
+        let current_dir = env::current_dir().unknown()?;
+        match find_project_hooks_file(&current_dir)? {
+            Some(project_path) => Self::from_file(&project_path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads the hooks from the given file, naming the file in the error if
+    /// it cannot be parsed.
+    fn from_file(path: &Path) -> Fallible<Self> {
+        let src = read_to_string(path).unknown()?;
+        src.parse::<Self>()
+            .with_context(|_| ErrorDetails::InvalidHookCommand {
+                command: path.to_string_lossy().to_string(),
+            })
+    }
+
+    /// Combines two hook configurations, preferring the hooks in `self`
+    /// wherever both are configured.
+    fn merge(self, other: Self) -> Self {
+        HookConfig {
+            node: merge_hooks(self.node, other.node),
+            yarn: merge_hooks(self.yarn, other.yarn),
+            npm: merge_hooks(self.npm, other.npm),
+            package: merge_hooks(self.package, other.package),
+            events: match (self.events, other.events) {
+                (Some(first), Some(second)) => Some(EventHooks {
+                    publish: first.publish.or(second.publish),
+                }),
+                (first, second) => first.or(second),
+            },
+        }
+    }
+
+    /// Returns the configured distro hook for the given tool, or `None` if
+    /// the tool should be fetched from its default public URL. Each tool is
+    /// configured in its own section, so npm doesn't use the package hooks.
+    pub fn for_tool(&self, tool: &ToolSpec) -> Option<&tool::DistroHook> {
+        match tool {
+            ToolSpec::Node(_) => self.node.as_ref().and_then(|hooks| hooks.distro.as_ref()),
+            ToolSpec::Yarn(_) => self.yarn.as_ref().and_then(|hooks| hooks.distro.as_ref()),
+            ToolSpec::Npm(_) => self.npm.as_ref().and_then(|hooks| hooks.distro.as_ref()),
+            ToolSpec::Package(..) => self
+                .package
+                .as_ref()
+                .and_then(|hooks| hooks.distro.as_ref()),
+        }
     }
 }
 
@@ -95,6 +186,9 @@ pub struct EventHooks {
 pub mod tests {
 
     use super::{tool, HookConfig, Publish};
+    use crate::error::ErrorDetails;
+    use crate::tool::ToolSpec;
+    use crate::version::VersionSpec;
     use std::fs;
     use std::path::PathBuf;
 
@@ -221,6 +315,53 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_from_str_local() {
+        let fixture_dir = fixture_path("hooks");
+        let mut local_file = fixture_dir.clone();
+
+        local_file.push("local.toml");
+        let hooks: HookConfig = fs::read_to_string(local_file)
+            .expect("Chould not read local.toml")
+            .parse()
+            .expect("Could not parse local.toml");
+
+        let node = hooks.node.unwrap();
+        let yarn = hooks.yarn.unwrap();
+        let package = hooks.package.unwrap();
+        assert_eq!(
+            node.distro,
+            Some(tool::DistroHook::Local("/mnt/tools/node/".to_string()))
+        );
+        assert_eq!(
+            node.index,
+            Some(tool::MetadataHook::Local("/mnt/tools/node/".to_string()))
+        );
+        assert_eq!(
+            yarn.distro,
+            Some(tool::DistroHook::Local(
+                "file:///mnt/tools/yarn/".to_string()
+            ))
+        );
+        assert_eq!(
+            package.distro,
+            Some(tool::DistroHook::Local("/mnt/tools/packages/".to_string()))
+        );
+        assert_eq!(
+            package.index,
+            Some(tool::MetadataHook::Local(
+                "/mnt/tools/packages/".to_string()
+            ))
+        );
+
+        // a local directory can't be combined with another source
+        assert!(
+            "[node.index]\nlocal = \"/mnt/tools/node/\"\nprefix = \"http://localhost/\""
+                .parse::<HookConfig>()
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_from_str_templates() {
         let fixture_dir = fixture_path("hooks");
@@ -271,4 +412,123 @@ pub mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_for_tool() {
+        let fixture_dir = fixture_path("hooks");
+        let mut url_file = fixture_dir.clone();
+
+        url_file.push("bins.toml");
+        let hooks: HookConfig = fs::read_to_string(url_file)
+            .expect("Could not read bins.toml")
+            .parse()
+            .expect("Could not parse bins.toml");
+
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Node(VersionSpec::Latest)),
+            Some(&tool::DistroHook::Bin(
+                "/some/bin/for/node/distro".to_string()
+            ))
+        );
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Yarn(VersionSpec::Latest)),
+            Some(&tool::DistroHook::Bin("/bin/to/yarn/distro".to_string()))
+        );
+        assert_eq!(hooks.for_tool(&ToolSpec::Npm(VersionSpec::Latest)), None);
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Package(
+                "typescript".to_string(),
+                VersionSpec::Latest
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn test_for_tool_separate_mirrors() {
+        let fixture_dir = fixture_path("hooks");
+        let mut mirrors_file = fixture_dir.clone();
+
+        mirrors_file.push("mirrors.toml");
+        let hooks: HookConfig = fs::read_to_string(mirrors_file)
+            .expect("Could not read mirrors.toml")
+            .parse()
+            .expect("Could not parse mirrors.toml");
+
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Node(VersionSpec::Latest)),
+            Some(&tool::DistroHook::Prefix(
+                "http://node.mirror/dist/".to_string()
+            ))
+        );
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Yarn(VersionSpec::Latest)),
+            Some(&tool::DistroHook::Prefix(
+                "http://yarn.mirror/dist/".to_string()
+            ))
+        );
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Npm(VersionSpec::Latest)),
+            Some(&tool::DistroHook::Template(
+                "http://npm.mirror/npm/-/npm-{{version}}.tgz".to_string()
+            ))
+        );
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Package(
+                "typescript".to_string(),
+                VersionSpec::Latest
+            )),
+            Some(&tool::DistroHook::Prefix(
+                "http://packages.mirror/".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_merge_prefers_project_hooks() {
+        let fixture_dir = fixture_path("hooks");
+        let mut prefixes_file = fixture_dir.clone();
+        let mut bins_file = fixture_dir.clone();
+
+        prefixes_file.push("prefixes.toml");
+        bins_file.push("bins.toml");
+        let project: HookConfig = fs::read_to_string(prefixes_file)
+            .expect("Could not read prefixes.toml")
+            .parse()
+            .expect("Could not parse prefixes.toml");
+        let user: HookConfig = fs::read_to_string(bins_file)
+            .expect("Could not read bins.toml")
+            .parse()
+            .expect("Could not parse bins.toml");
+
+        let hooks = project.merge(user);
+        assert_eq!(
+            hooks.for_tool(&ToolSpec::Node(VersionSpec::Latest)),
+            Some(&tool::DistroHook::Prefix(
+                "http://localhost/node/distro/".to_string()
+            ))
+        );
+        // prefixes.toml has no event hooks, so the user-level hook is kept
+        assert_eq!(
+            hooks.events.unwrap().publish,
+            Some(Publish::Bin("/events/bin".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_from_file_malformed() {
+        let fixture_dir = fixture_path("hooks");
+        let mut malformed_file = fixture_dir.clone();
+
+        malformed_file.push("malformed.toml");
+        match HookConfig::from_file(&malformed_file) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::InvalidHookCommand { command }) => {
+                    assert_eq!(command, &malformed_file.to_string_lossy().to_string())
+                }
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("parsed a malformed hooks file"),
+        }
+    }
 }
@@ -14,6 +14,7 @@ pub struct ResolveHook {
     prefix: Option<String>,
     template: Option<String>,
     bin: Option<String>,
+    local: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -23,40 +24,57 @@ pub struct PublishHook {
 }
 
 #[derive(Fail, Debug)]
-#[fail(display = "Hook contains more than one of 'prefix', 'template', or 'bin' fields")]
+#[fail(display = "Hook contains more than one of 'prefix', 'template', 'bin', or 'local' fields")]
 struct MultipleFieldsSpecified;
 
 #[derive(Fail, Debug)]
-#[fail(display = "Hook must contain either a 'prefix', 'template', or 'bin' field")]
+#[fail(display = "Hook must contain either a 'prefix', 'template', 'bin', or 'local' field")]
 struct NoFieldSpecified;
 
 impl ResolveHook {
-    fn into_hook<H, P, T, B>(self, to_prefix: P, to_template: T, to_bin: B) -> Fallible<H>
+    fn into_hook<H, P, T, B, L>(
+        self,
+        to_prefix: P,
+        to_template: T,
+        to_bin: B,
+        to_local: L,
+    ) -> Fallible<H>
     where
         P: FnOnce(String) -> H,
         T: FnOnce(String) -> H,
         B: FnOnce(String) -> H,
+        L: FnOnce(String) -> H,
     {
         match self {
             ResolveHook {
                 prefix: Some(prefix),
                 template: None,
                 bin: None,
+                local: None,
             } => Ok(to_prefix(prefix)),
             ResolveHook {
                 prefix: None,
                 template: Some(template),
                 bin: None,
+                local: None,
             } => Ok(to_template(template)),
             ResolveHook {
                 prefix: None,
                 template: None,
                 bin: Some(bin),
+                local: None,
             } => Ok(to_bin(bin)),
             ResolveHook {
                 prefix: None,
                 template: None,
                 bin: None,
+                local: Some(local),
+            } => Ok(to_local(local)),
+            ResolveHook {
+                prefix: None,
+                template: None,
+                bin: None,
+                local: None,
             } => Err(NoFieldSpecified.unknown()),
             _ => Err(MultipleFieldsSpecified.unknown()),
         }
@@ -67,6 +85,7 @@ impl ResolveHook {
             tool::DistroHook::Prefix,
             tool::DistroHook::Template,
             tool::DistroHook::Bin,
+            tool::DistroHook::Local,
         )
     }
 
@@ -75,6 +94,7 @@ impl ResolveHook {
             tool::MetadataHook::Prefix,
             tool::MetadataHook::Template,
             tool::MetadataHook::Bin,
+            tool::MetadataHook::Local,
         )
     }
 }
@@ -111,6 +131,7 @@ impl PublishHook {
 pub struct HookConfig {
     pub node: Option<ToolHooks<NodeDistro>>,
     pub yarn: Option<ToolHooks<YarnDistro>>,
+    pub npm: Option<ToolHooks<PackageDistro>>,
     pub packages: Option<ToolHooks<PackageDistro>>,
     pub events: Option<EventHooks>,
 }
@@ -148,11 +169,13 @@ impl HookConfig {
     pub fn into_hook_config(self) -> Fallible<super::HookConfig> {
         let node = self.node.map(|n| n.into_tool_hooks()).transpose()?;
         let yarn = self.yarn.map(|y| y.into_tool_hooks()).transpose()?;
+        let npm = self.npm.map(|n| n.into_tool_hooks()).transpose()?;
         let package = self.packages.map(|p| p.into_tool_hooks()).transpose()?;
         let events = self.events.map(|e| e.into_event_hooks()).transpose()?;
         Ok(super::HookConfig {
             node,
             yarn,
+            npm,
             package,
             events,
         })
@@ -1,11 +1,13 @@
 //! Types representing Notion Tool Hooks.
 
+use std::env;
 use std::ffi::OsString;
-use std::io::Read;
+use std::path::Path;
 use std::process::{Command, Stdio};
 
+use crate::distro::{encode_url_component, FILE_URL_PREFIX};
 use crate::error::ErrorDetails;
-use crate::path::{ARCH, OS};
+use crate::path::{arch, OS};
 use cmdline_words_parser::StrExt;
 use notion_fail::{throw, Fallible, ResultExt};
 use semver::Version;
@@ -20,19 +22,43 @@ pub enum DistroHook {
     Prefix(String),
     Template(String),
     Bin(String),
+    /// A directory of pre-downloaded archives, given as a path or a `file://` URL,
+    /// which are copied from disk instead of downloaded.
+    Local(String),
 }
 
 impl DistroHook {
     /// Performs resolution of the distro URL based on the given
     /// version and file name
     pub fn resolve(&self, version: &Version, filename: &str) -> Fallible<String> {
+        self.resolve_for_arch(version, filename, arch()?)
+    }
+
+    /// Like `resolve`, but fills the architecture of a template with `arch` instead of
+    /// `path::arch()`, for a Node build chosen for another architecture.
+    pub fn resolve_for_arch(
+        &self,
+        version: &Version,
+        filename: &str,
+        arch: &str,
+    ) -> Fallible<String> {
         match self {
-            &DistroHook::Prefix(ref prefix) => Ok(format!("{}{}", prefix, filename)),
-            &DistroHook::Template(ref template) => Ok(template
-                .replace(ARCH_TEMPLATE, ARCH)
+            &DistroHook::Prefix(ref prefix) => Ok(format!(
+                "{}{}",
+                expand_env_vars(prefix)?,
+                encode_url_component(filename)
+            )),
+            &DistroHook::Template(ref template) => Ok(expand_env_vars(template)?
+                .replace(ARCH_TEMPLATE, arch)
                 .replace(OS_TEMPLATE, OS)
-                .replace(VERSION_TEMPLATE, &version.to_string())),
-            &DistroHook::Bin(ref bin) => execute_binary(bin, Some(version.to_string())),
+                .replace(
+                    VERSION_TEMPLATE,
+                    &encode_url_component(&version.to_string()),
+                )),
+            &DistroHook::Bin(ref bin) => {
+                execute_binary(&expand_env_vars(bin)?, Some(version.to_string()))
+            }
+            &DistroHook::Local(ref dir) => local_file_url(dir, filename),
         }
     }
 }
@@ -43,21 +69,79 @@ pub enum MetadataHook {
     Prefix(String),
     Template(String),
     Bin(String),
+    /// A directory of index files, given as a path or a `file://` URL, which are read
+    /// from disk instead of fetched.
+    Local(String),
 }
 
 impl MetadataHook {
     /// Performs resolution of the metadata URL based on the given default file name
     pub fn resolve(&self, filename: &str) -> Fallible<String> {
         match self {
-            &MetadataHook::Prefix(ref prefix) => Ok(format!("{}{}", prefix, filename)),
-            &MetadataHook::Template(ref template) => Ok(template
-                .replace(ARCH_TEMPLATE, ARCH)
+            &MetadataHook::Prefix(ref prefix) => {
+                Ok(format!("{}{}", expand_env_vars(prefix)?, filename))
+            }
+            &MetadataHook::Template(ref template) => Ok(expand_env_vars(template)?
+                .replace(ARCH_TEMPLATE, arch()?)
                 .replace(OS_TEMPLATE, OS)),
-            &MetadataHook::Bin(ref bin) => execute_binary(bin, None),
+            &MetadataHook::Bin(ref bin) => execute_binary(&expand_env_vars(bin)?, None),
+            &MetadataHook::Local(ref dir) => local_file_url(dir, filename),
         }
     }
 }
 
+/// Produces the `file://` URL of a file in the directory of a `local` hook, which is
+/// given as a path or a `file://` URL.
+fn local_file_url(dir: &str, filename: &str) -> Fallible<String> {
+    let dir = expand_env_vars(dir)?;
+    let dir = dir.trim_start_matches(FILE_URL_PREFIX);
+    Ok(format!(
+        "{}{}",
+        FILE_URL_PREFIX,
+        Path::new(dir).join(filename).display()
+    ))
+}
+
+/// Expands the `${VAR}` references in a hook with the values of those variables in
+/// the environment, so that e.g. hostnames can be kept out of `hooks.toml`.
+fn expand_env_vars(hook: &str) -> Fallible<String> {
+    expand_vars(hook, |name| env::var(name).ok())
+}
+
+/// Expands the `${VAR}` references in a hook with the values produced by `lookup`.
+/// A `$$` produces a literal `$`. A reference to a variable that isn't set, or one
+/// that isn't closed, makes the hook invalid.
+fn expand_vars<F>(hook: &str, lookup: F) -> Fallible<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let invalid = || ErrorDetails::InvalidHookCommand {
+        command: hook.to_string(),
+    };
+
+    let mut expanded = String::with_capacity(hook.len());
+    let mut rest = hook;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        if rest.starts_with('$') {
+            expanded.push('$');
+            rest = &rest[1..];
+        } else if rest.starts_with('{') {
+            let end = rest.find('}').ok_or_else(invalid)?;
+            let value = lookup(&rest[1..end]).ok_or_else(invalid)?;
+            expanded.push_str(&value);
+            rest = &rest[end + 1..];
+        } else {
+            expanded.push('$');
+        }
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
 /// Execute a shell command and return the trimmed stdout from that command
 fn execute_binary(bin: &str, extra_arg: Option<String>) -> Fallible<String> {
     let mut trimmed = bin.trim().to_string();
@@ -75,24 +159,32 @@ fn execute_binary(bin: &str, extra_arg: Option<String>) -> Fallible<String> {
         args.push(OsString::from(arg));
     }
 
-    let child = Command::new(cmd)
+    let output = Command::new(cmd)
         .args(&args)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()
+        .output()
         .unknown()?;
 
-    let mut url = String::new();
-    child.stdout.unwrap().read_to_string(&mut url).unknown()?;
+    if !output.status.success() {
+        throw!(ErrorDetails::HookExecutionFailed {
+            command: String::from(bin.trim()),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    let url = String::from_utf8(output.stdout).unknown()?;
     Ok(url.trim().to_string())
 }
 
 #[cfg(test)]
 pub mod tests {
-    use super::{DistroHook, MetadataHook};
+    use super::{expand_vars, DistroHook, MetadataHook};
     use crate::path::{ARCH, OS};
     use semver::Version;
+    use std::path::Path;
 
     #[test]
     fn test_distro_prefix_resolve() {
@@ -108,6 +200,19 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_distro_template_resolve_prerelease_build() {
+        let hook =
+            DistroHook::Template("http://localhost/node/{{version}}/node.tar.gz".to_string());
+        let version = Version::parse("1.0.0-rc.1+build").unwrap();
+
+        assert_eq!(
+            hook.resolve(&version, "node.tar.gz")
+                .expect("Could not resolve URL"),
+            "http://localhost/node/1.0.0-rc.1%2Bbuild/node.tar.gz"
+        );
+    }
+
     #[test]
     fn test_distro_template_resolve() {
         let hook = DistroHook::Template(
@@ -128,6 +233,38 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_distro_template_resolve_for_arch() {
+        let hook = DistroHook::Template(
+            "http://localhost/node/{{os}}/{{arch}}/{{version}}/node.tar.gz".to_string(),
+        );
+        let version = Version::new(1, 0, 0);
+
+        assert_eq!(
+            hook.resolve_for_arch(&version, "node.tar.gz", "x64")
+                .expect("Could not resolve URL"),
+            format!("http://localhost/node/{}/x64/1.0.0/node.tar.gz", OS)
+        );
+    }
+
+    #[test]
+    fn test_distro_local_resolve() {
+        let version = Version::new(1, 0, 0);
+        let expected = format!(
+            "file://{}",
+            Path::new("/mnt/tools/node").join("node.tar.gz").display()
+        );
+
+        for dir in &["/mnt/tools/node", "file:///mnt/tools/node"] {
+            let hook = DistroHook::Local(dir.to_string());
+            assert_eq!(
+                hook.resolve(&version, "node.tar.gz")
+                    .expect("Could not resolve path"),
+                expected
+            );
+        }
+    }
+
     #[test]
     fn test_metadata_prefix_resolve() {
         let prefix = "http://localhost/node/index/";
@@ -151,4 +288,61 @@ pub mod tests {
             expected
         );
     }
+
+    #[test]
+    fn test_metadata_local_resolve() {
+        let hook = MetadataHook::Local("file:///mnt/tools/node".to_string());
+        let expected = format!(
+            "file://{}",
+            Path::new("/mnt/tools/node").join("index.json").display()
+        );
+
+        assert_eq!(
+            hook.resolve("index.json").expect("Could not resolve path"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let lookup = |name: &str| match name {
+            "CORP_MIRROR_HOST" => Some("mirror.corp".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            expand_vars("https://${CORP_MIRROR_HOST}/node/", lookup)
+                .expect("Could not expand hook"),
+            "https://mirror.corp/node/"
+        );
+        assert_eq!(
+            expand_vars("http://localhost/{{version}}/", lookup).expect("Could not expand hook"),
+            "http://localhost/{{version}}/"
+        );
+        assert!(expand_vars("https://${UNSET_MIRROR_HOST}/node/", lookup).is_err());
+        assert!(expand_vars("https://${CORP_MIRROR_HOST/node/", lookup).is_err());
+    }
+
+    #[test]
+    fn test_expand_vars_escaped() {
+        let lookup = |_: &str| None;
+
+        assert_eq!(
+            expand_vars("/bin/fetch --price $$5 --var $${NAME}", lookup)
+                .expect("Could not expand hook"),
+            "/bin/fetch --price $5 --var ${NAME}"
+        );
+        assert_eq!(
+            expand_vars("/bin/fetch $HOME", lookup).expect("Could not expand hook"),
+            "/bin/fetch $HOME"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_metadata_bin_failure() {
+        let hook = MetadataHook::Bin("false".to_string());
+
+        assert!(hook.resolve("index.json").is_err());
+    }
 }
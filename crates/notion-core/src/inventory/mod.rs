@@ -1,10 +1,13 @@
 //! Provides types for working with Notion's _inventory_, the local repository
 //! of available tool versions.
 
-use std::collections::{BTreeSet, HashSet};
-use std::fs::File;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{read_to_string, File};
 use std::io::Write;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::rc::Rc;
 use std::str::FromStr;
 use std::string::ToString;
 use std::time::{Duration, SystemTime};
@@ -12,25 +15,34 @@ use std::time::{Duration, SystemTime};
 use headers_011::Headers011;
 use lazycell::LazyCell;
 use reqwest;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use reqwest::hyper_011::header::{CacheControl, CacheDirective, Expires, HttpDate};
+use reqwest::StatusCode;
 use serde_json;
 use tempfile::NamedTempFile;
 
-use crate::distro::node::{NodeDistro, NodeVersion};
+use crate::config::config;
+use crate::distro::node::{self, NodeBuild, NodeDistro, NodeVersion};
 use crate::distro::package::{PackageDistro, PackageEntry, PackageIndex, PackageVersion};
-use crate::distro::yarn::YarnDistro;
-use crate::distro::{Distro, Fetched};
+use crate::distro::yarn::{self, YarnDistro};
+use crate::distro::{
+    http_client, is_timeout_http, local_file_path, network_timeout_error, Distro, Fetched,
+};
 use crate::error::ErrorDetails;
 use crate::fs::{ensure_containing_dir_exists, read_file_opt};
 use crate::hook::ToolHooks;
+use crate::npmrc::{self, Npmrc};
 use crate::path;
 use crate::style::progress_spinner;
-use crate::version::VersionSpec;
-use notion_fail::{throw, Fallible, ResultExt};
+use crate::version::{self, sort_desc, VersionSpec};
+use notion_fail::{throw, ExitCode, Fallible, ResultExt};
 use semver::{Version, VersionReq};
 
+mod prune;
 pub(crate) mod serial;
 
+pub use self::prune::{prune, prune_packages, prune_yarn};
+
 #[cfg(feature = "mock-network")]
 use mockito;
 
@@ -127,6 +139,13 @@ impl<D: Distro> Collection<D> {
     pub fn contains(&self, version: &Version) -> bool {
         self.versions.contains(version)
     }
+
+    /// Lists the Tool versions in this Collection, from newest to oldest.
+    pub fn list(&self) -> Vec<Version> {
+        let mut versions: Vec<Version> = self.versions.iter().cloned().collect();
+        sort_desc(&mut versions);
+        versions
+    }
 }
 
 pub trait FetchResolve<D: Distro> {
@@ -140,6 +159,20 @@ pub trait FetchResolve<D: Distro> {
         hooks: Option<&ToolHooks<D>>,
     ) -> Fallible<Fetched<Self::FetchedVersion>>;
 
+    /// Downloads the archive for a Distro version matching the specified semantic
+    /// versioning requirements into the inventory, without unpacking it, and returns
+    /// the resolved version.
+    fn fetch_archive(
+        &mut self,
+        name: String,
+        matching: &VersionSpec,
+        hooks: Option<&ToolHooks<D>>,
+    ) -> Fallible<Version> {
+        let distro = self.resolve(name, matching, hooks)?;
+        distro.cache()?;
+        Ok(distro.version().clone())
+    }
+
     /// Resolves the specified semantic versioning requirements into a distribution
     fn resolve(
         &self,
@@ -148,13 +181,17 @@ pub trait FetchResolve<D: Distro> {
         hooks: Option<&ToolHooks<D>>,
     ) -> Fallible<D> {
         let version = match *matching {
+            // the command should have decided what an unspecified version means
+            VersionSpec::None => throw!(ErrorDetails::VersionNotSpecified { tool: name }),
             VersionSpec::Latest => self.resolve_latest(name.clone(), hooks)?,
+            VersionSpec::Lts => self.resolve_lts(name.clone(), hooks)?,
             VersionSpec::Semver(ref requirement) => {
                 self.resolve_semver(name.clone(), requirement, hooks)?
             }
             VersionSpec::Exact(ref version) => {
                 self.resolve_exact(name.clone(), version.clone(), hooks)?
             }
+            VersionSpec::Tag(ref tag) => self.resolve_tag(name.clone(), tag, hooks)?,
         };
 
         D::new(name, version, hooks)
@@ -167,6 +204,16 @@ pub trait FetchResolve<D: Distro> {
         hooks: Option<&ToolHooks<D>>,
     ) -> Fallible<D::ResolvedVersion>;
 
+    /// Resolves the latest LTS version for this tool. Tools that don't distinguish LTS
+    /// releases resolve to their latest version.
+    fn resolve_lts(
+        &self,
+        name: String,
+        hooks: Option<&ToolHooks<D>>,
+    ) -> Fallible<D::ResolvedVersion> {
+        self.resolve_latest(name, hooks)
+    }
+
     /// Resolves a SemVer version for this tool, using either the `index` hook or the public registry
     fn resolve_semver(
         &self,
@@ -182,23 +229,188 @@ pub trait FetchResolve<D: Distro> {
         version: Version,
         hooks: Option<&ToolHooks<D>>,
     ) -> Fallible<D::ResolvedVersion>;
+
+    /// Resolves the version a dist-tag points to. Only packages published to the npm
+    /// registry have dist-tags, so by default no version matches.
+    fn resolve_tag(
+        &self,
+        name: String,
+        tag: &str,
+        _hooks: Option<&ToolHooks<D>>,
+    ) -> Fallible<D::ResolvedVersion> {
+        throw!(ErrorDetails::NoPackageFound {
+            name,
+            matching: VersionSpec::Tag(tag.to_string()),
+        })
+    }
 }
 
 fn registry_fetch_error(error: &reqwest::Error) -> ErrorDetails {
+    if is_timeout_http(error) {
+        return network_timeout_error();
+    }
+
     ErrorDetails::RegistryFetchError {
         error: error.to_string(),
     }
 }
 
-fn match_node_version(
-    url: &str,
-    predicate: impl Fn(&NodeEntry) -> bool,
-) -> Fallible<Option<Version>> {
-    let index: NodeIndex = resolve_node_versions(url)?.into_index()?;
-    let mut entries = index.entries.into_iter();
-    Ok(entries
-        .find(predicate)
-        .map(|NodeEntry { version, .. }| version))
+/// Sends a GET request for the given URL, subject to the network timeout.
+fn http_get(url: &str) -> Fallible<reqwest::Response> {
+    http_client()?
+        .get(url)
+        .send()
+        .with_context(registry_fetch_error)
+}
+
+/// Reads an index file from disk, for the `file://` URLs that `local` hooks resolve to.
+fn read_local_index(path: &Path) -> Fallible<String> {
+    read_to_string(path).with_context(|error| ErrorDetails::LocalIndexReadError {
+        path: path.display().to_string(),
+        error: error.to_string(),
+    })
+}
+
+/// Reads the body of a registry response, failing if it is an HTML page rather than
+/// JSON. Authenticating proxies often answer with a login page and a 200 status,
+/// which would otherwise surface as a confusing parse error.
+fn registry_response_text(url: &str, response: &mut reqwest::Response) -> Fallible<String> {
+    let text = response.text().unknown()?;
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+
+    if is_html(content_type, &text) {
+        throw!(ErrorDetails::RegistryReturnedHtml {
+            url: url.to_string(),
+        });
+    }
+
+    Ok(text)
+}
+
+/// Whether a response is an HTML page, judging by its `Content-Type` and by sniffing
+/// the body, since a JSON document can never start with `<`.
+fn is_html(content_type: Option<&str>, body: &str) -> bool {
+    let body = body.trim_start();
+    let html_type = content_type
+        .map(|content_type| content_type.to_ascii_lowercase().contains("text/html"))
+        .unwrap_or(false);
+
+    body.starts_with('<') || (html_type && !body.starts_with('{') && !body.starts_with('['))
+}
+
+/// Lazily loaded indexes of available Node versions, one for each index URL.
+pub struct LazyNodeIndex {
+    indexes: RefCell<HashMap<String, Rc<NodeIndex>>>,
+}
+
+impl LazyNodeIndex {
+    /// Constructs a new `LazyNodeIndex`.
+    pub fn new() -> LazyNodeIndex {
+        LazyNodeIndex {
+            indexes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Forces the loading of the index at the specified URL and returns a shared
+    /// reference to it. The first call for a URL reads the index from the local cache
+    /// (or fetches it if the cache has expired or `force_fetch` is set), and later calls
+    /// for the same URL reuse the parsed index.
+    pub fn get(&self, url: &str, force_fetch: bool) -> Fallible<Rc<NodeIndex>> {
+        if let Some(index) = self.indexes.borrow().get(url) {
+            return Ok(index.clone());
+        }
+
+        let index = Rc::new(resolve_node_versions(url, force_fetch)?.into_index()?);
+        self.indexes
+            .borrow_mut()
+            .insert(url.to_string(), index.clone());
+        Ok(index)
+    }
+}
+
+thread_local! {
+    // The Node index only needs to be parsed once per process, however many
+    // tools are resolved against it.
+    static NODE_INDEX: LazyNodeIndex = LazyNodeIndex::new();
+}
+
+fn resolve_node_version(url: &str, matching: &VersionSpec) -> Fallible<NodeBuild> {
+    let force_fetch = config()?.force_fetch();
+    let index = NODE_INDEX.with(|index| index.get(url, force_fetch))?;
+    let (version, _) = node::resolve(matching, &index)?.into_parts();
+    node::choose_build(version, &index)
+}
+
+impl NodeCollection {
+    /// Produces the details of an exact Node version that is already in the inventory,
+    /// without provisioning a distro. The bundled npm version is read from the local
+    /// npm version file, so the Node index is only consulted (and the npm version file
+    /// repaired) if that file is missing. Returns `None` if the version isn't cached
+    /// and unpacked.
+    fn fetch_cached(
+        &self,
+        version: &Version,
+        hooks: Option<&ToolHooks<NodeDistro>>,
+    ) -> Fallible<Option<Fetched<NodeVersion>>> {
+        if !self.contains(version) || !node::image_exists(version)? {
+            return Ok(None);
+        }
+
+        if path::node_npm_version_file(&version.to_string())?.is_file() {
+            let npm = node::load_default_npm_version(version)?;
+            return Ok(Some(Fetched::Already(NodeVersion::new(
+                version.clone(),
+                npm,
+            ))));
+        }
+
+        let url = match hooks {
+            Some(&ToolHooks {
+                index: Some(ref hook),
+                ..
+            }) => hook.resolve("index.json")?,
+            _ => public_node_version_index(),
+        };
+        let force_fetch = config()?.force_fetch();
+        let index = NODE_INDEX.with(|index| index.get(&url, force_fetch))?;
+        let node_version = node::resolve(&VersionSpec::Exact(version.clone()), &index)?;
+        node::save_default_npm_version(node_version.runtime(), node_version.npm())?;
+        Ok(Some(Fetched::Already(node_version)))
+    }
+
+    /// Resolves the Node version matching the specified requirements, along with the
+    /// npm version it bundles, from the Node index without fetching the distro. An
+    /// exact version that is already in the inventory is resolved without the index.
+    pub(crate) fn resolve_version(
+        &self,
+        matching: &VersionSpec,
+        hooks: Option<&ToolHooks<NodeDistro>>,
+    ) -> Fallible<NodeVersion> {
+        if let VersionSpec::Exact(ref version) = *matching {
+            if self.contains(version)
+                && path::node_npm_version_file(&version.to_string())?.is_file()
+            {
+                let npm = node::load_default_npm_version(version)?;
+                return Ok(NodeVersion::new(version.clone(), npm));
+            }
+        }
+
+        let url = match hooks {
+            Some(&ToolHooks {
+                index: Some(ref hook),
+                ..
+            }) => hook.resolve("index.json")?,
+            _ => public_node_version_index(),
+        };
+        let force_fetch = config()?.force_fetch();
+        let index = NODE_INDEX.with(|index| index.get(&url, force_fetch))?;
+        let node_version = node::resolve(matching, &index)?;
+        node::choose_build(node_version.runtime().clone(), &index)?;
+        Ok(node_version)
+    }
 }
 
 impl FetchResolve<NodeDistro> for NodeCollection {
@@ -210,15 +422,19 @@ impl FetchResolve<NodeDistro> for NodeCollection {
         matching: &VersionSpec,
         hooks: Option<&ToolHooks<NodeDistro>>,
     ) -> Fallible<Fetched<NodeVersion>> {
+        // Exact versions that are already inventoried can be used without
+        // provisioning the distro or downloading the Node index.
+        if let VersionSpec::Exact(ref version) = *matching {
+            if let Some(fetched) = self.fetch_cached(version, hooks)? {
+                return Ok(fetched);
+            }
+        }
+
         let distro = self.resolve(name, matching, hooks)?;
-        let fetched = distro.fetch(&self).unknown()?;
+        let fetched = distro.ensure_fetched(&self).unknown()?;
 
-        if let &Fetched::Now(NodeVersion {
-            runtime: ref version,
-            ..
-        }) = &fetched
-        {
-            self.versions.insert(version.clone());
+        if let Fetched::Now(ref node_version) = fetched {
+            self.versions.insert(node_version.runtime().clone());
         }
 
         Ok(fetched)
@@ -228,7 +444,7 @@ impl FetchResolve<NodeDistro> for NodeCollection {
         &self,
         _name: String,
         hooks: Option<&ToolHooks<NodeDistro>>,
-    ) -> Fallible<Version> {
+    ) -> Fallible<NodeBuild> {
         // NOTE: This assumes the registry always produces a list in sorted order
         //       from newest to oldest. This should be specified as a requirement
         //       when we document the plugin API.
@@ -239,15 +455,22 @@ impl FetchResolve<NodeDistro> for NodeCollection {
             }) => hook.resolve("index.json")?,
             _ => public_node_version_index(),
         };
-        let version_opt = match_node_version(&url, |_| true)?;
+        resolve_node_version(&url, &VersionSpec::Latest)
+    }
 
-        if let Some(version) = version_opt {
-            Ok(version)
-        } else {
-            throw!(ErrorDetails::NodeVersionNotFound {
-                matching: "latest".to_string()
-            })
-        }
+    fn resolve_lts(
+        &self,
+        _name: String,
+        hooks: Option<&ToolHooks<NodeDistro>>,
+    ) -> Fallible<NodeBuild> {
+        let url = match hooks {
+            Some(&ToolHooks {
+                index: Some(ref hook),
+                ..
+            }) => hook.resolve("index.json")?,
+            _ => public_node_version_index(),
+        };
+        resolve_node_version(&url, &VersionSpec::Lts)
     }
 
     fn resolve_semver(
@@ -255,7 +478,7 @@ impl FetchResolve<NodeDistro> for NodeCollection {
         _name: String,
         matching: &VersionReq,
         hooks: Option<&ToolHooks<NodeDistro>>,
-    ) -> Fallible<Version> {
+    ) -> Fallible<NodeBuild> {
         // ISSUE #34: also make sure this OS is available for this version
         let url = match hooks {
             Some(&ToolHooks {
@@ -264,26 +487,34 @@ impl FetchResolve<NodeDistro> for NodeCollection {
             }) => hook.resolve("index.json")?,
             _ => public_node_version_index(),
         };
-        let version_opt = match_node_version(&url, |&NodeEntry { version: ref v, .. }| {
-            matching.matches(v)
-        })?;
-
-        if let Some(version) = version_opt {
-            Ok(version)
-        } else {
-            throw!(ErrorDetails::NodeVersionNotFound {
-                matching: matching.to_string()
-            })
-        }
+        resolve_node_version(&url, &VersionSpec::Semver(matching.clone()))
     }
 
     fn resolve_exact(
         &self,
         _name: String,
         version: Version,
-        _hooks: Option<&ToolHooks<NodeDistro>>,
-    ) -> Fallible<Version> {
-        Ok(version.clone())
+        hooks: Option<&ToolHooks<NodeDistro>>,
+    ) -> Fallible<NodeBuild> {
+        // the index is only needed to tell whether to fall back to another
+        // architecture's build
+        if path::fallback_arch()?.is_none() {
+            return Ok(NodeBuild {
+                version,
+                arch: path::arch()?,
+            });
+        }
+
+        let url = match hooks {
+            Some(&ToolHooks {
+                index: Some(ref hook),
+                ..
+            }) => hook.resolve("index.json")?,
+            _ => public_node_version_index(),
+        };
+        let force_fetch = config()?.force_fetch();
+        let index = NODE_INDEX.with(|index| index.get(&url, force_fetch))?;
+        node::choose_build(version, &index)
     }
 }
 
@@ -298,7 +529,7 @@ impl FetchResolve<YarnDistro> for YarnCollection {
         hooks: Option<&ToolHooks<YarnDistro>>,
     ) -> Fallible<Fetched<Self::FetchedVersion>> {
         let distro = self.resolve(name, &matching, hooks)?;
-        let fetched = distro.fetch(&self).unknown()?;
+        let fetched = distro.ensure_fetched(&self).unknown()?;
 
         if let &Fetched::Now(ref version) = &fetched {
             self.versions.insert(version.clone());
@@ -319,9 +550,11 @@ impl FetchResolve<YarnDistro> for YarnCollection {
             }) => hook.resolve("latest-version")?,
             _ => public_yarn_latest_version(),
         };
-        let mut response: reqwest::Response =
-            reqwest::get(&url).with_context(registry_fetch_error)?;
-        Version::parse(&response.text().unknown()?).unknown()
+        let text = match local_file_path(&url) {
+            Some(path) => read_local_index(&path)?,
+            None => http_get(&url)?.text().unknown()?,
+        };
+        Version::parse(text.trim()).unknown()
     }
 
     fn resolve_semver(
@@ -339,21 +572,15 @@ impl FetchResolve<YarnDistro> for YarnCollection {
         };
 
         let spinner = progress_spinner(&format!("Fetching public registry: {}", url));
-        let releases: serial::YarnIndex = reqwest::get(&url)
-            .with_context(registry_fetch_error)?
-            .json()
-            .unknown()?;
-        let releases = releases.into_index()?.entries;
+        let response_text = match local_file_path(&url) {
+            Some(path) => read_local_index(&path)?,
+            None => registry_response_text(&url, &mut http_get(&url)?)?,
+        };
+        let releases: serial::YarnIndex = serde_json::de::from_str(&response_text).unknown()?;
+        let index = releases.into_index()?;
         spinner.finish_and_clear();
-        let version_opt = releases.into_iter().rev().find(|v| matching.matches(v));
 
-        if let Some(version) = version_opt {
-            Ok(version)
-        } else {
-            throw!(ErrorDetails::YarnVersionNotFound {
-                matching: matching.to_string()
-            })
-        }
+        yarn::resolve(&VersionSpec::Semver(matching.clone()), &index)
     }
 
     fn resolve_exact(
@@ -366,6 +593,38 @@ impl FetchResolve<YarnDistro> for YarnCollection {
     }
 }
 
+/// Returns the root URL of the registry to fetch the named package's metadata from:
+/// the registry configured for it in `.npmrc` (the project's, then the user's), or
+/// else the public registry. If `.npmrc` can't be read or doesn't name a valid
+/// registry URL, the public registry is used with a warning.
+pub(crate) fn package_registry_root(name: &str) -> String {
+    if cfg!(feature = "mock-network") {
+        return public_package_registry_root();
+    }
+
+    let npmrc = match Npmrc::current() {
+        Ok(npmrc) => npmrc,
+        Err(_) => {
+            eprintln!("Warning: could not read .npmrc, using the public registry");
+            return public_package_registry_root();
+        }
+    };
+
+    match npmrc.registry(name) {
+        Some(registry) if registry.starts_with("https://") || registry.starts_with("http://") => {
+            registry.trim_end_matches('/').to_string()
+        }
+        Some(registry) => {
+            eprintln!(
+                "Warning: invalid registry '{}' in .npmrc, using the public registry",
+                registry
+            );
+            public_package_registry_root()
+        }
+        None => public_package_registry_root(),
+    }
+}
+
 // use the input predicate to match a package in the index
 fn match_package_entry(
     index: PackageIndex,
@@ -375,12 +634,27 @@ fn match_package_entry(
     entries.find(predicate)
 }
 
-// fetch metadata for the input url
-fn resolve_package_metadata(package_info_url: &str) -> Fallible<serial::PackageMetadata> {
+// fetch metadata for the input url, authenticating with the registry if a token is configured
+fn resolve_package_metadata(
+    package_info_url: &str,
+    registry: &str,
+) -> Fallible<serial::PackageMetadata> {
+    if let Some(path) = local_file_path(package_info_url) {
+        return serde_json::de::from_str(&read_local_index(&path)?).unknown();
+    }
+
     let spinner = progress_spinner(&format!("Fetching package metadata: {}", package_info_url));
-    let mut response: reqwest::Response =
-        reqwest::get(package_info_url).with_context(registry_fetch_error)?;
-    let response_text: String = response.text().unknown()?;
+    let mut request = http_client()?.get(package_info_url);
+    if let Some(authorization) = npmrc::authorization(package_info_url, registry)? {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    let mut response: reqwest::Response = request.send().with_context(registry_fetch_error)?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        throw!(ErrorDetails::RegistryAuthFailed {
+            registry: package_info_url.to_string(),
+        });
+    }
+    let response_text = registry_response_text(package_info_url, &mut response)?;
 
     let metadata: serial::PackageMetadata = serde_json::de::from_str(&response_text).unknown()?;
 
@@ -399,7 +673,7 @@ impl FetchResolve<PackageDistro> for PackageCollection {
         hooks: Option<&ToolHooks<PackageDistro>>,
     ) -> Fallible<Fetched<Self::FetchedVersion>> {
         let distro = self.resolve(name, &matching, hooks)?;
-        let fetched = distro.fetch(&self).unknown()?;
+        let fetched = distro.ensure_fetched(&self).unknown()?;
 
         if let &Fetched::Now(PackageVersion { ref version, .. }) = &fetched {
             self.versions.insert(version.clone());
@@ -413,15 +687,16 @@ impl FetchResolve<PackageDistro> for PackageCollection {
         name: String,
         hooks: Option<&ToolHooks<PackageDistro>>,
     ) -> Fallible<PackageEntry> {
+        let registry = package_registry_root(&name);
         let url = match hooks {
             Some(&ToolHooks {
                 latest: Some(ref hook),
                 ..
             }) => hook.resolve(&name)?,
-            _ => format!("{}/{}", public_package_registry_root(), name),
+            _ => format!("{}/{}", registry, name),
         };
 
-        let package_index = resolve_package_metadata(&url)?.into_index();
+        let package_index = resolve_package_metadata(&url, &registry)?.into_index();
         let latest = package_index.latest.clone();
 
         let entry_opt =
@@ -432,10 +707,7 @@ impl FetchResolve<PackageDistro> for PackageCollection {
         if let Some(entry) = entry_opt {
             Ok(entry)
         } else {
-            throw!(ErrorDetails::NoPackageFound {
-                name: name.to_string(),
-                matching: VersionSpec::Latest,
-            })
+            throw!(package_not_found(&name, VersionSpec::Latest))
         }
     }
 
@@ -445,28 +717,30 @@ impl FetchResolve<PackageDistro> for PackageCollection {
         matching: &VersionReq,
         hooks: Option<&ToolHooks<PackageDistro>>,
     ) -> Fallible<PackageEntry> {
+        let registry = package_registry_root(&name);
         let url = match hooks {
             Some(&ToolHooks {
                 index: Some(ref hook),
                 ..
             }) => hook.resolve(&name)?,
-            _ => format!("{}/{}", public_package_registry_root(), name),
+            _ => format!("{}/{}", registry, name),
         };
 
-        let package_index = resolve_package_metadata(&url)?.into_index();
+        let package_index = resolve_package_metadata(&url, &registry)?.into_index();
 
+        let include_prereleases = version::include_prereleases();
         let entry_opt =
             match_package_entry(package_index, |&PackageEntry { version: ref v, .. }| {
-                matching.matches(v)
+                version::matches(matching, v, include_prereleases)
             });
 
         if let Some(entry) = entry_opt {
             Ok(entry)
         } else {
-            throw!(ErrorDetails::NoPackageFound {
-                name: name.to_string(),
-                matching: VersionSpec::Latest,
-            })
+            throw!(package_not_found(
+                &name,
+                VersionSpec::Semver(matching.clone())
+            ))
         }
     }
 
@@ -476,15 +750,16 @@ impl FetchResolve<PackageDistro> for PackageCollection {
         exact_version: Version,
         hooks: Option<&ToolHooks<PackageDistro>>,
     ) -> Fallible<PackageEntry> {
+        let registry = package_registry_root(&name);
         let url = match hooks {
             Some(&ToolHooks {
                 index: Some(ref hook),
                 ..
             }) => hook.resolve(&name)?,
-            _ => format!("{}/{}", public_package_registry_root(), name),
+            _ => format!("{}/{}", registry, name),
         };
 
-        let package_index = resolve_package_metadata(&url)?.into_index();
+        let package_index = resolve_package_metadata(&url, &registry)?.into_index();
 
         let entry_opt =
             match_package_entry(package_index, |&PackageEntry { version: ref v, .. }| {
@@ -494,17 +769,91 @@ impl FetchResolve<PackageDistro> for PackageCollection {
         if let Some(entry) = entry_opt {
             Ok(entry)
         } else {
-            throw!(ErrorDetails::NoPackageFound {
-                name: name.to_string(),
-                matching: VersionSpec::Latest,
-            })
+            throw!(package_not_found(&name, VersionSpec::Exact(exact_version)))
+        }
+    }
+
+    fn resolve_tag(
+        &self,
+        name: String,
+        tag: &str,
+        hooks: Option<&ToolHooks<PackageDistro>>,
+    ) -> Fallible<PackageEntry> {
+        let registry = package_registry_root(&name);
+        let url = match hooks {
+            Some(&ToolHooks {
+                index: Some(ref hook),
+                ..
+            }) => hook.resolve(&name)?,
+            _ => format!("{}/{}", registry, name),
+        };
+
+        let package_index = resolve_package_metadata(&url, &registry)?.into_index();
+
+        let entry_opt = match package_index.tags.get(tag).cloned() {
+            Some(tagged) => {
+                match_package_entry(package_index, |&PackageEntry { version: ref v, .. }| {
+                    &tagged == v
+                })
+            }
+            None => None,
+        };
+
+        if let Some(entry) = entry_opt {
+            Ok(entry)
+        } else {
+            throw!(package_not_found(&name, VersionSpec::Tag(tag.to_string())))
+        }
+    }
+}
+
+/// Produces the error for a package with no version matching the given spec. Npm is
+/// resolved from the registry like any other package, but it is one of the core
+/// tools, so it gets the same error as Node and Yarn.
+fn package_not_found(name: &str, matching: VersionSpec) -> ErrorDetails {
+    if name == "npm" {
+        ErrorDetails::NpmVersionNotFound {
+            matching: matching.to_string(),
+        }
+    } else {
+        ErrorDetails::NoPackageFound {
+            name: name.to_string(),
+            matching,
         }
     }
 }
 
 /// The index of the public Node server.
+///
+/// NOTE: The lookup methods assume the entries are sorted from newest to oldest,
+///       as they are in the public Node index.
 pub struct NodeIndex {
-    entries: Vec<NodeEntry>,
+    pub entries: Vec<NodeEntry>,
+}
+
+impl NodeIndex {
+    /// Produces the newest entry in the index.
+    pub fn latest(&self) -> Option<&NodeEntry> {
+        self.entries.first()
+    }
+
+    /// Produces the newest entry in the index that belongs to an LTS line.
+    pub fn latest_lts(&self) -> Option<&NodeEntry> {
+        self.entries.iter().find(|entry| entry.lts.is_some())
+    }
+
+    /// Produces the newest entry in the index that satisfies the requirement.
+    pub fn matching(&self, requirement: &VersionReq) -> Option<&NodeEntry> {
+        let include_prereleases = version::include_prereleases();
+        self.entries
+            .iter()
+            .find(|entry| version::matches(requirement, &entry.version, include_prereleases))
+    }
+
+    /// Produces the entry for the exact version, if the index lists it.
+    pub fn exact(&self, version: &Version) -> Option<&NodeEntry> {
+        self.entries.iter().find(|entry| &entry.version == version)
+    }
 }
 
 #[derive(Debug)]
@@ -512,11 +861,22 @@ pub struct NodeEntry {
     pub version: Version,
     pub npm: Version,
     pub files: NodeDistroFiles,
+    /// The codename of the LTS line this release belongs to, if any.
+    pub lts: Option<String>,
+}
+
+impl NodeEntry {
+    /// Checks whether this release includes the named build, e.g. `linux-x64`. Entries
+    /// that don't list any builds (as in some custom indexes) are assumed to have all of
+    /// them.
+    pub fn has_build(&self, build: &str) -> bool {
+        self.files.files.is_empty() || self.files.files.contains(build)
+    }
 }
 
 /// The public Yarn index.
 pub struct YarnIndex {
-    entries: BTreeSet<Version>,
+    pub entries: BTreeSet<Version>,
 }
 
 /// The set of available files on the public Node server for a given Node version.
@@ -525,24 +885,34 @@ pub struct NodeDistroFiles {
     pub files: HashSet<String>,
 }
 
-/// Reads a public index from the Node cache, if it exists and hasn't expired.
-fn read_cached_opt() -> Fallible<Option<serial::NodeIndex>> {
+/// Reads a public index from the Node cache, if it exists and hasn't expired. If
+/// `allow_stale` is set, the cached index is returned even if it has expired.
+fn read_cached_opt(allow_stale: bool) -> Fallible<Option<serial::NodeIndex>> {
+    if !allow_stale && !cache_is_fresh()? {
+        return Ok(None);
+    }
+
+    let cached: Option<String> = read_file_opt(&path::node_index_file()?).unknown()?;
+
+    if let Some(string) = cached {
+        return Ok(serde_json::de::from_str(&string).unknown()?);
+    }
+
+    Ok(None)
+}
+
+/// Checks whether the expiry date recorded for the Node cache is still in the future.
+fn cache_is_fresh() -> Fallible<bool> {
     let expiry: Option<String> = read_file_opt(&path::node_index_expiry_file()?).unknown()?;
 
     if let Some(string) = expiry {
         let expiry_date: HttpDate = HttpDate::from_str(&string).unknown()?;
         let current_date: HttpDate = HttpDate::from(SystemTime::now());
 
-        if current_date < expiry_date {
-            let cached: Option<String> = read_file_opt(&path::node_index_file()?).unknown()?;
-
-            if let Some(string) = cached {
-                return Ok(serde_json::de::from_str(&string).unknown()?);
-            }
-        }
+        return Ok(current_date < expiry_date);
     }
 
-    Ok(None)
+    Ok(false)
 }
 
 /// Get the cache max-age of an HTTP reponse.
@@ -559,50 +929,312 @@ fn max_age(response: &reqwest::Response) -> u32 {
     4 * 60 * 60
 }
 
-fn resolve_node_versions(url: &str) -> Fallible<serial::NodeIndex> {
-    match read_cached_opt()? {
-        Some(serial) => Ok(serial),
-        None => {
-            let spinner = progress_spinner(&format!("Fetching public registry: {}", url));
-            let mut response: reqwest::Response =
-                reqwest::get(url).with_context(registry_fetch_error)?;
-            let response_text: String = response.text().unknown()?;
-            let cached: NamedTempFile = NamedTempFile::new_in(path::tmp_dir()?).unknown()?;
+/// Loads the public Node index, from the local cache if possible.
+///
+/// A downloaded index is cached in `node_index_file`, and `node_index_expiry_file`
+/// records when that copy becomes stale: the value of the registry's `Expires` header
+/// if it sent one, and otherwise the time of download plus the `max-age` from its
+/// `Cache-Control` header (four hours if it sent neither). Until then, the cached copy
+/// is used without contacting the registry.
+///
+/// If `force_fetch` is set, the cache is bypassed and the index is always downloaded.
+/// If the download fails with a network error (e.g. when offline) and a copy of the
+/// index is cached, that copy is used instead, even if stale, and a warning is shown.
+fn resolve_node_versions(url: &str, force_fetch: bool) -> Fallible<serial::NodeIndex> {
+    // a local index is read directly, rather than cached
+    if let Some(path) = local_file_path(url) {
+        return serde_json::de::from_str(&read_local_index(&path)?).unknown();
+    }
 
-            // Block to borrow cached for cached_file.
-            {
-                let mut cached_file: &File = cached.as_file();
-                cached_file.write(response_text.as_bytes()).unknown()?;
+    if !force_fetch {
+        if let Some(serial) = read_cached_opt(false)? {
+            return Ok(serial);
+        }
+    }
+
+    match fetch_node_versions(url) {
+        Ok(serial) => Ok(serial),
+        Err(error) => {
+            if let ExitCode::NetworkError = error.exit_code() {
+                if let Some(serial) = read_cached_opt(true)? {
+                    eprintln!(
+                        "Warning: could not fetch the public registry, using a cached copy that may be out of date"
+                    );
+                    return Ok(serial);
+                }
             }
+            Err(error)
+        }
+    }
+}
+
+/// Downloads the public Node index and saves it, along with its expiry date, to the cache.
+fn fetch_node_versions(url: &str) -> Fallible<serial::NodeIndex> {
+    let spinner = progress_spinner(&format!("Fetching public registry: {}", url));
+    let mut response: reqwest::Response = http_get(url)?;
+    let response_text = registry_response_text(url, &mut response)?;
+    let cached: NamedTempFile = NamedTempFile::new_in(path::tmp_dir()?).unknown()?;
+
+    // Block to borrow cached for cached_file.
+    {
+        let mut cached_file: &File = cached.as_file();
+        cached_file.write(response_text.as_bytes()).unknown()?;
+    }
+
+    let index_cache_file = path::node_index_file()?;
+    ensure_containing_dir_exists(&index_cache_file)?;
+    cached.persist(index_cache_file).unknown()?;
+
+    let expiry: NamedTempFile = NamedTempFile::new_in(path::tmp_dir()?).unknown()?;
+
+    // Block to borrow expiry for expiry_file.
+    {
+        let mut expiry_file: &File = expiry.as_file();
+
+        if let Some(expires_header) = response.headers().get_011::<Expires>() {
+            write!(expiry_file, "{}", expires_header).unknown()?;
+        } else {
+            let expiry_date = SystemTime::now() + Duration::from_secs(max_age(&response).into());
 
-            let index_cache_file = path::node_index_file()?;
-            ensure_containing_dir_exists(&index_cache_file)?;
-            cached.persist(index_cache_file).unknown()?;
+            write!(expiry_file, "{}", HttpDate::from(expiry_date)).unknown()?;
+        }
+    }
+
+    let index_expiry_file = path::node_index_expiry_file()?;
+    ensure_containing_dir_exists(&index_expiry_file)?;
+    expiry.persist(index_expiry_file).unknown()?;
+
+    let serial: serial::NodeIndex = serde_json::de::from_str(&response_text).unknown()?;
+
+    spinner.finish_and_clear();
+    Ok(serial)
+}
 
-            let expiry: NamedTempFile = NamedTempFile::new_in(path::tmp_dir()?).unknown()?;
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::hook::tool::MetadataHook;
+    use crate::path::tests::with_temp_home;
+    use std::fs::{create_dir_all, remove_file, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lazy_node_index_by_url() {
+        let dir = tempdir().expect("could not create temp dir");
+        let index_url = |name: &str, version: &str| {
+            let file = dir.path().join(name);
+            let json = format!(r#"[{{"version":"v{}","npm":"6.4.1","files":[]}}]"#, version);
+            write(&file, json).expect("could not write index");
+            format!("file://{}", file.display())
+        };
+        let first = index_url("first.json", "10.15.3");
+        let second = index_url("second.json", "11.10.1");
+
+        let lazy = LazyNodeIndex::new();
+        let latest = |url: &str| {
+            lazy.get(url, false)
+                .expect("could not load index")
+                .latest()
+                .map(|entry| entry.version.to_string())
+        };
+        assert_eq!(latest(&first), Some("10.15.3".to_string()));
+        assert_eq!(latest(&second), Some("11.10.1".to_string()));
+        assert_eq!(latest(&first), Some("10.15.3".to_string()));
+    }
 
-            // Block to borrow expiry for expiry_file.
+    #[test]
+    fn test_fetch_cached() {
+        with_temp_home(|home| {
+            let node = Version::parse("10.15.3").unwrap();
+            let collection = NodeCollection {
+                versions: vec![node.clone()].into_iter().collect(),
+                phantom: PhantomData,
+            };
+            let hooks = |dir: &Path| ToolHooks::<NodeDistro> {
+                distro: None,
+                latest: None,
+                index: Some(MetadataHook::Prefix(format!("file://{}/", dir.display()))),
+                phantom: PhantomData,
+            };
+            let fetch_cached = |version: &Version, dir: &Path| match collection
+                .fetch_cached(version, Some(&hooks(dir)))
             {
-                let mut expiry_file: &File = expiry.as_file();
+                Ok(Some(Fetched::Already(fetched))) => Some(fetched),
+                Ok(None) => None,
+                other => panic!("unexpected result: {:?}", other),
+            };
+
+            // only versions that are inventoried and unpacked are used
+            let no_index = home.join("no-index");
+            assert_eq!(
+                fetch_cached(&Version::parse("11.10.1").unwrap(), &no_index),
+                None
+            );
+            assert_eq!(fetch_cached(&node, &no_index), None);
+
+            // the saved npm version is used without loading the index
+            create_dir_all(path::node_image_version_dir("10.15.3").unwrap()).unwrap();
+            node::save_default_npm_version(&node, &Version::parse("6.4.1").unwrap()).unwrap();
+            assert_eq!(
+                fetch_cached(&node, &no_index),
+                Some(NodeVersion::new(
+                    node.clone(),
+                    Version::parse("6.4.1").unwrap()
+                ))
+            );
+
+            // a missing npm version file is repaired from the index
+            let index_dir = home.join("index");
+            create_dir_all(&index_dir).unwrap();
+            write(
+                index_dir.join("index.json"),
+                r#"[{"version":"v10.15.3","npm":"6.4.2","files":[]}]"#,
+            )
+            .unwrap();
+            remove_file(path::node_npm_version_file("10.15.3").unwrap()).unwrap();
+            let repaired = NodeVersion::new(node.clone(), Version::parse("6.4.2").unwrap());
+            assert_eq!(fetch_cached(&node, &index_dir), Some(repaired));
+            assert_eq!(
+                node::load_default_npm_version(&node).unwrap(),
+                Version::parse("6.4.2").unwrap()
+            );
+        });
+    }
 
-                if let Some(expires_header) = response.headers().get_011::<Expires>() {
-                    write!(expiry_file, "{}", expires_header).unknown()?;
-                } else {
-                    let expiry_date =
-                        SystemTime::now() + Duration::from_secs(max_age(&response).into());
+    #[test]
+    fn test_resolve_tag() {
+        let dir = tempdir().expect("could not create temp dir");
+        write(
+            dir.path().join("typescript"),
+            r#"{
+  "name": "typescript",
+  "versions": {
+    "3.4.5": {"version":"3.4.5","dist":{"shasum":"a","tarball":"https://example.com/typescript-3.4.5.tgz"}},
+    "3.5.0-rc": {"version":"3.5.0-rc","dist":{"shasum":"b","tarball":"https://example.com/typescript-3.5.0-rc.tgz"}}
+  },
+  "dist-tags": {"latest":"3.4.5","next":"3.5.0-rc"}
+}"#,
+        )
+        .unwrap();
+        let hooks = ToolHooks::<PackageDistro> {
+            distro: None,
+            latest: None,
+            index: Some(MetadataHook::Prefix(format!(
+                "file://{}/",
+                dir.path().display()
+            ))),
+            phantom: PhantomData,
+        };
+        let collection = PackageCollection {
+            versions: BTreeSet::new(),
+            phantom: PhantomData,
+        };
 
-                    write!(expiry_file, "{}", HttpDate::from(expiry_date)).unknown()?;
-                }
-            }
+        match collection.resolve_tag("typescript".to_string(), "next", Some(&hooks)) {
+            Ok(entry) => assert_eq!(entry.version, Version::parse("3.5.0-rc").unwrap()),
+            Err(error) => panic!("could not resolve tag: {}", error),
+        }
 
-            let index_expiry_file = path::node_index_expiry_file()?;
-            ensure_containing_dir_exists(&index_expiry_file)?;
-            expiry.persist(index_expiry_file).unknown()?;
+        match collection.resolve_tag("typescript".to_string(), "beta", Some(&hooks)) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::NoPackageFound {
+                    name,
+                    matching: VersionSpec::Tag(tag),
+                }) => {
+                    assert_eq!(name, "typescript");
+                    assert_eq!(tag, "beta");
+                }
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(entry) => panic!("resolved an unknown tag to {}", entry.version),
+        }
+    }
 
-            let serial: serial::NodeIndex = serde_json::de::from_str(&response_text).unknown()?;
+    #[test]
+    fn test_resolve_tag_node_and_yarn() {
+        let node = NodeCollection {
+            versions: BTreeSet::new(),
+            phantom: PhantomData,
+        };
+        match node.resolve_tag("node".to_string(), "next", None) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::NoPackageFound { name, .. }) => assert_eq!(name, "node"),
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("resolved a dist-tag for node"),
+        }
 
-            spinner.finish_and_clear();
-            Ok(serial)
+        let yarn = YarnCollection {
+            versions: BTreeSet::new(),
+            phantom: PhantomData,
+        };
+        match yarn.resolve_tag("yarn".to_string(), "next", None) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::NoPackageFound { name, .. }) => assert_eq!(name, "yarn"),
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("resolved a dist-tag for yarn"),
         }
     }
+
+    #[test]
+    fn test_is_html() {
+        let login_page = "<!DOCTYPE html>\n<html><body>Please log in</body></html>";
+        assert!(is_html(Some("text/html; charset=utf-8"), login_page));
+        assert!(is_html(Some("application/json"), login_page));
+        assert!(is_html(None, "  <html></html>"));
+        assert!(is_html(Some("text/html"), "Please log in"));
+
+        assert!(!is_html(Some("application/json"), r#"{"name": "npm"}"#));
+        assert!(!is_html(None, "[]"));
+        // a registry that mislabels its JSON is still trusted
+        assert!(!is_html(Some("text/html"), r#"[{"version": "v10.15.3"}]"#));
+    }
+
+    #[test]
+    fn test_package_metadata_bins_and_engines() {
+        let metadata = r#"{
+            "name": "cowsay",
+            "description": null,
+            "dist-tags": { "latest": "1.4.0" },
+            "versions": {
+                "1.4.0": {
+                    "version": "1.4.0",
+                    "dist": { "shasum": "abc", "tarball": "cowsay-1.4.0.tgz" },
+                    "bin": { "cowthink": "./cli.js", "cowsay": "./cli.js" },
+                    "engines": { "node": ">= 4" }
+                },
+                "1.3.0": {
+                    "version": "1.3.0",
+                    "dist": { "shasum": "def", "tarball": "cowsay-1.3.0.tgz" },
+                    "bin": "./cli.js",
+                    "engines": ["node >= 0.8"]
+                },
+                "1.2.0": {
+                    "version": "1.2.0",
+                    "dist": { "shasum": "ghi", "tarball": "cowsay-1.2.0.tgz" }
+                }
+            }
+        }"#;
+        let metadata: serial::PackageMetadata = serde_json::de::from_str(metadata).unwrap();
+        let index = metadata.into_index();
+
+        let entries: Vec<(String, Vec<String>, Option<String>)> = index
+            .entries
+            .into_iter()
+            .map(|entry| (entry.version.to_string(), entry.bins, entry.engines))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "1.4.0".to_string(),
+                    vec!["cowsay".to_string(), "cowthink".to_string()],
+                    Some(">= 4".to_string()),
+                ),
+                ("1.3.0".to_string(), vec!["cowsay".to_string()], None),
+                ("1.2.0".to_string(), vec![], None),
+            ]
+        );
+    }
 }
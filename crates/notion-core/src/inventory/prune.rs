@@ -0,0 +1,234 @@
+//! Provides functions for removing tool versions that are no longer needed from
+//! the inventory, to reclaim disk space.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::remove_file;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use semver::Version;
+
+use crate::error::ErrorDetails;
+use crate::fs::{read_dir_eager, trace_fs};
+use crate::path::{self, decode_package_name};
+use notion_fail::{Fallible, ResultExt};
+
+/// Groups the files in an inventory directory by the tool version they belong to,
+/// as determined by `parse` from each file name. Files it doesn't recognize are
+/// left out.
+fn files_by_version<K, F>(dir: &Path, parse: F) -> Fallible<BTreeMap<K, Vec<PathBuf>>>
+where
+    K: Ord,
+    F: Fn(&str) -> Option<K>,
+{
+    let mut files = BTreeMap::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    for (entry, metadata) in read_dir_eager(dir)? {
+        if !metadata.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let key = path
+            .file_name()
+            .and_then(|name| parse(&name.to_string_lossy()));
+        if let Some(key) = key {
+            files.entry(key).or_insert_with(Vec::new).push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Removes the files in an inventory directory for every version that `keep`
+/// rejects, and returns those versions in order. If `dry_run` is set, nothing is
+/// removed.
+fn prune_dir<K, F, P>(dir: &Path, parse: F, keep: P, dry_run: bool) -> Fallible<Vec<K>>
+where
+    K: Ord,
+    F: Fn(&str) -> Option<K>,
+    P: Fn(&K) -> bool,
+{
+    let mut pruned = Vec::new();
+
+    for (key, files) in files_by_version(dir, parse)? {
+        if keep(&key) {
+            continue;
+        }
+        if !dry_run {
+            for file in files {
+                trace_fs("remove", &file, remove_file(&file)).with_context(|error| {
+                    ErrorDetails::DeleteFileError {
+                        file: file.to_string_lossy().to_string(),
+                        error: error.to_string(),
+                    }
+                })?;
+            }
+        }
+        pruned.push(key);
+    }
+
+    Ok(pruned)
+}
+
+/// Parses the version from the name of a file in the Node inventory: an archive
+/// (`node-v10.15.3-linux-x64.tar.gz`), an npm version file (`node-v10.15.3-npm`),
+/// or a partial download of an archive.
+fn parse_node_file(name: &str) -> Option<Version> {
+    lazy_static! {
+        static ref NODE_FILE: Regex = Regex::new(r"^node-v(?P<version>\d+\.\d+\.\d+)-").unwrap();
+    }
+
+    let caps = NODE_FILE.captures(name)?;
+    Version::parse(&caps["version"]).ok()
+}
+
+/// Parses the version from the name of a Yarn archive (`yarn-v1.12.3.tar.gz`) or a
+/// partial download of one.
+fn parse_yarn_file(name: &str) -> Option<Version> {
+    lazy_static! {
+        static ref YARN_FILE: Regex =
+            Regex::new(r"^yarn-v(?P<version>\d+\.\d+\.\d+)\.tar\.gz").unwrap();
+    }
+
+    let caps = YARN_FILE.captures(name)?;
+    Version::parse(&caps["version"]).ok()
+}
+
+/// Parses the package name and version from the name of a package tarball
+/// (`cowsay-1.4.0.tgz`) or its shasum file (`cowsay-1.4.0.shasum`).
+fn parse_package_file(name: &str) -> Option<(String, Version)> {
+    let stem = name.trim_end_matches(".partial").trim_end_matches(".etag");
+    let stem = if stem.ends_with(".tgz") {
+        stem.trim_end_matches(".tgz")
+    } else if stem.ends_with(".shasum") {
+        stem.trim_end_matches(".shasum")
+    } else {
+        return None;
+    };
+
+    // package names can contain hyphens, so the version starts after the first
+    // hyphen that is followed by a valid version
+    stem.match_indices('-').find_map(|(index, _)| {
+        Version::parse(&stem[index + 1..])
+            .ok()
+            .map(|version| (decode_package_name(&stem[..index]), version))
+    })
+}
+
+/// Removes the archives, npm version files, and partial downloads of every Node
+/// version in the inventory that isn't in `keep`, and returns the removed versions.
+/// If `dry_run` is set, the versions are listed without removing anything.
+pub fn prune(keep: &HashSet<Version>, dry_run: bool) -> Fallible<Vec<Version>> {
+    prune_dir(
+        &path::node_inventory_dir()?,
+        parse_node_file,
+        |version| keep.contains(version),
+        dry_run,
+    )
+}
+
+/// Removes the archives of every Yarn version in the inventory that isn't in
+/// `keep`, and returns the removed versions. If `dry_run` is set, the versions
+/// are listed without removing anything.
+pub fn prune_yarn(keep: &HashSet<Version>, dry_run: bool) -> Fallible<Vec<Version>> {
+    prune_dir(
+        &path::yarn_inventory_dir()?,
+        parse_yarn_file,
+        |version| keep.contains(version),
+        dry_run,
+    )
+}
+
+/// Removes the tarballs and shasum files of every package version in the inventory
+/// that isn't in `keep`, and returns the removed package names and versions. If
+/// `dry_run` is set, they are listed without removing anything.
+pub fn prune_packages(
+    keep: &HashSet<(String, Version)>,
+    dry_run: bool,
+) -> Fallible<Vec<(String, Version)>> {
+    prune_dir(
+        &path::package_inventory_dir()?,
+        parse_package_file,
+        |package| keep.contains(package),
+        dry_run,
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    fn version(src: &str) -> Version {
+        Version::parse(src).unwrap()
+    }
+
+    #[test]
+    fn test_parse_node_file() {
+        assert_eq!(
+            parse_node_file("node-v10.15.3-linux-x64.tar.gz"),
+            Some(version("10.15.3"))
+        );
+        assert_eq!(
+            parse_node_file("node-v10.15.3-npm"),
+            Some(version("10.15.3"))
+        );
+        assert_eq!(
+            parse_node_file("node-v8.15.1-win-x64.zip.partial"),
+            Some(version("8.15.1"))
+        );
+        assert_eq!(parse_node_file("index.json"), None);
+    }
+
+    #[test]
+    fn test_parse_package_file() {
+        assert_eq!(
+            parse_package_file("cowsay-1.4.0.tgz"),
+            Some(("cowsay".to_string(), version("1.4.0")))
+        );
+        assert_eq!(
+            parse_package_file("ts-node-8.0.0-beta.1.shasum"),
+            Some(("ts-node".to_string(), version("8.0.0-beta.1")))
+        );
+        assert_eq!(
+            parse_package_file("@angular%2fcli-7.3.6.tgz"),
+            Some(("@angular/cli".to_string(), version("7.3.6")))
+        );
+        assert_eq!(parse_package_file("cowsay.json"), None);
+    }
+
+    #[test]
+    fn test_prune_dir() {
+        let dir = tempdir().expect("could not create temp dir");
+        for name in &[
+            "node-v10.15.3-linux-x64.tar.gz",
+            "node-v10.15.3-npm",
+            "node-v8.15.1-linux-x64.tar.gz",
+            "node-v8.15.1-npm",
+            "index.json",
+        ] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+        let keep = |version: &Version| version.major == 10;
+
+        assert_eq!(
+            prune_dir(dir.path(), parse_node_file, keep, true).unwrap(),
+            vec![version("8.15.1")]
+        );
+        assert!(dir.path().join("node-v8.15.1-npm").exists());
+
+        assert_eq!(
+            prune_dir(dir.path(), parse_node_file, keep, false).unwrap(),
+            vec![version("8.15.1")]
+        );
+        assert!(!dir.path().join("node-v8.15.1-linux-x64.tar.gz").exists());
+        assert!(!dir.path().join("node-v8.15.1-npm").exists());
+        assert!(dir.path().join("node-v10.15.3-npm").exists());
+        assert!(dir.path().join("index.json").exists());
+    }
+}
@@ -40,21 +40,37 @@ fn versions_matching(dir: &Path, re: &Regex) -> Fallible<BTreeSet<Version>> {
         .collect::<Fallible<BTreeSet<Version>>>()?)
 }
 
+/// Builds the regex matching the name of a Node archive in the inventory for the
+/// given OS and any of the given architectures. Other files, such as archives for
+/// another architecture or partial downloads, don't match.
+fn node_archive_regex(os: &str, archs: &[&str]) -> Regex {
+    let archs: Vec<String> = archs.iter().map(|arch| regex::escape(arch)).collect();
+    Regex::new(&format!(
+        r"(?x)
+        ^
+        node
+        -
+        v(?P<version>\d+\.\d+\.\d+) # Node version
+        -
+        {}                          # operating system
+        -
+        (?:{})                      # architecture
+        \.(zip|tar\.gz|tar\.xz)
+        $
+        ",
+        regex::escape(os),
+        archs.join("|")
+    ))
+    .unwrap()
+}
+
 impl NodeCollection {
     pub(crate) fn load() -> Fallible<Self> {
-        let re = Regex::new(
-            r"(?x)
-            node
-            -
-            v(?P<version>\d+\.\d+\.\d+) # Node version
-            -
-            (?P<os>[a-z]+)              # operating system
-            -
-            (?P<arch>[a-z0-9]+)         # architecture
-            \.(zip|tar\.gz)
-            ",
-        )
-        .unwrap();
+        // a release without a build for this machine may have been fetched for the
+        // fallback architecture instead
+        let mut archs = vec![path::arch()?];
+        archs.extend(path::fallback_arch()?);
+        let re = node_archive_regex(path::OS, &archs);
 
         let versions = versions_matching(&path::node_inventory_dir()?, &re)?;
 
@@ -69,10 +85,12 @@ impl YarnCollection {
     pub(crate) fn load() -> Fallible<Self> {
         let re = Regex::new(
             r"(?x)
+            ^
             yarn
             -
             v(?P<version>\d+\.\d+\.\d+) # Yarn version
             \.tar\.gz
+            $
             ",
         )
         .unwrap();
@@ -108,6 +126,32 @@ pub struct NodeEntry {
     #[serde(with = "option_version_serde")]
     pub npm: Option<Version>,
     pub files: Vec<String>,
+    #[serde(default)]
+    pub lts: NodeLts,
+}
+
+/// The `lts` field of an entry in the public Node index, which is `false` for
+/// releases that are not part of an LTS line and the LTS codename otherwise.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum NodeLts {
+    Codename(String),
+    Flag(bool),
+}
+
+impl Default for NodeLts {
+    fn default() -> Self {
+        NodeLts::Flag(false)
+    }
+}
+
+impl NodeLts {
+    fn into_codename(self) -> Option<String> {
+        match self {
+            NodeLts::Codename(codename) => Some(codename),
+            NodeLts::Flag(_) => None,
+        }
+    }
 }
 
 impl NodeIndex {
@@ -122,6 +166,7 @@ impl NodeIndex {
                     version: entry.version,
                     npm,
                     files: data,
+                    lts: entry.lts.into_codename(),
                 });
             }
         }
@@ -193,13 +238,20 @@ pub struct PackageVersionInfo {
     #[serde(with = "version_serde")]
     pub version: Version,
     pub dist: DistInfo,
+    // the executables and Node requirements let an install be planned without
+    // fetching the package; they're read leniently since older packages use other forms
+    #[serde(default)]
+    pub bin: Option<serde_json::Value>,
+    #[serde(default)]
+    pub engines: Option<serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PackageDistTags {
     #[serde(with = "version_serde")]
     pub latest: Version,
-    pub beta: Option<String>,
+    #[serde(flatten)]
+    pub other: HashMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -231,10 +283,14 @@ impl PackageMetadata {
     pub fn into_index(self) -> package::PackageIndex {
         let mut entries = Vec::new();
         for (_, version_info) in self.versions {
+            let bins = version_info.bin_names(&self.name);
+            let engines = version_info.node_engines();
             let entry = package::PackageEntry {
                 version: version_info.version,
                 tarball: version_info.dist.tarball,
                 shasum: version_info.dist.shasum,
+                bins,
+                engines,
             };
             entries.push(entry);
         }
@@ -242,14 +298,52 @@ impl PackageMetadata {
         // sort entries by version, largest to smallest
         entries.sort_by(|a, b| b.version.cmp(&a.version));
 
+        // tags pointing at versions that can't be parsed are ignored
+        let tags = self
+            .dist_tags
+            .other
+            .into_iter()
+            .filter_map(|(tag, version)| Version::parse(&version).ok().map(|v| (tag, v)))
+            .collect();
+
         package::PackageIndex {
             latest: self.dist_tags.latest,
+            tags: tags,
             entries: entries,
         }
     }
 }
 
+impl PackageVersionInfo {
+    /// The names of the executables this version provides, in sorted order. The `bin`
+    /// field is either a map of names to paths, or a single path for an executable
+    /// named after the package.
+    fn bin_names(&self, package_name: &str) -> Vec<String> {
+        let mut names: Vec<String> = match self.bin {
+            Some(serde_json::Value::String(_)) => vec![package_name.to_string()],
+            Some(serde_json::Value::Object(ref map)) => map.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+
+    /// The Node requirement from the `engines` field, if it has one.
+    fn node_engines(&self) -> Option<String> {
+        self.engines
+            .as_ref()
+            .and_then(|engines| engines.get("node"))
+            .and_then(|node| node.as_str())
+            .map(|node| node.to_string())
+    }
+}
+
 impl package::PackageConfig {
+    pub fn from_file(file: PathBuf) -> Fallible<Self> {
+        let config_src = read_to_string(file).unknown()?;
+        PackageConfig::from_json(config_src)?.into_config()
+    }
+
     pub fn to_serial(&self) -> PackageConfig {
         PackageConfig {
             name: self.name.to_string(),
@@ -282,8 +376,6 @@ impl PackageConfig {
         serde_json::to_string_pretty(&self).unknown()
     }
 
-    // not used yet - needed for listing and uninstall
-    #[allow(dead_code)]
     pub fn from_json(src: String) -> Fallible<Self> {
         serde_json::de::from_str(&src).unknown()
     }
@@ -295,6 +387,18 @@ impl PackageConfig {
         ensure_containing_dir_exists(&config_file_path)?;
         write(config_file_path, src).unknown()
     }
+
+    pub fn into_config(self) -> Fallible<package::PackageConfig> {
+        Ok(package::PackageConfig {
+            name: self.name.clone(),
+            version: self.version,
+            bins: self.bins,
+            platform: self
+                .platform
+                .into_image()?
+                .ok_or(ErrorDetails::NoBinPlatform { binary: self.name })?,
+        })
+    }
 }
 
 impl BinConfig {
@@ -327,3 +431,41 @@ impl BinConfig {
         })
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_node_archive_regex() {
+        let re = node_archive_regex("linux", &["arm64", "x64"]);
+        assert!(re.is_match("node-v10.15.3-linux-x64.tar.gz"));
+        assert!(re.is_match("node-v10.15.3-linux-arm64.tar.xz"));
+        assert!(!re.is_match("node-v10.15.3-linux-x86.tar.gz"));
+        assert!(!re.is_match("node-v10.15.3-darwin-x64.tar.gz"));
+        assert!(!re.is_match("node-v10.15.3-linux-x64.tar.gz.partial"));
+        assert!(!re.is_match("old-node-v10.15.3-linux-x64.tar.gz"));
+    }
+
+    #[test]
+    fn test_versions_matching_skips_other_archives() {
+        let dir = tempdir().expect("could not create temp dir");
+        for name in &[
+            "node-v10.15.3-linux-x64.tar.gz",
+            "node-v11.10.1-linux-x64.tar.gz.partial",
+            "node-v8.15.1-linux-x86.tar.gz",
+            "node-v10.15.3-npm",
+        ] {
+            File::create(dir.path().join(name)).expect("could not create file");
+        }
+
+        let re = node_archive_regex("linux", &["x64"]);
+        let versions = versions_matching(dir.path(), &re).expect("could not read versions");
+        assert_eq!(
+            versions.into_iter().collect::<Vec<_>>(),
+            vec![Version::parse("10.15.3").unwrap()]
+        );
+    }
+}
@@ -2,6 +2,7 @@
 
 #![cfg_attr(feature = "universal-docs", feature(doc_cfg))]
 
+pub mod config;
 mod distro;
 pub mod env;
 pub mod error;
@@ -11,6 +12,7 @@ mod hook;
 pub mod inventory;
 pub mod manifest;
 pub mod monitor;
+mod npmrc;
 pub mod path;
 pub mod platform;
 pub mod project;
@@ -20,4 +22,8 @@ pub mod shim;
 pub mod style;
 pub mod tool;
 pub mod toolchain;
+pub mod verify;
 pub mod version;
+
+#[cfg(feature = "trace-fs")]
+pub use crate::fs::init_trace_logger;
@@ -0,0 +1,306 @@
+//! Provides types for reading npm configuration from `.npmrc` files.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::env::npm_token;
+use crate::path;
+use notion_fail::{Fallible, ResultExt};
+
+/// The settings from one or more `.npmrc` files.
+#[derive(Debug, Default)]
+pub struct Npmrc {
+    settings: HashMap<String, String>,
+}
+
+/// Replaces `${NAME}` references to environment variables in an `.npmrc` value, as
+/// npm does. References to unset variables are left as they are.
+fn expand_env_vars(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        expanded.push_str(&rest[..start]);
+        match env::var(&rest[start + 2..end]) {
+            Ok(var) => expanded.push_str(&var),
+            Err(_) => expanded.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Strips the scheme from a URL, producing the form npm uses to key per-registry
+/// settings, e.g. `//registry.npmjs.org/`.
+fn nerf_dart(url: &str) -> &str {
+    match url.find("//") {
+        Some(index) => &url[index..],
+        None => url,
+    }
+}
+
+/// Determines whether a URL is the registry root `registry`, or lies under it. Both
+/// are compared in their nerf-dart form, by whole path segments, so that e.g.
+/// `//npm.example.com` covers `//npm.example.com/cowsay` but not
+/// `//npm.example.com.evil/cowsay`.
+fn is_under(url: &str, registry: &str) -> bool {
+    let url = nerf_dart(url);
+    let registry = nerf_dart(registry).trim_end_matches('/');
+    url == registry || (url.starts_with(registry) && url[registry.len()..].starts_with('/'))
+}
+
+impl Npmrc {
+    /// Parses the contents of an `.npmrc` file. Blank lines, comments, and lines
+    /// without a `=` are ignored.
+    pub fn parse(src: &str) -> Npmrc {
+        let settings = src
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+            .filter_map(|line| {
+                let index = line.find('=')?;
+                let key = line[..index].trim().to_string();
+                let value = line[index + 1..].trim().trim_matches('"');
+                Some((key, expand_env_vars(value)))
+            })
+            .collect();
+        Npmrc { settings }
+    }
+
+    /// Reads the `.npmrc` file at the given path, if it exists.
+    fn from_file(path: &Path) -> Fallible<Npmrc> {
+        if !path.is_file() {
+            return Ok(Npmrc::default());
+        }
+        Ok(Npmrc::parse(&read_to_string(path).unknown()?))
+    }
+
+    /// Loads the settings for the current directory: those of the project `.npmrc`,
+    /// if any, and then those of the user `.npmrc` that the project doesn't override.
+    pub fn current() -> Fallible<Npmrc> {
+        let mut npmrc = Npmrc::from_file(&path::user_npmrc_file()?)?;
+        let current_dir = env::current_dir().unknown()?;
+        if let Some(project_file) = path::find_project_npmrc_file(&current_dir)? {
+            npmrc
+                .settings
+                .extend(Npmrc::from_file(&project_file)?.settings);
+        }
+        Ok(npmrc)
+    }
+
+    /// Produces the value of a setting, if it is set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings.get(key).map(String::as_str)
+    }
+
+    /// Produces the registry configured for the named package: the `@scope:registry`
+    /// setting for a scoped package, if set, or else the `registry` setting.
+    pub fn registry(&self, package: &str) -> Option<&str> {
+        let scoped = if package.starts_with('@') {
+            package
+                .find('/')
+                .and_then(|index| self.get(&format!("{}:registry", &package[..index])))
+        } else {
+            None
+        };
+        scoped.or_else(|| self.get("registry"))
+    }
+
+    /// Produces the auth token configured for the registry serving the given URL, i.e.
+    /// the `//<registry>/:_authToken` setting with the longest registry prefix of the URL.
+    pub fn auth_token(&self, url: &str) -> Option<&str> {
+        self.settings
+            .iter()
+            .filter_map(|(key, value)| {
+                let registry = key.trim_end_matches(":_authToken");
+                if registry.len() < key.len() && is_under(url, registry) {
+                    Some((registry.len(), value.as_str()))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|&(len, _)| len)
+            .map(|(_, token)| token)
+    }
+}
+
+/// Produces the value of the HTTP `"Authorization"` header for the given URL: the auth
+/// token configured for it in `.npmrc`, or else `npm_token`, but only if the URL is
+/// under `registry`, so the token isn't sent to other hosts (e.g. tarball mirrors).
+fn authorization_for(
+    npmrc: &Npmrc,
+    npm_token: Option<String>,
+    url: &str,
+    registry: &str,
+) -> Option<String> {
+    let token = match npmrc.auth_token(url) {
+        Some(token) => Some(token.to_string()),
+        None if is_under(url, registry) => npm_token,
+        None => None,
+    };
+    token.map(|token| format!("Bearer {}", token))
+}
+
+/// Produces the value of the HTTP `"Authorization"` header to send for the given URL
+/// when fetching from `registry`: the auth token configured for the URL in `.npmrc`,
+/// or else the value of `NPM_TOKEN` if the URL is under `registry`. Returns `None` if
+/// there is no token, for anonymous access.
+pub(crate) fn authorization(url: &str, registry: &str) -> Fallible<Option<String>> {
+    Ok(authorization_for(
+        &Npmrc::current()?,
+        npm_token(),
+        url,
+        registry,
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const NPMRC: &'static str = r#"
+# a comment
+; another comment
+registry=https://registry.npmjs.org/
+@corp:registry=https://npm.example.com/private/
+//registry.npmjs.org/:_authToken=public-token
+//npm.example.com/:_authToken = "example-token"
+//npm.example.com/private/:_authToken=private-token
+not a setting
+"#;
+
+    #[test]
+    fn test_parse() {
+        let npmrc = Npmrc::parse(NPMRC);
+        assert_eq!(npmrc.get("registry"), Some("https://registry.npmjs.org/"));
+        assert_eq!(
+            npmrc.get("//npm.example.com/:_authToken"),
+            Some("example-token")
+        );
+        assert_eq!(npmrc.get("not a setting"), None);
+    }
+
+    #[test]
+    fn test_registry() {
+        let npmrc = Npmrc::parse(NPMRC);
+        assert_eq!(
+            npmrc.registry("@corp/cli"),
+            Some("https://npm.example.com/private/")
+        );
+        assert_eq!(
+            npmrc.registry("@other/cli"),
+            Some("https://registry.npmjs.org/")
+        );
+        assert_eq!(
+            npmrc.registry("cowsay"),
+            Some("https://registry.npmjs.org/")
+        );
+        assert_eq!(Npmrc::parse("").registry("cowsay"), None);
+    }
+
+    #[test]
+    fn test_auth_token() {
+        let npmrc = Npmrc::parse(NPMRC);
+        assert_eq!(
+            npmrc.auth_token("https://registry.npmjs.org/cowsay"),
+            Some("public-token")
+        );
+        assert_eq!(
+            npmrc.auth_token("https://npm.example.com/@corp%2fcli"),
+            Some("example-token")
+        );
+        assert_eq!(
+            npmrc.auth_token("https://npm.example.com/private/@corp%2fcli"),
+            Some("private-token")
+        );
+        assert_eq!(
+            npmrc.auth_token("https://registry.yarnpkg.com/cowsay"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_auth_token_whole_segments() {
+        let npmrc = Npmrc::parse("//npm.example.com:_authToken=example-token");
+        assert_eq!(
+            npmrc.auth_token("https://npm.example.com/cowsay"),
+            Some("example-token")
+        );
+        assert_eq!(
+            npmrc.auth_token("https://npm.example.com.evil/cowsay"),
+            None
+        );
+
+        let npmrc = Npmrc::parse(NPMRC);
+        assert_eq!(
+            npmrc.auth_token("https://npm.example.com/privateer/cowsay"),
+            Some("example-token")
+        );
+    }
+
+    #[test]
+    fn test_authorization_for() {
+        let registry = "https://registry.npmjs.org";
+        let npm_token = Some("npm-token".to_string());
+        let empty = Npmrc::default();
+        assert_eq!(
+            authorization_for(
+                &empty,
+                npm_token.clone(),
+                "https://registry.npmjs.org/cowsay/-/cowsay-1.4.0.tgz",
+                registry
+            ),
+            Some("Bearer npm-token".to_string())
+        );
+        assert_eq!(
+            authorization_for(
+                &empty,
+                npm_token.clone(),
+                "https://cdn.example.com/cowsay/-/cowsay-1.4.0.tgz",
+                registry
+            ),
+            None
+        );
+        assert_eq!(
+            authorization_for(
+                &empty,
+                npm_token.clone(),
+                "https://registry.npmjs.org.evil/cowsay",
+                registry
+            ),
+            None
+        );
+
+        let npmrc = Npmrc::parse(NPMRC);
+        assert_eq!(
+            authorization_for(
+                &npmrc,
+                npm_token,
+                "https://npm.example.com/private/cli.tgz",
+                registry
+            ),
+            Some("Bearer private-token".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        env::set_var("NOTION_TEST_NPMRC_TOKEN", "secret");
+        assert_eq!(
+            expand_env_vars("${NOTION_TEST_NPMRC_TOKEN}"),
+            "secret".to_string()
+        );
+        assert_eq!(
+            expand_env_vars("a-${NOTION_TEST_NPMRC_UNSET}-b"),
+            "a-${NOTION_TEST_NPMRC_UNSET}-b".to_string()
+        );
+    }
+}
@@ -4,7 +4,10 @@
 use std::env;
 use std::path::{Path, PathBuf};
 
-use notion_fail::Fallible;
+use crate::config::config;
+use crate::env::NOTION_HOME;
+use crate::error::ErrorDetails;
+use notion_fail::{throw, Fallible};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "universal-docs")] {
@@ -24,11 +27,13 @@ cfg_if::cfg_if! {
     }
 }
 
+/// The Notion home directory: `NOTION_HOME` if it is set, or the default location
+/// otherwise. This is read directly rather than through `config()`, so that the home
+/// can be found (e.g. by the shims) even when another setting is invalid.
 pub fn notion_home() -> Fallible<PathBuf> {
-    if let Some(home) = env::var_os("NOTION_HOME") {
-        Ok(Path::new(&home).to_path_buf())
-    } else {
-        default_notion_home()
+    match env::var_os(NOTION_HOME) {
+        Some(home) => Ok(PathBuf::from(home)),
+        None => default_notion_home(),
     }
 }
 
@@ -44,6 +49,20 @@ pub fn node_inventory_dir() -> Fallible<PathBuf> {
     Ok(inventory_dir()?.join("node"))
 }
 
+pub fn npm_inventory_dir() -> Fallible<PathBuf> {
+    Ok(inventory_dir()?.join("npm"))
+}
+
+/// The inventory file for the registry tarball of a standalone npm version.
+pub fn npm_archive_file(version: &str) -> Fallible<PathBuf> {
+    Ok(npm_inventory_dir()?.join(npm_distro_file_name(version)))
+}
+
+/// The expected shasum of the registry tarball of a standalone npm version.
+pub fn npm_archive_shasum(version: &str) -> Fallible<PathBuf> {
+    Ok(npm_inventory_dir()?.join(npm_shasum_file_name(version)))
+}
+
 pub fn yarn_inventory_dir() -> Fallible<PathBuf> {
     Ok(inventory_dir()?.join("yarn"))
 }
@@ -80,8 +99,40 @@ pub fn node_image_root_dir() -> Fallible<PathBuf> {
     Ok(image_dir()?.join("node"))
 }
 
+/// The directory holding the images of a Node version, one for each npm version. The
+/// images of a build for another architecture than the machine's, requested with
+/// `NOTION_ARCH`, are kept under a directory named for that architecture, so that
+/// they don't take the place of the native build of the same version.
+pub fn node_image_version_dir(node: &str) -> Fallible<PathBuf> {
+    let root = node_image_root_dir()?;
+    Ok(match config()?.arch_override() {
+        Some(arch) if arch != NATIVE_ARCH.with(|native| *native) => root.join(arch).join(node),
+        _ => root.join(node),
+    })
+}
+
 pub fn node_image_dir(node: &str, npm: &str) -> Fallible<PathBuf> {
-    Ok(node_image_root_dir()?.join(node).join(npm))
+    Ok(node_image_version_dir(node)?.join(npm))
+}
+
+/// The link to the image directory of the user's active Node version, which gives
+/// external tools a path that stays the same when the version changes.
+pub fn node_image_current_link() -> Fallible<PathBuf> {
+    Ok(node_image_root_dir()?.join("current"))
+}
+
+pub fn npm_image_root_dir() -> Fallible<PathBuf> {
+    Ok(image_dir()?.join("npm"))
+}
+
+/// The image directory of a version of npm fetched separately from Node, for
+/// platforms that pin a different npm than the one bundled with their Node.
+pub fn npm_image_dir(version: &str) -> Fallible<PathBuf> {
+    Ok(npm_image_root_dir()?.join(version))
+}
+
+pub fn npm_image_bin_dir(version: &str) -> Fallible<PathBuf> {
+    Ok(npm_image_dir(version)?.join("bin"))
 }
 
 pub fn yarn_image_root_dir() -> Fallible<PathBuf> {
@@ -101,7 +152,9 @@ pub fn package_image_root_dir() -> Fallible<PathBuf> {
 }
 
 pub fn package_image_dir(name: &str, version: &str) -> Fallible<PathBuf> {
-    Ok(package_image_root_dir()?.join(name).join(version))
+    Ok(package_image_root_dir()?
+        .join(encode_package_name(name))
+        .join(version))
 }
 
 pub fn shim_dir() -> Fallible<PathBuf> {
@@ -112,6 +165,62 @@ pub fn user_hooks_file() -> Fallible<PathBuf> {
     Ok(notion_home()?.join("hooks.toml"))
 }
 
+/// Finds the project-level `hooks.toml` by walking up from `start`, stopping at
+/// the package root (the nearest directory containing a `package.json`). Returns
+/// `None` if there is no hooks file or `start` is not inside a package.
+pub fn find_project_hooks_file(start: &Path) -> Fallible<Option<PathBuf>> {
+    let mut hooks_file = None;
+    for dir in start.ancestors() {
+        let candidate = dir.join("hooks.toml");
+        if hooks_file.is_none() && candidate.is_file() {
+            hooks_file = Some(candidate);
+        }
+        if dir.join("package.json").is_file() {
+            return Ok(hooks_file);
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the project-level `.npmrc`, which npm only reads from the package root (the
+/// nearest directory containing a `package.json` above `start`). Returns `None` if
+/// there is no such file or `start` is not inside a package.
+pub fn find_project_npmrc_file(start: &Path) -> Fallible<Option<PathBuf>> {
+    for dir in start.ancestors() {
+        if dir.join("package.json").is_file() {
+            let npmrc_file = dir.join(".npmrc");
+            return Ok(if npmrc_file.is_file() {
+                Some(npmrc_file)
+            } else {
+                None
+            });
+        }
+    }
+    Ok(None)
+}
+
+/// The user's home directory. This fails if it can't be determined, or if `HOME`
+/// is set to something other than an existing directory (e.g. in a container with
+/// a bogus `HOME`).
+pub(crate) fn home_dir() -> Fallible<PathBuf> {
+    check_home_dir(dirs::home_dir().ok_or(ErrorDetails::NoHomeEnvironmentVar)?)
+}
+
+/// Checks that the given home directory is an existing directory.
+fn check_home_dir(home: PathBuf) -> Fallible<PathBuf> {
+    if !home.is_dir() {
+        throw!(ErrorDetails::HomeNotADirectory {
+            path: home.to_string_lossy().to_string(),
+        });
+    }
+    Ok(home)
+}
+
+/// The user-level `.npmrc`, in the user's home directory.
+pub fn user_npmrc_file() -> Fallible<PathBuf> {
+    Ok(home_dir()?.join(".npmrc"))
+}
+
 pub fn tools_dir() -> Fallible<PathBuf> {
     Ok(notion_home()?.join("tools"))
 }
@@ -128,12 +237,24 @@ pub fn user_platform_file() -> Fallible<PathBuf> {
     Ok(user_toolchain_dir()?.join("platform.json"))
 }
 
+pub fn user_platform_lock_file() -> Fallible<PathBuf> {
+    Ok(user_toolchain_dir()?.join("platform.json.lock"))
+}
+
+pub fn user_platform_backup_file() -> Fallible<PathBuf> {
+    Ok(user_toolchain_dir()?.join("platform.json.bak"))
+}
+
+pub fn user_platform_history_file() -> Fallible<PathBuf> {
+    Ok(user_toolchain_dir()?.join("platform.history"))
+}
+
 pub fn user_package_dir() -> Fallible<PathBuf> {
     Ok(user_toolchain_dir()?.join("packages"))
 }
 
 pub fn user_package_config_file(package_name: &str) -> Fallible<PathBuf> {
-    Ok(user_package_dir()?.join(format!("{}.json", package_name)))
+    Ok(user_package_dir()?.join(format!("{}.json", encode_package_name(package_name))))
 }
 
 pub fn user_tool_bin_config(bin_name: &str) -> Fallible<PathBuf> {
@@ -142,12 +263,72 @@ pub fn user_tool_bin_config(bin_name: &str) -> Fallible<PathBuf> {
         .join(format!("{}.json", bin_name)))
 }
 
-pub fn node_distro_file_name(version: &str) -> String {
-    format!(
+/// The Node architectures that can be requested with the `NOTION_ARCH` override.
+pub const SUPPORTED_ARCHS: [&'static str; 4] = ["x64", "x86", "arm64", "armv7l"];
+
+thread_local! {
+    // Detecting the native architecture may run a subprocess, so it is only done
+    // once per process.
+    static NATIVE_ARCH: &'static str = native_arch();
+}
+
+/// The system architecture component of Node distribution names: the `NOTION_ARCH`
+/// override if it is set, or the architecture of the machine otherwise, which can
+/// differ from `ARCH` when running under emulation.
+pub fn arch() -> Fallible<&'static str> {
+    match config()?.arch_override() {
+        Some(arch) => Ok(arch),
+        None => Ok(NATIVE_ARCH.with(|arch| *arch)),
+    }
+}
+
+/// The architecture whose Node builds can be used instead when a Node release has no
+/// build for `arch()`: when Notion itself runs under emulation (e.g. an x64 build
+/// under Rosetta on an arm64 Mac), builds for the emulated `ARCH` run the same way.
+/// There is no fallback if the architecture is overridden.
+pub fn fallback_arch() -> Fallible<Option<&'static str>> {
+    if config()?.arch_override().is_some() {
+        return Ok(None);
+    }
+
+    let native = NATIVE_ARCH.with(|arch| *arch);
+    Ok(if native == ARCH { None } else { Some(ARCH) })
+}
+
+/// The name of the current platform's build in the `files` list of an entry in the
+/// public Node index, e.g. `linux-x64`.
+pub fn node_index_build_name() -> Fallible<String> {
+    Ok(node_index_build_name_for(OS, arch()?))
+}
+
+/// The name of the build for the given OS and architecture in the `files` list of an
+/// entry in the public Node index, which names macOS and Windows builds differently
+/// from their archives.
+pub fn node_index_build_name_for(os: &str, arch: &str) -> String {
+    match os {
+        "darwin" => format!("osx-{}-tar", arch),
+        "win" => format!("win-{}-zip", arch),
+        _ => format!("{}-{}", os, arch),
+    }
+}
+
+/// The extension of Node distribution archives: the `NOTION_ARCHIVE_FORMAT` override
+/// if it is set, or the platform's `archive_extension` otherwise.
+pub fn node_archive_extension() -> Fallible<String> {
+    Ok(match config()?.archive_format() {
+        Some(format) => format.to_string(),
+        None => archive_extension(),
+    })
+}
+
+/// The file name of the Node distribution archive for the given version and
+/// architecture on the current OS, e.g. `node-v10.13.0-linux-x64.tar.gz`.
+pub fn node_distro_file_name(version: &str, arch: &str) -> Fallible<String> {
+    Ok(format!(
         "{}.{}",
-        node_archive_root_dir_name(version),
-        archive_extension()
-    )
+        node_archive_root_dir_name(version, arch),
+        node_archive_extension()?
+    ))
 }
 
 pub fn node_npm_version_file(version: &str) -> Fallible<PathBuf> {
@@ -155,18 +336,35 @@ pub fn node_npm_version_file(version: &str) -> Fallible<PathBuf> {
     Ok(node_inventory_dir()?.join(&filename))
 }
 
-pub fn node_archive_root_dir_name(version: &str) -> String {
-    format!("node-v{}-{}-{}", version, OS, ARCH)
+/// The name of the root directory inside the Node distribution archive for the
+/// given version and architecture on the current OS, e.g. `node-v10.13.0-linux-x64`.
+pub fn node_archive_root_dir_name(version: &str, arch: &str) -> String {
+    node_archive_root_dir_name_for(version, OS, arch)
+}
+
+/// The name of the root directory inside the Node distribution archive for the
+/// given version, OS, and architecture, as named in the public Node index.
+pub fn node_archive_root_dir_name_for(version: &str, os: &str, arch: &str) -> String {
+    format!("node-v{}-{}-{}", version, os, arch)
 }
 
-pub fn node_archive_npm_package_json_path(version: &str) -> PathBuf {
-    Path::new(&node_archive_root_dir_name(version))
-        .join("lib")
+/// The path of the bundled npm's `package.json`, relative to the root of a Node
+/// distribution (either the unpacked archive root or a Node image directory).
+pub fn node_npm_package_json_path() -> PathBuf {
+    Path::new("lib")
         .join("node_modules")
         .join("npm")
         .join("package.json")
 }
 
+pub fn npm_distro_file_name(version: &str) -> String {
+    format!("npm-{}.tgz", version)
+}
+
+pub fn npm_shasum_file_name(version: &str) -> String {
+    format!("npm-{}.shasum", version)
+}
+
 pub fn yarn_distro_file_name(version: &str) -> String {
     format!("{}.tar.gz", yarn_archive_root_dir_name(version))
 }
@@ -184,28 +382,153 @@ pub fn package_shasum_file_name(name: &str, version: &str) -> String {
 }
 
 pub fn package_archive_root_dir_name(name: &str, version: &str) -> String {
-    format!("{}-{}", name, version)
+    format!("{}-{}", encode_package_name(name), version)
+}
+
+/// Encodes a package name for use in a single path component. The slash in a scoped
+/// package name (e.g. `@angular/cli`) is encoded as `%2f`. Package names can't contain
+/// `%`, so `decode_package_name` always recovers the original name.
+pub fn encode_package_name(name: &str) -> String {
+    name.replace('/', "%2f")
+}
+
+/// Recovers a package name encoded by `encode_package_name`.
+pub fn decode_package_name(encoded: &str) -> String {
+    encoded.replace("%2f", "/")
 }
 
 #[cfg(test)]
 pub mod tests {
 
     use super::*;
+    use crate::config::init_config;
+    use crate::env::ARCH_OVERRIDE;
+    use lazy_static::lazy_static;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    lazy_static! {
+        static ref HOME_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    /// Runs `test` with `NOTION_HOME` set to a new temporary directory. The variable
+    /// is shared by every test in the process, so these tests run one at a time, and
+    /// any test that compares paths under the Notion home must run inside one too.
+    pub fn with_temp_home<F: FnOnce(&Path)>(test: F) {
+        let _guard = HOME_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let home = tempdir().expect("could not create temp dir");
+        env::set_var(NOTION_HOME, home.path());
+        test(home.path());
+        env::remove_var(NOTION_HOME);
+    }
 
     #[test]
     fn test_node_distro_file_name() {
         assert_eq!(
-            node_distro_file_name("1.2.3"),
-            format!("node-v1.2.3-{}-{}.{}", OS, ARCH, archive_extension())
+            node_distro_file_name("1.2.3", native_arch()).unwrap(),
+            format!(
+                "node-v1.2.3-{}-{}.{}",
+                OS,
+                native_arch(),
+                archive_extension()
+            )
         );
     }
 
     #[test]
     fn test_node_archive_root_dir() {
         assert_eq!(
-            node_archive_root_dir_name("1.2.3"),
-            format!("node-v1.2.3-{}-{}", OS, ARCH)
+            node_archive_root_dir_name("1.2.3", native_arch()),
+            format!("node-v1.2.3-{}-{}", OS, native_arch())
+        );
+    }
+
+    #[test]
+    fn test_node_archive_root_dir_for_platforms() {
+        assert_eq!(
+            node_archive_root_dir_name_for("10.13.0", "win", "x64"),
+            "node-v10.13.0-win-x64"
+        );
+        assert_eq!(
+            node_archive_root_dir_name_for("10.13.0", "win", "x86"),
+            "node-v10.13.0-win-x86"
+        );
+        assert_eq!(
+            node_archive_root_dir_name_for("8.15.1", "linux", "x64"),
+            "node-v8.15.1-linux-x64"
+        );
+        assert_eq!(
+            node_archive_root_dir_name_for("11.10.1", "darwin", "x64"),
+            "node-v11.10.1-darwin-x64"
+        );
+    }
+
+    #[test]
+    fn test_check_home_dir() {
+        let dir = tempdir().expect("could not create temp dir");
+        assert_eq!(
+            check_home_dir(dir.path().to_path_buf()).unwrap(),
+            dir.path()
         );
+
+        let file = dir.path().join("home");
+        std::fs::write(&file, "").unwrap();
+        for home in &[file, dir.path().join("missing")] {
+            match check_home_dir(home.clone()) {
+                Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                    Some(ErrorDetails::HomeNotADirectory { path }) => {
+                        assert_eq!(path, &home.to_string_lossy().to_string())
+                    }
+                    other => panic!("unexpected error: {:?}", other),
+                },
+                Ok(_) => panic!("accepted {} as the home directory", home.display()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_node_image_version_dir() {
+        with_temp_home(|_| {
+            assert_eq!(
+                node_image_version_dir("10.13.0").unwrap(),
+                node_image_root_dir().unwrap().join("10.13.0")
+            );
+        });
+    }
+
+    #[test]
+    fn test_node_image_version_dir_for_other_arch() {
+        with_temp_home(|_| {
+            let other = SUPPORTED_ARCHS
+                .iter()
+                .find(|arch| **arch != native_arch())
+                .unwrap();
+            // each test runs on its own thread, so these settings aren't loaded yet
+            init_config(&[(ARCH_OVERRIDE, other.to_string())]).unwrap();
+
+            assert_eq!(
+                node_archive_root_dir_name("10.13.0", arch().unwrap()),
+                format!("node-v10.13.0-{}-{}", OS, other)
+            );
+            assert_eq!(
+                node_image_version_dir("10.13.0").unwrap(),
+                node_image_root_dir().unwrap().join(other).join("10.13.0")
+            );
+        });
+    }
+
+    #[test]
+    fn test_node_index_build_name_for_platforms() {
+        assert_eq!(node_index_build_name_for("linux", "x64"), "linux-x64");
+        assert_eq!(node_index_build_name_for("darwin", "x64"), "osx-x64-tar");
+        assert_eq!(node_index_build_name_for("win", "x86"), "win-x86-zip");
+    }
+
+    #[test]
+    fn test_npm_distro_file_name() {
+        assert_eq!(npm_distro_file_name("6.7.0"), "npm-6.7.0.tgz");
     }
 
     #[test]
@@ -220,4 +543,83 @@ pub mod tests {
             "yarn-v1.2.3".to_string()
         );
     }
+
+    #[test]
+    fn test_package_file_names() {
+        assert_eq!(
+            package_distro_file_name("ember-cli", "3.7.1"),
+            "ember-cli-3.7.1.tgz"
+        );
+        assert_eq!(
+            package_distro_file_name("@angular/cli", "7.2.0"),
+            "@angular%2fcli-7.2.0.tgz"
+        );
+        assert_eq!(
+            package_shasum_file_name("@angular/cli", "7.2.0"),
+            "@angular%2fcli-7.2.0.shasum"
+        );
+    }
+
+    #[test]
+    fn test_scoped_package_paths() {
+        with_temp_home(|_| {
+            let image_dir = package_image_dir("@angular/cli", "7.2.0").unwrap();
+            assert_eq!(
+                image_dir,
+                package_image_root_dir()
+                    .unwrap()
+                    .join("@angular%2fcli")
+                    .join("7.2.0")
+            );
+
+            let config_file = user_package_config_file("@angular/cli").unwrap();
+            assert_eq!(
+                config_file,
+                user_package_dir().unwrap().join("@angular%2fcli.json")
+            );
+        });
+    }
+
+    #[test]
+    fn test_package_name_round_trip() {
+        for name in &["ember-cli", "@angular/cli", "@types/node"] {
+            assert_eq!(decode_package_name(&encode_package_name(name)), *name);
+        }
+    }
+
+    fn fixture_path(fixture_dir: &str) -> PathBuf {
+        let mut cargo_manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        cargo_manifest_dir.push("fixtures");
+        cargo_manifest_dir.push(fixture_dir);
+        cargo_manifest_dir
+    }
+
+    #[test]
+    fn test_find_project_hooks_file() {
+        let project_dir = fixture_path("hooks_project");
+        assert_eq!(
+            find_project_hooks_file(&project_dir).expect("could not search for hooks"),
+            Some(project_dir.join("hooks.toml"))
+        );
+
+        let basic_dir = fixture_path("basic");
+        assert_eq!(
+            find_project_hooks_file(&basic_dir).expect("could not search for hooks"),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_create_dir_symlink() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let src = dir.path().join("image");
+        let dst = dir.path().join("current");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("node"), "").unwrap();
+
+        create_dir_symlink(src.clone(), dst.clone()).expect("could not create symlink");
+        assert!(dst.join("node").is_file());
+        assert_eq!(std::fs::read_link(&dst).unwrap(), src);
+    }
 }
@@ -4,16 +4,15 @@
 use std::io;
 use std::os::unix;
 use std::path::PathBuf;
+#[cfg(target_os = "macos")]
+use std::process::Command;
 
-use dirs;
-
-use crate::error::ErrorDetails;
 use notion_fail::Fallible;
 
-use super::{node_image_dir, notion_home, shim_dir};
+use super::{home_dir, node_image_dir, notion_home, shim_dir};
 
 // These are taken from: https://nodejs.org/dist/index.json and are used
-// by `path::archive_root_dir` to determine the root directory of the
+// by `path::node_archive_root_dir_name` to determine the root directory of the
 // contents of a Node installer archive.
 
 cfg_if::cfg_if! {
@@ -40,6 +39,35 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Detects the architecture of the machine at runtime. An x64 build running on an
+/// Apple Silicon Mac under Rosetta translation reports `arm64`, so that native Node
+/// builds are used; if the check can't be made, this falls back to `ARCH`.
+#[cfg(target_os = "macos")]
+pub fn native_arch() -> &'static str {
+    // `sysctl.proc_translated` is 1 for a translated process, 0 for a native one,
+    // and missing altogether on Intel Macs.
+    let translated = Command::new("sysctl")
+        .args(&["-n", "sysctl.proc_translated"])
+        .output()
+        .map(|output| {
+            output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "1"
+        })
+        .unwrap_or(false);
+
+    if translated {
+        "arm64"
+    } else {
+        ARCH
+    }
+}
+
+/// Detects the architecture of the machine at runtime, which on Linux is always
+/// the compile-time `ARCH`.
+#[cfg(not(target_os = "macos"))]
+pub fn native_arch() -> &'static str {
+    ARCH
+}
+
 // ~/
 //     .notion/
 //         cache/                                          cache_dir
@@ -56,18 +84,27 @@ cfg_if::cfg_if! {
 //         tools/                                          tools_dir
 //             inventory/                                  inventory_dir
 //                 node/                                   node_inventory_dir
-//                     node-v4.8.4-linux-x64.tar.gz        node_distro_file_name("4.8.4")
+//                     node-v4.8.4-linux-x64.tar.gz        node_distro_file_name("4.8.4", "x64")
 //                     node-v4.8.4-npm                     node_npm_version_file("4.8.4")
 //                     ...
 //                 packages/                               package_inventory_dir
 //                     ember-cli-3.7.1.tgz                 package_distro_file("ember-cli", "3.7.1")
 //                     ember-cli-3.7.1.shasum              package_distro_shasum("ember-cli", "3.7.1")
+//                 npm/                                    npm_inventory_dir
+//                     npm-6.7.0.tgz                       npm_archive_file("6.7.0")
+//                     npm-6.7.0.shasum                    npm_archive_shasum("6.7.0")
 //                 yarn/                                   yarn_inventory_dir
 //             image/                                      image_dir
 //                 node/                                   node_image_root_dir
-//                     10.13.0/
+//                     current -> 10.13.0/6.4.0/           node_image_current_link
+//                     10.13.0/                            node_image_version_dir("10.13.0")
 //                         6.4.0/                          node_image_dir("10.13.0", "6.4.0")
 //                             bin/                        node_image_bin_dir("10.13.0", "6.4.0")
+//                     x86/                                (with NOTION_ARCH=x86 on an x64 machine)
+//                         10.13.0/                        node_image_version_dir("10.13.0")
+//                 npm/                                    npm_image_root_dir
+//                     6.7.0/                              npm_image_dir("6.7.0")
+//                         bin/                            npm_image_bin_dir("6.7.0")
 //                 yarn/                                   yarn_image_root_dir
 //                     1.7.0/                              yarn_image_dir("1.7.0")
 //                 packages/                               package_image_root_dir
@@ -78,20 +115,27 @@ cfg_if::cfg_if! {
 //                     tsc.json                            user_tool_bin_config("tsc")
 //                 packages/                               user_package_dir
 //                     ember-cli.json                      user_package_config_file("ember-cli")
+//                     @angular%2fcli.json                 user_package_config_file("@angular/cli")
 //                 platform.json                           user_platform_file
+//                 platform.json.lock                      user_platform_lock_file
+//                 platform.json.bak                       user_platform_backup_file
+//                 platform.history                        user_platform_history_file
 //         notion                                          notion_file
 //         shim                                            shim_executable
 //         hooks.toml                                      user_hooks_file
 
 pub fn default_notion_home() -> Fallible<PathBuf> {
-    let home = dirs::home_dir().ok_or(ErrorDetails::NoHomeEnvironmentVar)?;
-    Ok(home.join(".notion"))
+    Ok(home_dir()?.join(".notion"))
 }
 
 pub fn archive_extension() -> String {
     String::from("tar.gz")
 }
 
+/// The Node archive formats that can be requested with the `NOTION_ARCHIVE_FORMAT`
+/// override. The xz-compressed tarballs are smaller downloads.
+pub const SUPPORTED_ARCHIVE_FORMATS: [&'static str; 2] = ["tar.gz", "tar.xz"];
+
 pub fn node_image_bin_dir(node: &str, npm: &str) -> Fallible<PathBuf> {
     Ok(node_image_dir(node, npm)?.join("bin"))
 }
@@ -112,3 +156,9 @@ pub fn shim_executable() -> Fallible<PathBuf> {
 pub fn create_file_symlink(src: PathBuf, dst: PathBuf) -> Result<(), io::Error> {
     unix::fs::symlink(src, dst)
 }
+
+/// Create a symlink to a directory. The `dst` path will be a symbolic link pointing
+/// to the `src` directory. Unix makes no distinction between file and directory links.
+pub fn create_dir_symlink(src: PathBuf, dst: PathBuf) -> Result<(), io::Error> {
+    unix::fs::symlink(src, dst)
+}
@@ -14,21 +14,30 @@ use notion_fail::Fallible;
 use super::{node_image_dir, notion_home, shim_dir};
 
 // These are taken from: https://nodejs.org/dist/index.json and are used
-// by `path::archive_root_dir` to determine the root directory of the
+// by `path::node_archive_root_dir_name` to determine the root directory of the
 // contents of a Node installer archive.
 
+/// The OS component of a Node distribution zip file's name.
 pub const OS: &'static str = "win";
 
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86")] {
+        /// The system architecture component of a Node distribution zip file's name.
         pub const ARCH: &'static str = "x86";
     } else if #[cfg(target_arch = "x86_64")] {
+        /// The system architecture component of a Node distribution zip file's name.
         pub const ARCH: &'static str = "x64";
     } else {
         compile_error!("Unsupported target_arch variant of Windows (expected 'x86' or 'x64').");
     }
 }
 
+/// Detects the architecture of the machine at runtime, which on Windows is always
+/// the compile-time `ARCH`.
+pub fn native_arch() -> &'static str {
+    ARCH
+}
+
 // C:\Users\johndoe\AppData\Local\
 //     Notion\
 //         cache\                                          cache_dir
@@ -49,12 +58,21 @@ cfg_if::cfg_if! {
 //                 packages\                               package_inventory_dir
 //                     ember-cli-3.7.1.tgz                 package_distro_file("ember-cli", "3.7.1")
 //                     ember-cli-3.7.1.shasum              package_distro_shasum("ember-cli", "3.7.1")
+//                 npm\                                    npm_inventory_dir
+//                     npm-6.7.0.tgz                       npm_archive_file("6.7.0")
+//                     npm-6.7.0.shasum                    npm_archive_shasum("6.7.0")
 //                 yarn\                                   yarn_inventory_dir
 //             image\                                      image_dir
 //                 node\                                   node_image_root_dir
-//                     10.13.0\
+//                     current -> 10.13.0\6.4.0\           node_image_current_link
+//                     10.13.0\                            node_image_version_dir("10.13.0")
 //                         6.4.0\                          node_image_dir("10.13.0", "6.4.0")
 //                                                         node_image_bin_dir("10.13.0", "6.4.0")
+//                     x86\                                (with NOTION_ARCH=x86 on an x64 machine)
+//                         10.13.0\                        node_image_version_dir("10.13.0")
+//                 npm\                                    npm_image_root_dir
+//                     6.7.0\                              npm_image_dir("6.7.0")
+//                         bin\                            npm_image_bin_dir("6.7.0")
 //                 yarn\                                   yarn_image_root_dir
 //                     1.7.0\                              yarn_image_dir("1.7.0")
 //                 packages\                               package_image_root_dir
@@ -65,7 +83,11 @@ cfg_if::cfg_if! {
 //                     tsc.json                            user_tool_bin_config("tsc")
 //                 packages\                               user_package_dir
 //                     ember-cli.json                      user_package_config_file("ember-cli")
+//                     @angular%2fcli.json                 user_package_config_file("@angular/cli")
 //                 platform.json                           user_platform_file
+//                 platform.json.lock                      user_platform_lock_file
+//                 platform.json.bak                       user_platform_backup_file
+//                 platform.history                        user_platform_history_file
 //         notion.exe                                      notion_file
 //         shim.exe                                        shim_executable
 //         hooks.toml                                      user_hooks_file
@@ -79,6 +101,10 @@ pub fn archive_extension() -> String {
     String::from("zip")
 }
 
+/// The Node archive formats that can be requested with the `NOTION_ARCHIVE_FORMAT`
+/// override.
+pub const SUPPORTED_ARCHIVE_FORMATS: [&'static str; 1] = ["zip"];
+
 pub fn node_image_bin_dir(node: &str, npm: &str) -> Fallible<PathBuf> {
     node_image_dir(node, npm)
 }
@@ -104,3 +130,14 @@ pub fn create_file_symlink(src: PathBuf, dst: PathBuf) -> Result<(), io::Error>
     #[cfg(feature = "universal-docs")]
     unimplemented!()
 }
+
+/// Create a symlink to a directory. The `dst` path will be a symbolic link pointing
+/// to the `src` directory. Windows needs a different kind of link for directories.
+pub fn create_dir_symlink(src: PathBuf, dst: PathBuf) -> Result<(), io::Error> {
+    #[cfg(windows)]
+    return windows::fs::symlink_dir(src, dst);
+
+    // "universal-docs" is built on a Unix machine, so we can't include Windows-specific libs
+    #[cfg(feature = "universal-docs")]
+    unimplemented!()
+}
@@ -1,13 +1,17 @@
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 use envoy;
 use semver::Version;
 
 use crate::distro::node::{load_default_npm_version, NodeVersion};
+use crate::env::{tool_version, NODE_VERSION, NPM_VERSION, YARN_VERSION};
+use crate::error::ErrorDetails;
 use crate::path;
 use crate::session::Session;
-use notion_fail::{Fallible, ResultExt};
+use crate::version::VersionSpec;
+use notion_fail::{throw, Fallible, ResultExt};
 
 /// A specification of tool versions needed for a platform
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -21,41 +25,187 @@ pub struct PlatformSpec {
 }
 
 impl PlatformSpec {
+    /// Produces the platform specified by the `NOTION_NODE`, `NOTION_NPM`, and
+    /// `NOTION_YARN` environment variables, if any of them are set.
+    pub fn from_env() -> Fallible<Option<PlatformSpec>> {
+        PlatformSpec::from_versions(
+            tool_version(NODE_VERSION),
+            tool_version(NPM_VERSION),
+            tool_version(YARN_VERSION),
+        )
+    }
+
+    /// Produces a platform from version strings, if any are given. The Node version
+    /// is required if either of the others is given.
+    fn from_versions(
+        node: Option<String>,
+        npm: Option<String>,
+        yarn: Option<String>,
+    ) -> Fallible<Option<PlatformSpec>> {
+        let node = match node {
+            Some(node) => node,
+            None if npm.is_none() && yarn.is_none() => return Ok(None),
+            None => throw!(ErrorDetails::NoEnvNodeVersion),
+        };
+
+        let parse_opt = |version: Option<String>| match version {
+            Some(version) => VersionSpec::parse_version(version).map(Some),
+            None => Ok(None),
+        };
+
+        Ok(Some(PlatformSpec {
+            node_runtime: VersionSpec::parse_version(node)?,
+            npm: parse_opt(npm)?,
+            yarn: parse_opt(yarn)?,
+        }))
+    }
+
+    /// Ensures that this platform's tools are fetched, and produces the image to run
+    /// them with. A Node archive that is already in the inventory isn't unpacked
+    /// again, so this fails if its image directory has since been removed, rather
+    /// than producing an image whose `PATH` can't find Node.
     pub fn checkout(&self, session: &mut Session) -> Fallible<Image> {
         session.ensure_node(&self.node_runtime)?;
 
+        let bundled_npm = load_default_npm_version(&self.node_runtime)?;
+        let npm = self.standalone_npm(&bundled_npm);
+        if let Some(ref npm_version) = npm {
+            session.ensure_npm(npm_version)?;
+        }
+
         if let Some(ref yarn_version) = self.yarn {
             session.ensure_yarn(yarn_version)?;
         }
 
+        let node = NodeVersion::new(self.node_runtime.clone(), bundled_npm);
+
+        let node_str = node.runtime().to_string();
+        if !path::node_image_bin_dir(&node_str, &node.npm().to_string())?.is_dir() {
+            throw!(node_not_fetched(node.runtime()));
+        }
+
         Ok(Image {
-            node: NodeVersion {
-                runtime: self.node_runtime.clone(),
-                npm: match self.npm {
-                    Some(ref version) => version.clone(),
-                    None => load_default_npm_version(&self.node_runtime)?,
-                },
-            },
+            node,
+            npm,
             yarn: self.yarn.clone(),
         })
     }
+
+    /// Returns the pinned npm version if it differs from `bundled_npm`, the npm
+    /// bundled with this platform's Node, in which case it runs from its own image.
+    pub(crate) fn standalone_npm(&self, bundled_npm: &Version) -> Option<Version> {
+        self.npm.clone().filter(|npm| npm != bundled_npm)
+    }
+
+    /// Returns the full Node version of this platform, if the npm version is pinned.
+    pub fn node_version(&self) -> Option<NodeVersion> {
+        self.npm
+            .as_ref()
+            .map(|npm| NodeVersion::new(self.node_runtime.clone(), npm.clone()))
+    }
+
+    /// Returns the major version of this platform's Node, e.g. `10` for v10.13.0,
+    /// for grouping Node versions by release line.
+    pub fn node_line(&self) -> u64 {
+        self.node_runtime.major
+    }
+
+    /// Returns the major and minor versions of this platform's Node, e.g. `(10, 13)`
+    /// for v10.13.0.
+    pub fn node_minor_line(&self) -> (u64, u64) {
+        (self.node_runtime.major, self.node_runtime.minor)
+    }
+
+    /// Produces a summary of this platform's versions for display to the user, e.g.:
+    ///
+    /// ```text
+    /// node: v10.13.0 (npm: v6.4.0)
+    /// yarn: v1.7.0
+    /// ```
+    ///
+    /// The npm version and the Yarn line are left out if they aren't pinned.
+    pub fn display_versions(&self) -> String {
+        let mut summary = format!("node: v{}", self.node_runtime);
+        if let Some(ref npm) = self.npm {
+            summary.push_str(&format!(" (npm: v{})", npm));
+        }
+        if let Some(ref yarn) = self.yarn {
+            summary.push_str(&format!("\nyarn: v{}", yarn));
+        }
+        summary
+    }
+
+    /// Produces a modified version of the current `PATH` environment variable that
+    /// will find the executables for this platform's Node, npm, and Yarn versions in
+    /// their installation directories. Unlike `checkout`, this doesn't fetch anything,
+    /// so the tools must already be installed.
+    pub fn to_env_path(&self) -> Fallible<OsString> {
+        let node_str = self.node_runtime.to_string();
+        if !path::node_npm_version_file(&node_str)?.is_file() {
+            throw!(node_not_fetched(&self.node_runtime));
+        }
+
+        let bundled_npm = load_default_npm_version(&self.node_runtime)?;
+        if !path::node_image_bin_dir(&node_str, &bundled_npm.to_string())?.is_dir() {
+            throw!(node_not_fetched(&self.node_runtime));
+        }
+
+        let npm = self.standalone_npm(&bundled_npm);
+        if let Some(ref npm) = npm {
+            if !path::npm_image_bin_dir(&npm.to_string())?.is_dir() {
+                throw!(no_toolchain("npm"));
+            }
+        }
+
+        if let Some(ref yarn) = self.yarn {
+            if !path::yarn_image_bin_dir(&yarn.to_string())?.is_dir() {
+                throw!(no_toolchain("yarn"));
+            }
+        }
+
+        let image = Image {
+            node: NodeVersion::new(self.node_runtime.clone(), bundled_npm),
+            npm,
+            yarn: self.yarn.clone(),
+        };
+
+        image.path()
+    }
+}
+
+fn node_not_fetched(version: &Version) -> ErrorDetails {
+    ErrorDetails::NodeNotFetched {
+        version: version.to_string(),
+    }
+}
+
+fn no_toolchain(shim_name: &str) -> ErrorDetails {
+    ErrorDetails::NoToolChain {
+        shim_name: shim_name.to_string(),
+    }
 }
 
 /// A platform image.
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct Image {
-    /// The pinned version of Node.
+    /// The pinned version of Node, along with the npm bundled with it.
     pub node: NodeVersion,
+    /// The pinned version of npm, if it differs from the npm bundled with Node.
+    pub npm: Option<Version>,
     /// The pinned version of Yarn, if any.
     pub yarn: Option<Version>,
 }
 
 impl Image {
     pub fn bins(&self) -> Fallible<Vec<PathBuf>> {
-        let node_str = self.node.runtime.to_string();
-        let npm_str = self.node.npm.to_string();
-        // ISSUE(#292): Install npm, and handle using that
-        let mut bins = vec![path::node_image_bin_dir(&node_str, &npm_str)?];
+        let mut bins = Vec::new();
+        // a standalone npm must come before the Node image, to shadow the bundled npm
+        if let Some(ref npm) = self.npm {
+            bins.push(path::npm_image_bin_dir(&npm.to_string())?);
+        }
+        let node_str = self.node.runtime().to_string();
+        let npm_str = self.node.npm().to_string();
+        bins.push(path::node_image_bin_dir(&node_str, &npm_str)?);
         if let Some(ref yarn) = self.yarn {
             let yarn_str = yarn.to_string();
             bins.push(path::yarn_image_bin_dir(&yarn_str)?);
@@ -80,6 +230,91 @@ impl Image {
     }
 }
 
+/// Where the version of a tool in a `ResolvedPlatform` comes from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// Set by the `NOTION_NODE`, `NOTION_NPM`, or `NOTION_YARN` environment variables.
+    Environment,
+    /// Pinned in the project's `package.json`.
+    Project,
+    /// Set as the user's default with `notion install`.
+    User,
+    /// Not pinned, so the version bundled with Node is used (for npm).
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Source::Environment => "environment",
+            Source::Project => "project",
+            Source::User => "user",
+            Source::Default => "default",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The platform in effect for a directory, along with where each tool's version
+/// comes from, e.g. for an editor integration to report that Node is pinned by the
+/// project while npm is the one bundled with Node.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedPlatform {
+    pub platform: PlatformSpec,
+    pub node: Source,
+    pub npm: Source,
+    /// The source of the Yarn version, if there is one.
+    pub yarn: Option<Source>,
+}
+
+impl ResolvedPlatform {
+    /// Attributes a platform taken from the given source. A platform that doesn't pin
+    /// npm uses the version bundled with Node.
+    pub(crate) fn new(platform: PlatformSpec, source: Source) -> Self {
+        ResolvedPlatform {
+            node: source,
+            npm: if platform.npm.is_some() {
+                source
+            } else {
+                Source::Default
+            },
+            yarn: platform.yarn.as_ref().map(|_| source),
+            platform,
+        }
+    }
+
+    /// Takes the platform from the first source that has one: the versions set in the
+    /// environment, then the project, then the user. A lower source is only consulted
+    /// if the ones above it are empty, since e.g. resolving a project's `.nvmrc` may
+    /// fetch the Node index.
+    pub(crate) fn layer<P, U>(
+        env: Option<PlatformSpec>,
+        project: P,
+        user: U,
+    ) -> Fallible<Option<ResolvedPlatform>>
+    where
+        P: FnOnce() -> Fallible<Option<ResolvedPlatform>>,
+        U: FnOnce() -> Fallible<Option<PlatformSpec>>,
+    {
+        if let Some(platform) = env {
+            return Ok(Some(ResolvedPlatform::new(platform, Source::Environment)));
+        }
+
+        if let Some(resolved) = project()? {
+            return Ok(Some(resolved));
+        }
+
+        Ok(user()?.map(|platform| ResolvedPlatform::new(platform, Source::User)))
+    }
+}
+
+/// Resolves the platform in effect for the given directory, the same way
+/// `Session::current_platform` does for the current directory. Returns `None` if
+/// there is no platform at all.
+pub fn resolve_effective_platform(cwd: &Path) -> Fallible<Option<ResolvedPlatform>> {
+    Session::for_dir(cwd).resolved_platform()
+}
+
 /// A lightweight namespace type representing the system environment, i.e. the environment
 /// with Notion removed.
 pub struct System;
@@ -118,18 +353,222 @@ impl System {
 mod test {
 
     use super::*;
+    use crate::distro::node::save_default_npm_version;
+    use crate::path::tests::with_temp_home;
     use crate::path::{notion_home, shim_dir};
     use semver::Version;
     use std;
+    use std::fs::create_dir_all;
     use std::path::PathBuf;
 
     // Since unit tests are run in parallel, tests that modify the PATH environment variable are subject to race conditions
     // To prevent that, ensure that all tests that rely on PATH are run in serial by adding them to this meta-test
     #[test]
     fn test_paths() {
-        test_image_path();
-        test_system_path();
-        test_system_enabled_path();
+        with_temp_home(|_| {
+            test_image_path();
+            test_system_path();
+            test_system_enabled_path();
+        });
+    }
+
+    #[test]
+    fn test_resolved_platform_sources() {
+        let full = PlatformSpec {
+            node_runtime: Version::parse("10.13.0").unwrap(),
+            npm: Some(Version::parse("6.4.0").unwrap()),
+            yarn: Some(Version::parse("1.7.0").unwrap()),
+        };
+        let resolved = ResolvedPlatform::new(full.clone(), Source::Project);
+        assert_eq!(resolved.platform, full);
+        assert_eq!(resolved.node, Source::Project);
+        assert_eq!(resolved.npm, Source::Project);
+        assert_eq!(resolved.yarn, Some(Source::Project));
+
+        let node_only = PlatformSpec {
+            node_runtime: Version::parse("10.13.0").unwrap(),
+            npm: None,
+            yarn: None,
+        };
+        let resolved = ResolvedPlatform::new(node_only, Source::User);
+        assert_eq!(resolved.node, Source::User);
+        assert_eq!(resolved.npm, Source::Default);
+        assert_eq!(resolved.yarn, None);
+    }
+
+    fn platform(node: &str) -> PlatformSpec {
+        PlatformSpec {
+            node_runtime: Version::parse(node).unwrap(),
+            npm: None,
+            yarn: None,
+        }
+    }
+
+    fn unreachable_layer<T>() -> Fallible<Option<T>> {
+        panic!("a lower layer was consulted")
+    }
+
+    #[test]
+    fn test_layer_env_over_project_and_user() {
+        let resolved = ResolvedPlatform::layer(
+            Some(platform("10.13.0")),
+            unreachable_layer,
+            unreachable_layer,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(resolved.platform, platform("10.13.0"));
+        assert_eq!(resolved.node, Source::Environment);
+    }
+
+    #[test]
+    fn test_layer_project_over_user() {
+        let resolved = ResolvedPlatform::layer(
+            None,
+            || {
+                Ok(Some(ResolvedPlatform::new(
+                    platform("8.15.1"),
+                    Source::Project,
+                )))
+            },
+            unreachable_layer,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(resolved.platform, platform("8.15.1"));
+        assert_eq!(resolved.node, Source::Project);
+    }
+
+    #[test]
+    fn test_layer_user() {
+        let resolved = ResolvedPlatform::layer(None, || Ok(None), || Ok(Some(platform("6.16.0"))))
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.platform, platform("6.16.0"));
+        assert_eq!(resolved.node, Source::User);
+        assert_eq!(resolved.npm, Source::Default);
+
+        assert_eq!(
+            ResolvedPlatform::layer(None, || Ok(None), || Ok(None)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_display_versions() {
+        let full = PlatformSpec {
+            node_runtime: Version::parse("10.13.0").unwrap(),
+            npm: Some(Version::parse("6.4.0").unwrap()),
+            yarn: Some(Version::parse("1.7.0").unwrap()),
+        };
+        assert_eq!(
+            full.display_versions(),
+            "node: v10.13.0 (npm: v6.4.0)\nyarn: v1.7.0"
+        );
+
+        let node_only = PlatformSpec {
+            node_runtime: Version::parse("10.13.0").unwrap(),
+            npm: None,
+            yarn: None,
+        };
+        assert_eq!(node_only.display_versions(), "node: v10.13.0");
+    }
+
+    #[test]
+    fn test_node_line() {
+        let platform = PlatformSpec {
+            node_runtime: Version::parse("10.13.0").unwrap(),
+            npm: None,
+            yarn: None,
+        };
+        assert_eq!(platform.node_line(), 10);
+        assert_eq!(platform.node_minor_line(), (10, 13));
+    }
+
+    #[test]
+    fn test_standalone_npm() {
+        let v640 = Version::parse("6.4.0").unwrap();
+        let v670 = Version::parse("6.7.0").unwrap();
+        let mut platform = PlatformSpec {
+            node_runtime: Version::parse("10.13.0").unwrap(),
+            npm: None,
+            yarn: None,
+        };
+        assert_eq!(platform.standalone_npm(&v640), None);
+
+        platform.npm = Some(v640.clone());
+        assert_eq!(platform.standalone_npm(&v640), None);
+
+        platform.npm = Some(v670.clone());
+        assert_eq!(platform.standalone_npm(&v640), Some(v670));
+    }
+
+    #[test]
+    fn test_from_versions() {
+        assert_eq!(PlatformSpec::from_versions(None, None, None).unwrap(), None);
+
+        assert_eq!(
+            PlatformSpec::from_versions(
+                Some("10.13.0".to_string()),
+                None,
+                Some("1.7.0".to_string())
+            )
+            .unwrap(),
+            Some(PlatformSpec {
+                node_runtime: Version::parse("10.13.0").unwrap(),
+                npm: None,
+                yarn: Some(Version::parse("1.7.0").unwrap()),
+            })
+        );
+
+        // the Node version is required if any version is set
+        assert!(PlatformSpec::from_versions(None, Some("6.4.0".to_string()), None).is_err());
+
+        assert!(
+            PlatformSpec::from_versions(Some("not-a-version".to_string()), None, None).is_err()
+        );
+    }
+
+    #[test]
+    fn test_env_path_requires_installed_images() {
+        with_temp_home(|_| {
+            let check = |platform: &PlatformSpec, expected: &str| match platform.to_env_path() {
+                Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                    Some(ErrorDetails::NodeNotFetched { version }) if expected == "node" => {
+                        assert_eq!(version, "10.15.3")
+                    }
+                    Some(ErrorDetails::NoToolChain { shim_name }) => {
+                        assert_eq!(shim_name, expected)
+                    }
+                    other => panic!("unexpected error: {:?}", other),
+                },
+                Ok(_) => panic!("built a PATH without the {} image", expected),
+            };
+            let node = Version::parse("10.15.3").unwrap();
+            let platform = PlatformSpec {
+                node_runtime: node.clone(),
+                npm: None,
+                yarn: Some(Version::parse("1.12.3").unwrap()),
+            };
+
+            // without the npm version file, the Node version was never fetched
+            check(&platform, "node");
+            save_default_npm_version(&node, &Version::parse("6.4.1").unwrap()).unwrap();
+            check(&platform, "node");
+
+            create_dir_all(path::node_image_bin_dir("10.15.3", "6.4.1").unwrap()).unwrap();
+            check(&platform, "yarn");
+
+            create_dir_all(path::yarn_image_bin_dir("1.12.3").unwrap()).unwrap();
+            assert!(platform.to_env_path().is_ok());
+
+            // an npm pinned apart from the bundled one needs its own image
+            let pinned_npm = PlatformSpec {
+                npm: Some(Version::parse("6.7.0").unwrap()),
+                ..platform
+            };
+            check(&pinned_npm, "npm");
+        });
     }
 
     #[cfg(unix)]
@@ -166,10 +605,8 @@ mod test {
         let v643 = Version::parse("6.4.3").unwrap();
 
         let no_yarn_image = Image {
-            node: NodeVersion {
-                runtime: v123.clone(),
-                npm: v643.clone(),
-            },
+            node: NodeVersion::new(v123.clone(), v643.clone()),
+            npm: None,
             yarn: None,
         };
 
@@ -179,10 +616,8 @@ mod test {
         );
 
         let with_yarn_image = Image {
-            node: NodeVersion {
-                runtime: v123.clone(),
-                npm: v643.clone(),
-            },
+            node: NodeVersion::new(v123.clone(), v643.clone()),
+            npm: None,
             yarn: Some(v457.clone()),
         };
 
@@ -193,6 +628,29 @@ mod test {
                 expected_node_bin, expected_yarn_bin
             ),
         );
+
+        let npm_bin = notion_home()
+            .unwrap()
+            .join("tools")
+            .join("image")
+            .join("npm")
+            .join("6.7.0")
+            .join("bin");
+        let expected_npm_bin = npm_bin.as_path().to_str().unwrap();
+
+        let with_npm_image = Image {
+            node: NodeVersion::new(v123.clone(), v643.clone()),
+            npm: Some(Version::parse("6.7.0").unwrap()),
+            yarn: None,
+        };
+
+        assert_eq!(
+            with_npm_image.path().unwrap().into_string().unwrap(),
+            format!(
+                "{}:{}:/usr/bin:/blah:/doesnt/matter/bin",
+                expected_npm_bin, expected_node_bin
+            ),
+        );
     }
 
     #[cfg(windows)]
@@ -232,10 +690,8 @@ mod test {
         let v643 = Version::parse("6.4.3").unwrap();
 
         let no_yarn_image = Image {
-            node: NodeVersion {
-                runtime: v123.clone(),
-                npm: v643.clone(),
-            },
+            node: NodeVersion::new(v123.clone(), v643.clone()),
+            npm: None,
             yarn: None,
         };
 
@@ -245,10 +701,8 @@ mod test {
         );
 
         let with_yarn_image = Image {
-            node: NodeVersion {
-                runtime: v123.clone(),
-                npm: v643.clone(),
-            },
+            node: NodeVersion::new(v123.clone(), v643.clone()),
+            npm: None,
             yarn: Some(v457.clone()),
         };
 
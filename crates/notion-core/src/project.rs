@@ -4,6 +4,7 @@
 use std::collections::HashMap;
 use std::env;
 use std::ffi::OsStr;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
@@ -14,6 +15,8 @@ use crate::distro::node::{load_default_npm_version, NodeVersion};
 use crate::error::ErrorDetails;
 use crate::manifest::{serial, Manifest};
 use crate::platform::PlatformSpec;
+use crate::style::quiet;
+use crate::version::VersionSpec;
 use notion_fail::{throw, Fallible, ResultExt};
 
 fn is_node_root(dir: &Path) -> bool {
@@ -53,20 +56,33 @@ impl LazyDependentBins {
 
 /// A lazily loaded Project
 pub struct LazyProject {
+    /// The directory to look for the project from, if not the current directory.
+    dir: Option<PathBuf>,
     project: LazyCell<Option<Rc<Project>>>,
 }
 
 impl LazyProject {
     pub fn new() -> Self {
         LazyProject {
+            dir: None,
+            project: LazyCell::new(),
+        }
+    }
+
+    /// Constructs a `LazyProject` for the project containing `dir`, rather than the
+    /// current directory.
+    pub fn for_dir(dir: &Path) -> Self {
+        LazyProject {
+            dir: Some(dir.to_path_buf()),
             project: LazyCell::new(),
         }
     }
 
     pub fn get(&self) -> Fallible<Option<Rc<Project>>> {
-        let project = self
-            .project
-            .try_borrow_with(|| Project::for_current_dir())?;
+        let project = self.project.try_borrow_with(|| match self.dir {
+            Some(ref dir) => Project::for_dir(dir),
+            None => Project::for_current_dir(),
+        })?;
         Ok(project.clone())
     }
 }
@@ -87,7 +103,7 @@ impl Project {
     }
 
     /// Returns the Node project for the input directory, if any.
-    fn for_dir(base_dir: &Path) -> Fallible<Option<Rc<Project>>> {
+    pub(crate) fn for_dir(base_dir: &Path) -> Fallible<Option<Rc<Project>>> {
         let mut dir = base_dir.clone();
         while !is_project_root(dir) {
             dir = match dir.parent() {
@@ -120,6 +136,41 @@ impl Project {
         &self.manifest
     }
 
+    /// Returns the Node version requirement from the `engines` section of the
+    /// project manifest, if any. A requirement that Notion doesn't understand is
+    /// ignored with a warning, so that the user's platform is used instead.
+    pub fn engines_node(&self) -> Option<VersionSpec> {
+        let engines = self.manifest.engines()?;
+        match VersionSpec::parse(&engines) {
+            // only packages have dist-tags, so a word isn't a Node version either
+            Ok(VersionSpec::Tag(_)) | Err(_) => {
+                eprintln!(
+                    "Warning: ignoring the engines.node requirement '{}' in {}, which Notion doesn't understand",
+                    engines,
+                    self.package_file().display()
+                );
+                None
+            }
+            Ok(spec) => Some(spec),
+        }
+    }
+
+    /// Returns the Node version from the project's `.nvmrc` file, if any, so that
+    /// projects set up for nvm work without a Notion pin. A `.nvmrc` that doesn't
+    /// hold a version Notion understands is ignored with a warning.
+    pub fn nvmrc_node(&self) -> Option<VersionSpec> {
+        let path = self.project_root.join(".nvmrc");
+        let src = read_to_string(&path).ok()?;
+        let spec = parse_nvmrc(&src);
+        if spec.is_none() {
+            eprintln!(
+                "Warning: ignoring {}, which doesn't contain a Node version Notion understands",
+                path.display()
+            );
+        }
+        spec
+    }
+
     /// Returns the path to the `package.json` file for this project.
     pub fn package_file(&self) -> PathBuf {
         self.project_root.join("package.json")
@@ -179,26 +230,29 @@ impl Project {
     pub fn pin_node(&self, node_version: &NodeVersion) -> Fallible<()> {
         // prevent writing the npm version if it is equal to the default version
 
-        let npm_str = load_default_npm_version(&node_version.runtime)
+        let npm_str = load_default_npm_version(node_version.runtime())
             .ok()
             .and_then(|default| {
-                if node_version.npm == default {
+                if *node_version.npm() == default {
                     None
                 } else {
-                    Some(node_version.npm.to_string())
+                    Some(node_version.npm().to_string())
                 }
             });
 
         let toolchain = serial::ToolchainSpec::new(
-            node_version.runtime.to_string(),
+            node_version.runtime().to_string(),
             npm_str,
             self.manifest().yarn_str().clone(),
         );
         Manifest::update_toolchain(toolchain, self.package_file())?;
-        println!(
-            "Pinned node version {} (with npm {}) in package.json",
-            node_version.runtime, node_version.npm
-        );
+        if !quiet() {
+            println!(
+                "Pinned node version {} (with npm {}) in package.json",
+                node_version.runtime(),
+                node_version.npm()
+            );
+        }
         Ok(())
     }
 
@@ -211,7 +265,9 @@ impl Project {
                 Some(yarn_version.to_string()),
             );
             Manifest::update_toolchain(toolchain, self.package_file())?;
-            println!("Pinned yarn version {} in package.json", yarn_version);
+            if !quiet() {
+                println!("Pinned yarn version {} in package.json", yarn_version);
+            }
         } else {
             throw!(ErrorDetails::NoPinnedNodeVersion);
         }
@@ -227,7 +283,9 @@ impl Project {
                 self.manifest().yarn_str().clone(),
             );
             Manifest::update_toolchain(toolchain, self.package_file())?;
-            println!("Pinned npm version {} in package.json", npm_version);
+            if !quiet() {
+                println!("Pinned npm version {} in package.json", npm_version);
+            }
         } else {
             throw!(ErrorDetails::NoPinnedNodeVersion);
         }
@@ -235,6 +293,26 @@ impl Project {
     }
 }
 
+/// Parses the Node version in an `.nvmrc` file: a version (`v10.15.3`), a partial
+/// version (`10`), or one of nvm's aliases for the latest (`node`) or latest LTS
+/// (`lts/*`) version.
+fn parse_nvmrc(src: &str) -> Option<VersionSpec> {
+    let line = src
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+
+    match line {
+        "node" | "stable" => Some(VersionSpec::Latest),
+        "lts/*" => Some(VersionSpec::Lts),
+        _ => match VersionSpec::parse(line.trim_start_matches('v')).ok()? {
+            // nvm has no dist-tags, so this is an alias Notion doesn't know
+            VersionSpec::Tag(_) => None,
+            spec => Some(spec),
+        },
+    }
+}
+
 // unit tests
 
 #[cfg(test)]
@@ -243,7 +321,9 @@ pub mod tests {
     use std::ffi::OsStr;
     use std::path::PathBuf;
 
-    use crate::project::Project;
+    use crate::project::{parse_nvmrc, Project};
+    use crate::version::VersionSpec;
+    use semver::Version;
 
     fn fixture_path(fixture_dir: &str) -> PathBuf {
         let mut cargo_manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -301,4 +381,62 @@ pub mod tests {
 
         assert!(test_project.get_dependency_path(&"foo".to_string()) == expected_path);
     }
+
+    #[test]
+    fn engines_node() {
+        let project_path = fixture_path("engines");
+        let test_project = Project::for_dir(&project_path).unwrap().unwrap();
+        let requirement = match test_project.engines_node() {
+            Some(VersionSpec::Semver(requirement)) => requirement,
+            _ => panic!("expected a version requirement from engines.node"),
+        };
+        assert!(requirement.matches(&Version::parse("10.15.3").unwrap()));
+        assert!(!requirement.matches(&Version::parse("11.10.1").unwrap()));
+
+        let basic_path = fixture_path("basic");
+        let basic_project = Project::for_dir(&basic_path).unwrap().unwrap();
+        assert!(basic_project.engines_node().is_none());
+    }
+
+    #[test]
+    fn engines_node_invalid() {
+        let project_path = fixture_path("engines_invalid");
+        let test_project = Project::for_dir(&project_path).unwrap().unwrap();
+        assert!(test_project.engines_node().is_none());
+    }
+
+    #[test]
+    fn test_parse_nvmrc() {
+        assert_eq!(
+            parse_nvmrc("v10.15.3\n"),
+            Some(VersionSpec::Exact(Version::parse("10.15.3").unwrap()))
+        );
+        assert_eq!(parse_nvmrc("node"), Some(VersionSpec::Latest));
+        assert_eq!(parse_nvmrc("lts/*\n"), Some(VersionSpec::Lts));
+        match parse_nvmrc("10") {
+            Some(VersionSpec::Semver(requirement)) => {
+                assert!(requirement.matches(&Version::parse("10.15.3").unwrap()));
+                assert!(!requirement.matches(&Version::parse("11.10.1").unwrap()));
+            }
+            _ => panic!("expected a version requirement from .nvmrc"),
+        }
+
+        assert_eq!(parse_nvmrc(""), None);
+        assert_eq!(parse_nvmrc("lts/dubnium"), None);
+        assert_eq!(parse_nvmrc("iojs"), None);
+    }
+
+    #[test]
+    fn nvmrc_node() {
+        let project_path = fixture_path("nvmrc");
+        let test_project = Project::for_dir(&project_path).unwrap().unwrap();
+        assert_eq!(
+            test_project.nvmrc_node(),
+            Some(VersionSpec::Exact(Version::parse("10.15.3").unwrap()))
+        );
+
+        let basic_path = fixture_path("basic");
+        let basic_project = Project::for_dir(&basic_path).unwrap().unwrap();
+        assert_eq!(basic_project.nvmrc_node(), None);
+    }
 }
@@ -5,18 +5,21 @@
 use std::rc::Rc;
 
 use crate::distro::node::NodeVersion;
-use crate::distro::package::{PackageVersion, UserTool};
+use crate::distro::package::{self, InstallPlan, PackageVersion, UserTool};
 use crate::distro::Fetched;
 use crate::error::ErrorDetails;
 use crate::hook::{HookConfig, LazyHookConfig, Publish};
 use crate::inventory::{FetchResolve, Inventory, LazyInventory};
-use crate::platform::PlatformSpec;
+use crate::path;
+use crate::platform::{PlatformSpec, ResolvedPlatform, Source};
 use crate::project::{LazyProject, Project};
+use crate::tool::ToolSpec;
 use crate::toolchain::LazyToolchain;
 use crate::version::VersionSpec;
 
 use std::ffi::OsStr;
 use std::fmt::{self, Display, Formatter};
+use std::path::Path;
 use std::process::exit;
 
 use crate::event::EventLog;
@@ -45,6 +48,7 @@ pub enum ActivityKind {
     Shim,
     Completions,
     Which,
+    Verify,
 }
 
 impl Display for ActivityKind {
@@ -70,6 +74,7 @@ impl Display for ActivityKind {
             &ActivityKind::Shim => "shim",
             &ActivityKind::Completions => "completions",
             &ActivityKind::Which => "which",
+            &ActivityKind::Verify => "verify",
         };
         f.write_str(s)
     }
@@ -102,16 +107,37 @@ impl Session {
         }
     }
 
+    /// Constructs a `Session` for the project containing `dir`, rather than the
+    /// current directory.
+    pub fn for_dir(dir: &Path) -> Session {
+        Session {
+            project: LazyProject::for_dir(dir),
+            ..Session::new()
+        }
+    }
+
     /// Produces a reference to the current Node project, if any.
     pub fn project(&self) -> Fallible<Option<Rc<Project>>> {
         self.project.get()
     }
 
+    /// Produces the platform to use: the versions set in the environment, if any,
+    /// or else the project platform (pinned or from its `.nvmrc`), or else the user
+    /// platform.
     pub fn current_platform(&self) -> Fallible<Option<Rc<PlatformSpec>>> {
-        match self.project_platform()? {
-            Some(platform) => Ok(Some(platform)),
-            None => self.user_platform(),
-        }
+        Ok(self
+            .resolved_platform()?
+            .map(|resolved| Rc::new(resolved.platform)))
+    }
+
+    /// Produces the platform to use, as `current_platform` does, along with where
+    /// each of its versions comes from.
+    pub fn resolved_platform(&self) -> Fallible<Option<ResolvedPlatform>> {
+        ResolvedPlatform::layer(
+            PlatformSpec::from_env()?,
+            || self.resolved_project_platform(),
+            || Ok(self.user_platform()?.map(|platform| (*platform).clone())),
+        )
     }
 
     pub fn user_platform(&self) -> Fallible<Option<Rc<PlatformSpec>>> {
@@ -121,14 +147,70 @@ impl Session {
             .map(|platform| Rc::new(platform.clone())))
     }
 
-    /// Returns the current project's pinned platform image, if any.
+    /// Returns the version of Node the current project asks for, if any: its
+    /// `engines.node` requirement, or else the version in its `.nvmrc`.
+    pub fn project_node_spec(&self) -> Fallible<Option<VersionSpec>> {
+        if let Some(ref project) = self.project()? {
+            if let Some(spec) = project.engines_node() {
+                return Ok(Some(spec));
+            }
+            return Ok(project.nvmrc_node());
+        }
+        Ok(None)
+    }
+
+    /// Returns the current project's platform image, if any: the one pinned in its
+    /// package.json, or else one for the Node version in its `.nvmrc`.
     pub fn project_platform(&self) -> Fallible<Option<Rc<PlatformSpec>>> {
+        Ok(self
+            .resolved_project_platform()?
+            .map(|resolved| Rc::new(resolved.platform)))
+    }
+
+    /// Produces the current project's platform, as `project_platform` does, along
+    /// with where each of its versions comes from.
+    fn resolved_project_platform(&self) -> Fallible<Option<ResolvedPlatform>> {
         if let Some(ref project) = self.project()? {
-            return Ok(project.platform());
+            if let Some(platform) = project.platform() {
+                return Ok(Some(ResolvedPlatform::new(
+                    (*platform).clone(),
+                    Source::Project,
+                )));
+            }
+            if let Some(spec) = project.nvmrc_node() {
+                let platform = self.nvmrc_platform(&spec)?;
+                return Ok(Some(ResolvedPlatform {
+                    node: Source::Project,
+                    npm: Source::Default,
+                    yarn: platform.yarn.as_ref().map(|_| Source::User),
+                    platform,
+                }));
+            }
         }
         Ok(None)
     }
 
+    /// Produces the platform for a project that names its Node version in `.nvmrc`
+    /// rather than pinning one. The version is resolved from the Node index, with
+    /// the npm bundled with it, and Yarn comes from the user platform.
+    fn nvmrc_platform(&self, spec: &VersionSpec) -> Fallible<PlatformSpec> {
+        let inventory = self.inventory.get()?;
+        let hooks = self.hooks.get()?;
+        let (node_runtime, npm) = inventory
+            .node
+            .resolve_version(spec, hooks.node.as_ref())?
+            .into_parts();
+        let yarn = self
+            .user_platform()?
+            .and_then(|platform| platform.yarn.clone());
+
+        Ok(PlatformSpec {
+            node_runtime,
+            npm: Some(npm),
+            yarn,
+        })
+    }
+
     /// Produces a reference to the current inventory.
     pub fn inventory(&self) -> Fallible<&Inventory> {
         self.inventory.get()
@@ -176,6 +258,16 @@ impl Session {
         Ok(())
     }
 
+    /// Ensures that a specific npm version has been fetched and unpacked into its own
+    /// image, separate from the npm bundled with Node
+    pub(crate) fn ensure_npm(&mut self, version: &Version) -> Fallible<()> {
+        if !path::npm_image_dir(&version.to_string())?.is_dir() {
+            self.fetch_npm(&VersionSpec::exact(version))?;
+        }
+
+        Ok(())
+    }
+
     /// Fetch and unpack a version of Node matching the input requirements.
     pub fn install_node(&mut self, version_spec: &VersionSpec) -> Fallible<()> {
         let node_distro = self.fetch_node(version_spec)?.into_version();
@@ -184,25 +276,36 @@ impl Session {
         Ok(())
     }
 
+    /// Fails if the user toolchain has no Node version. Yarn and npm can only be set
+    /// alongside one, so this is checked before fetching them.
+    fn ensure_user_node(&self) -> Fallible<()> {
+        if self.user_platform()?.is_none() {
+            throw!(ErrorDetails::NoUserNodeVersion);
+        }
+        Ok(())
+    }
+
     /// Fetch and unpack a version of Yarn matching the input requirements.
     pub fn install_yarn(&mut self, version_spec: &VersionSpec) -> Fallible<()> {
+        self.ensure_user_node()?;
         let yarn_distro = self.fetch_yarn(version_spec)?.into_version();
         let toolchain = self.toolchain.get_mut()?;
         toolchain.set_active_yarn(yarn_distro)?;
         Ok(())
     }
 
-    /// Fetch, unpack, and install a version of Npm matching the input requirements.
-    // ISSUE(#292): Install npm as part of the platform
+    /// Fetch and unpack a version of Npm matching the input requirements, and use it
+    /// instead of the npm bundled with the user's Node.
     pub fn install_npm(&mut self, version_spec: &VersionSpec) -> Fallible<()> {
-        let npm_version = self.install_package("npm".to_string(), version_spec)?;
+        self.ensure_user_node()?;
+        let npm_version = self.fetch_npm(version_spec)?.into_version().version;
         let toolchain = self.toolchain.get_mut()?;
         toolchain.set_active_npm(npm_version)?;
         Ok(())
     }
 
     /// Fetch, unpack, and install a package matching the input requirements.
-    pub fn install_package(&mut self, name: String, version: &VersionSpec) -> Fallible<Version> {
+    pub fn install_package(&mut self, name: String, version: &VersionSpec) -> Fallible<()> {
         // fetches and unpacks package
         let fetched_package = self.fetch_package(name, version)?;
         let package_version = fetched_package.version();
@@ -220,17 +323,51 @@ impl Session {
         //
         // If you specify an "engines" field, then npm will require that "node" be somewhere on that list. If "engines" is omitted, then npm will just assume that it works on node.
         let req_node_version = package_version.engines_spec()?;
-        let node_version = self.fetch_node(&req_node_version)?.into_version();
+        let (node_runtime, npm) = self
+            .fetch_node(&req_node_version)?
+            .into_version()
+            .into_parts();
 
         use_platform = Rc::new(PlatformSpec {
-            node_runtime: node_version.runtime,
-            npm: Some(node_version.npm),
+            node_runtime,
+            npm: Some(npm),
             yarn: None,
         });
 
         // finally, install the package
         package_version.install(&use_platform, self)?;
-        Ok(package_version.version.clone())
+        Ok(())
+    }
+
+    /// Works out what installing a package matching the input requirements would do.
+    /// The package and the Node version it would use are resolved from the registry
+    /// metadata and the Node index, so nothing is fetched or unpacked.
+    pub fn plan_package_install(
+        &self,
+        name: String,
+        version: &VersionSpec,
+    ) -> Fallible<InstallPlan> {
+        let inventory = self.inventory.get()?;
+        let hooks = self.hooks.get()?;
+        let distro = inventory
+            .packages
+            .resolve(name, version, hooks.package.as_ref())?;
+        let (node_runtime, npm) = inventory
+            .node
+            .resolve_version(&distro.engines_spec()?, hooks.node.as_ref())?
+            .into_parts();
+
+        let platform = PlatformSpec {
+            node_runtime,
+            npm: Some(npm),
+            yarn: None,
+        };
+        Ok(distro.install_plan(&platform))
+    }
+
+    /// Uninstall a package from the user toolchain.
+    pub fn uninstall_package(&mut self, name: &str) -> Fallible<()> {
+        package::uninstall(name)
     }
 
     /// Fetches a Node version matching the specified semantic versioning requirements.
@@ -251,13 +388,43 @@ impl Session {
             .fetch("yarn".to_string(), &version_spec, hooks.yarn.as_ref())
     }
 
+    /// Downloads the archive for a tool version matching the specified semantic
+    /// versioning requirements into the inventory without unpacking it, and returns
+    /// the resolved version.
+    pub fn fetch_archive(&mut self, tool: &ToolSpec) -> Fallible<Version> {
+        let inventory = self.inventory.get_mut()?;
+        let hooks = self.hooks.get()?;
+        match tool {
+            ToolSpec::Node(version) => {
+                inventory
+                    .node
+                    .fetch_archive("node".to_string(), version, hooks.node.as_ref())
+            }
+            ToolSpec::Yarn(version) => {
+                inventory
+                    .yarn
+                    .fetch_archive("yarn".to_string(), version, hooks.yarn.as_ref())
+            }
+            ToolSpec::Npm(version) => {
+                inventory
+                    .packages
+                    .fetch_archive("npm".to_string(), version, hooks.npm.as_ref())
+            }
+            ToolSpec::Package(name, version) => {
+                inventory
+                    .packages
+                    .fetch_archive(name.to_string(), version, hooks.package.as_ref())
+            }
+        }
+    }
+
     /// Fetches a Npm version matching the specified semantic versioning requirements.
     pub fn fetch_npm(&mut self, version_spec: &VersionSpec) -> Fallible<Fetched<PackageVersion>> {
         let inventory = self.inventory.get_mut()?;
         let hooks = self.hooks.get()?;
         inventory
             .packages
-            .fetch("npm".to_string(), version_spec, hooks.package.as_ref())
+            .fetch("npm".to_string(), version_spec, hooks.npm.as_ref())
     }
 
     /// Fetches a Package version matching the specified semantic versioning requirements.
@@ -305,7 +472,7 @@ impl Session {
             let hooks = self.hooks.get()?;
             let npm_version = inventory
                 .packages
-                .resolve("npm".to_string(), version_spec, hooks.package.as_ref())?
+                .resolve("npm".to_string(), version_spec, hooks.npm.as_ref())?
                 .version;
             project.pin_npm(&npm_version)?;
         } else {
@@ -17,7 +17,7 @@ if test $status != 0;
 end;
 "#;
 
-static SET_NOTION_HOME: &'static str = "set -x NOTION_HOME \"$HOME/.notion\"\n";
+static SET_NOTION_HOME: &'static str = "set -gx NOTION_HOME \"$HOME/.notion\"\n";
 static UNSET_NOTION_HOME: &'static str = "set -e NOTION_HOME\n";
 
 impl Shell for Fish {
@@ -28,19 +28,19 @@ impl Shell for Fish {
     fn compile_postscript(&self, postscript: &Postscript) -> String {
         match postscript {
             &Postscript::Activate(ref s) => {
-                let updated_path = format!("set -x PATH \"{}\"\n", s);
+                let updated_path = format!("set -gx PATH \"{}\"\n", s);
                 updated_path + STATUS_HANDLING + SET_NOTION_HOME
             }
             // ISSUE(#99): proper escaping
             &Postscript::Deactivate(ref s) => {
-                let updated_path = format!("set -x PATH \"{}\"\n", s);
+                let updated_path = format!("set -gx PATH \"{}\"\n", s);
                 updated_path + STATUS_HANDLING + UNSET_NOTION_HOME
             }
             &Postscript::ToolVersion {
                 ref tool,
                 ref version,
             } => format!(
-                "set -x NOTION_{}_VERSION {}\n",
+                "set -gx NOTION_{}_VERSION {}\n",
                 tool.to_ascii_uppercase(),
                 version
             ),
@@ -61,7 +61,7 @@ mod tests {
 
         assert_eq!(
             fish.compile_postscript(&Postscript::Deactivate("some:path".to_string())),
-            String::from("set -x PATH \"some:path\"\n")
+            String::from("set -gx PATH \"some:path\"\n")
                 + super::STATUS_HANDLING
                 + super::UNSET_NOTION_HOME
         );
@@ -71,7 +71,7 @@ mod tests {
             fish.compile_postscript(&Postscript::Deactivate(
                 "/path:/with:/single'quotes'".to_string()
             )),
-            String::from("set -x PATH \"/path:/with:/single'quotes'\"\n")
+            String::from("set -gx PATH \"/path:/with:/single'quotes'\"\n")
                 + super::STATUS_HANDLING
                 + super::UNSET_NOTION_HOME
         );
@@ -81,12 +81,12 @@ mod tests {
                 tool: "test".to_string(),
                 version: Version::parse("2.4.5").unwrap()
             }),
-            "set -x NOTION_TEST_VERSION 2.4.5\n"
+            "set -gx NOTION_TEST_VERSION 2.4.5\n"
         );
 
         assert_eq!(
             fish.compile_postscript(&Postscript::Activate("some:path".to_string())),
-            String::from("set -x PATH \"some:path\"\n")
+            String::from("set -gx PATH \"some:path\"\n")
                 + super::STATUS_HANDLING
                 + super::SET_NOTION_HOME
         );
@@ -13,9 +13,11 @@ use crate::env;
 
 mod bash;
 mod fish;
+mod powershell;
 
 pub(crate) use self::bash::Bash;
 pub(crate) use self::fish::Fish;
+pub(crate) use self::powershell::PowerShell;
 
 pub enum Postscript {
     Activate(String),
@@ -68,6 +70,8 @@ impl FromStr for CurrentShell {
         match src {
             "bash" => Ok(CurrentShell(Box::new(Bash { postscript_path }))),
             "fish" => Ok(CurrentShell(Box::new(Fish { postscript_path }))),
+            // Windows PowerShell is `powershell`, and PowerShell Core is `pwsh`
+            "powershell" | "pwsh" => Ok(CurrentShell(Box::new(PowerShell { postscript_path }))),
             _ => Err(ErrorDetails::UnrecognizedShell {
                 name: src.to_string(),
             }
@@ -0,0 +1,90 @@
+use std::path::{Path, PathBuf};
+
+use super::{Postscript, Shell};
+
+pub(crate) struct PowerShell {
+    pub(crate) postscript_path: PathBuf,
+}
+
+static SET_NOTION_HOME: &'static str = "$env:NOTION_HOME = \"$env:LOCALAPPDATA\\Notion\"\n";
+static UNSET_NOTION_HOME: &'static str =
+    "Remove-Item Env:\\NOTION_HOME -ErrorAction SilentlyContinue\n";
+
+/// Quotes a value as a PowerShell single-quoted string, in which nothing is
+/// interpolated and a single quote is escaped by doubling it.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl Shell for PowerShell {
+    fn postscript_path(&self) -> &Path {
+        &self.postscript_path
+    }
+
+    fn compile_postscript(&self, postscript: &Postscript) -> String {
+        match postscript {
+            &Postscript::Activate(ref s) => format!("$env:PATH = {}\n", quote(s)) + SET_NOTION_HOME,
+            &Postscript::Deactivate(ref s) => {
+                format!("$env:PATH = {}\n", quote(s)) + UNSET_NOTION_HOME
+            }
+            &Postscript::ToolVersion {
+                ref tool,
+                ref version,
+            } => format!(
+                "$env:NOTION_{}_VERSION = '{}'\n",
+                tool.to_ascii_uppercase(),
+                version
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semver::Version;
+    use std::str::FromStr;
+
+    use crate::shell::{CurrentShell, Postscript, Shell};
+
+    #[test]
+    fn test_compile_postscript() {
+        let powershell =
+            CurrentShell::from_str("powershell").expect("Could not create PowerShell shell");
+
+        assert_eq!(
+            powershell.compile_postscript(&Postscript::Deactivate("C:\\some;D:\\path".to_string())),
+            String::from("$env:PATH = 'C:\\some;D:\\path'\n") + super::UNSET_NOTION_HOME
+        );
+
+        assert_eq!(
+            powershell.compile_postscript(&Postscript::Deactivate(
+                "C:\\path;C:\\with\\single'quotes'".to_string()
+            )),
+            String::from("$env:PATH = 'C:\\path;C:\\with\\single''quotes'''\n")
+                + super::UNSET_NOTION_HOME
+        );
+
+        assert_eq!(
+            powershell.compile_postscript(&Postscript::ToolVersion {
+                tool: "test".to_string(),
+                version: Version::parse("2.4.5").unwrap()
+            }),
+            "$env:NOTION_TEST_VERSION = '2.4.5'\n"
+        );
+
+        assert_eq!(
+            powershell.compile_postscript(&Postscript::Activate("C:\\some;D:\\path".to_string())),
+            String::from("$env:PATH = 'C:\\some;D:\\path'\n") + super::SET_NOTION_HOME
+        );
+    }
+
+    #[test]
+    fn test_pwsh() {
+        let pwsh = CurrentShell::from_str("pwsh").expect("Could not create PowerShell shell");
+
+        assert_eq!(
+            pwsh.compile_postscript(&Postscript::Deactivate("C:\\some".to_string())),
+            String::from("$env:PATH = 'C:\\some'\n") + super::UNSET_NOTION_HOME
+        );
+    }
+}
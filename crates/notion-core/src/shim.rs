@@ -1,10 +1,13 @@
 //! Provides utilities for modifying shims for 3rd-party executables
 
+use std::env::consts::EXE_SUFFIX;
+use std::path::Path;
 use std::{fs, io};
 
 use crate::error::ErrorDetails;
+use crate::fs::{ensure_dir_exists, trace_fs};
 use crate::path;
-use notion_fail::{throw, FailExt, Fallible};
+use notion_fail::{throw, FailExt, Fallible, ResultExt};
 
 fn symlink_error(error: &io::Error) -> ErrorDetails {
     if let Some(inner_err) = error.get_ref() {
@@ -18,10 +21,11 @@ fn symlink_error(error: &io::Error) -> ErrorDetails {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Debug)]
 pub enum ShimResult {
     Created,
     AlreadyExists,
+    Repaired,
     Deleted,
     DoesntExist,
 }
@@ -33,11 +37,44 @@ fn is_3p_shim(name: &str) -> bool {
     }
 }
 
+/// Checks whether the file at `shim` is a link to the Notion shim executable.
+fn is_shim(shim: &Path, executable: &Path) -> bool {
+    match fs::read_link(shim) {
+        Ok(target) => target == executable,
+        Err(_) => false,
+    }
+}
+
+/// Creates the shim directory if it's missing, e.g. because it was removed by hand,
+/// so that shims can be created in it.
+pub fn ensure_shim_dir_exists() -> Fallible<()> {
+    ensure_dir_exists(&path::shim_dir()?)
+}
+
+/// Creates the shim for an executable, if it doesn't already exist. An existing file
+/// that isn't a link to the shim executable (e.g. a broken link, or a stale regular
+/// file) is replaced, so it's always safe to call this again.
 pub fn create(shim_name: &str) -> Fallible<ShimResult> {
+    ensure_shim_dir_exists()?;
     let executable = path::shim_executable()?;
     let shim = path::shim_file(shim_name)?;
-    match path::create_file_symlink(executable, shim) {
-        Ok(_) => Ok(ShimResult::Created),
+
+    let created = if fs::symlink_metadata(&shim).is_ok() {
+        if is_shim(&shim, &executable) {
+            return Ok(ShimResult::AlreadyExists);
+        }
+        trace_fs("remove", &shim, fs::remove_file(&shim)).with_context(symlink_error)?;
+        ShimResult::Repaired
+    } else {
+        ShimResult::Created
+    };
+
+    match trace_fs(
+        "symlink",
+        &shim,
+        path::create_file_symlink(executable, shim.clone()),
+    ) {
+        Ok(_) => Ok(created),
         Err(err) => {
             if err.kind() == io::ErrorKind::AlreadyExists {
                 Ok(ShimResult::AlreadyExists)
@@ -48,6 +85,9 @@ pub fn create(shim_name: &str) -> Fallible<ShimResult> {
     }
 }
 
+/// Deletes the shim for a 3rd-party executable. Files in the shim directory that
+/// aren't links to the shim executable were not created by Notion, so they are
+/// left alone and an error is returned instead.
 pub fn delete(shim_name: &str) -> Fallible<ShimResult> {
     if !is_3p_shim(shim_name) {
         throw!(ErrorDetails::SymlinkError {
@@ -55,7 +95,15 @@ pub fn delete(shim_name: &str) -> Fallible<ShimResult> {
         });
     }
     let shim = path::shim_file(shim_name)?;
-    match fs::remove_file(shim) {
+    if fs::symlink_metadata(&shim).is_ok() && !is_shim(&shim, &path::shim_executable()?) {
+        throw!(ErrorDetails::SymlinkError {
+            error: format!(
+                "cannot delete `{}`, not a Notion shim",
+                shim.to_string_lossy()
+            ),
+        });
+    }
+    match trace_fs("remove", &shim, fs::remove_file(&shim)) {
         Ok(_) => Ok(ShimResult::Deleted),
         Err(err) => {
             if err.kind() == io::ErrorKind::NotFound {
@@ -66,3 +114,128 @@ pub fn delete(shim_name: &str) -> Fallible<ShimResult> {
         }
     }
 }
+
+/// Removes the shim for an uninstalled executable. Removing a shim that doesn't
+/// exist is not an error.
+pub fn remove(shim_name: &str) -> Fallible<()> {
+    delete(shim_name).map(|_| ())
+}
+
+/// Lists the names of the executables that currently have a shim, in sorted order.
+/// Files in the shim directory that aren't links to the shim executable are skipped.
+pub fn list() -> Fallible<Vec<String>> {
+    let shim_dir = path::shim_dir()?;
+    if !shim_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let executable = path::shim_executable()?;
+    let mut shims = Vec::new();
+
+    for entry in fs::read_dir(shim_dir).unknown()? {
+        let entry = entry.unknown()?;
+        if !is_shim(&entry.path(), &executable) {
+            continue;
+        }
+        if let Some(file_name) = entry.file_name().to_str() {
+            if file_name.ends_with(EXE_SUFFIX) {
+                let len = file_name.len() - EXE_SUFFIX.len();
+                shims.push(file_name[..len].to_string());
+            }
+        }
+    }
+
+    shims.sort();
+    Ok(shims)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::path::tests::with_temp_home;
+    use std::fs::File;
+
+    // creating links to files needs extra privileges on Windows
+    #[test]
+    #[cfg(unix)]
+    fn test_create_is_idempotent() {
+        with_temp_home(|_| {
+            assert_eq!(create("cowsay").unwrap(), ShimResult::Created);
+            assert_eq!(create("cowsay").unwrap(), ShimResult::AlreadyExists);
+            assert_eq!(
+                fs::read_link(path::shim_file("cowsay").unwrap()).unwrap(),
+                path::shim_executable().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_create_repairs_broken_shims() {
+        with_temp_home(|home| {
+            ensure_shim_dir_exists().unwrap();
+            let shim = path::shim_file("cowsay").unwrap();
+
+            path::create_file_symlink(home.join("missing"), shim.clone()).unwrap();
+            assert_eq!(create("cowsay").unwrap(), ShimResult::Repaired);
+            assert_eq!(
+                fs::read_link(&shim).unwrap(),
+                path::shim_executable().unwrap()
+            );
+
+            fs::remove_file(&shim).unwrap();
+            File::create(&shim).unwrap();
+            assert_eq!(create("cowsay").unwrap(), ShimResult::Repaired);
+            assert_eq!(
+                fs::read_link(&shim).unwrap(),
+                path::shim_executable().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_remove() {
+        with_temp_home(|_| {
+            create("cowsay").unwrap();
+            remove("cowsay").expect("could not remove shim");
+            assert!(fs::symlink_metadata(path::shim_file("cowsay").unwrap()).is_err());
+
+            // removing a shim that is already gone is not an error
+            remove("cowsay").expect("could not remove missing shim");
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_remove_leaves_other_files() {
+        with_temp_home(|_| {
+            ensure_shim_dir_exists().unwrap();
+            let file = path::shim_file("cowsay").unwrap();
+            File::create(&file).unwrap();
+
+            assert!(remove("cowsay").is_err());
+            assert!(file.is_file());
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_list() {
+        with_temp_home(|_| {
+            assert!(list().unwrap().is_empty());
+
+            create("tsc").unwrap();
+            create("cowsay").unwrap();
+            File::create(path::shim_file("not-a-shim").unwrap()).unwrap();
+
+            assert_eq!(list().unwrap(), vec!["cowsay", "tsc"]);
+        });
+    }
+
+    #[test]
+    fn test_delete_refuses_builtin_tools() {
+        assert!(delete("node").is_err());
+        assert!(delete("yarn").is_err());
+    }
+}
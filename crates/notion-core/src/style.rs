@@ -2,6 +2,7 @@
 
 use std::env;
 
+use crate::config::config;
 use archive::Origin;
 use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
@@ -10,6 +11,8 @@ use term_size;
 
 const NOTION_DEV: &'static str = "NOTION_DEV";
 
+pub const NOTION_VERBOSE: &'static str = "NOTION_VERBOSE";
+
 /// Represents the context from which an error is being reported.
 pub enum ErrorContext {
     /// An error reported from the `notion` executable.
@@ -27,6 +30,24 @@ pub fn display_error(cx: ErrorContext, err: &NotionError) {
     } else {
         display_internal_error(err);
     }
+
+    if env::var(NOTION_VERBOSE).is_ok() {
+        display_error_causes(err);
+    }
+}
+
+/// Displays the chain of lower-level errors that caused an error to stderr.
+fn display_error_causes(err: &NotionError) {
+    let mut causes = err.as_fail().iter_causes().peekable();
+    if causes.peek().is_none() {
+        return;
+    }
+
+    eprintln!();
+    eprintln!("{}", style("caused by:").yellow().bold());
+    for cause in causes {
+        eprintln!("    {}", cause);
+    }
 }
 
 /// Displays a user-friendly error to stderr
@@ -92,6 +113,14 @@ fn display_development_details(err: &NotionError) {
     }
 }
 
+/// Whether progress and informational output is suppressed by `--quiet`. Errors
+/// are always displayed.
+pub fn quiet() -> bool {
+    // Output is never the place to report an invalid setting: the CLI reports it when
+    // it starts, and a shim when it needs that setting, so output is shown until then.
+    config().map(|config| config.quiet()).unwrap_or(false)
+}
+
 /// Determines the string to display based on the Origin of the operation.
 fn action_str(origin: Origin) -> &'static str {
     match origin {
@@ -105,6 +134,10 @@ fn action_str(origin: Origin) -> &'static str {
 /// length (i.e., the number of logical progress steps in the process being
 /// visualized by the progress bar).
 pub fn progress_bar(origin: Origin, details: &str, len: u64) -> ProgressBar {
+    if quiet() {
+        return ProgressBar::hidden();
+    }
+
     let display_width = term_size::dimensions().map(|(w, _)| w).unwrap_or(80);
     let action = action_str(origin);
     let action_width = action.len() + 2; // plus 2 spaces to look nice
@@ -139,6 +172,10 @@ pub fn progress_bar(origin: Origin, details: &str, len: u64) -> ProgressBar {
 /// Constructs a command-line progress spinner with the specified "message"
 /// string. The spinner is ticked by default every 20ms.
 pub fn progress_spinner(message: &str) -> ProgressBar {
+    if quiet() {
+        return ProgressBar::hidden();
+    }
+
     // ⠋ Fetching public registry: https://nodejs.org/dist/index.json
     let spinner = ProgressBar::new_spinner();
 
@@ -8,11 +8,14 @@ use std::marker::Sized;
 use std::path::Path;
 use std::process::{Command, ExitStatus};
 
+use lazy_static::lazy_static;
+use regex::Regex;
+
 use crate::env::UNSAFE_GLOBAL;
 use crate::error::ErrorDetails;
 use crate::session::Session;
 use crate::version::VersionSpec;
-use notion_fail::{Fallible, ResultExt};
+use notion_fail::{throw, Fallible, ResultExt};
 
 mod binary;
 mod node;
@@ -33,7 +36,27 @@ pub enum ToolSpec {
     Package(String, VersionSpec),
 }
 
+/// The kind of tool a `ToolSpec` refers to, for code that needs to branch on it
+/// without matching names.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ToolKind {
+    Node,
+    Yarn,
+    Npm,
+    Package,
+}
+
 impl ToolSpec {
+    /// Produces the kind of tool this specifies.
+    pub fn kind(&self) -> ToolKind {
+        match self {
+            ToolSpec::Node(_) => ToolKind::Node,
+            ToolSpec::Yarn(_) => ToolKind::Yarn,
+            ToolSpec::Npm(_) => ToolKind::Npm,
+            ToolSpec::Package(..) => ToolKind::Package,
+        }
+    }
+
     pub fn from_str_and_version(tool_name: &str, version: VersionSpec) -> Self {
         match tool_name {
             "node" => ToolSpec::Node(version),
@@ -43,12 +66,80 @@ impl ToolSpec {
         }
     }
 
+    /// Parses a tool specifier of the form `<name>` or `<name>@<version>`, e.g. `node`,
+    /// `node@16`, `yarn@1.22`, `npm@latest`, or `@scope/package@1.2.3`. A bare name
+    /// implies the default version.
+    pub fn parse(input: &str) -> Fallible<Self> {
+        let input = input.trim();
+
+        // a leading '@' belongs to a scoped package name, not to the version
+        let (name, version) = match input.get(1..).and_then(|rest| rest.find('@')) {
+            Some(index) => {
+                let (name, version) = input.split_at(index + 1);
+                (name, Some(&version[1..]))
+            }
+            None => (input, None),
+        };
+
+        if !is_valid_tool_name(name) {
+            throw!(ErrorDetails::NoSuchTool {
+                tool: name.to_string()
+            });
+        }
+
+        let version = match version {
+            Some(version) => VersionSpec::parse(version)?,
+            None => VersionSpec::default(),
+        };
+
+        // only tools published to the npm registry have dist-tags
+        if let VersionSpec::Tag(ref tag) = version {
+            if name == "node" || name == "yarn" {
+                throw!(ErrorDetails::VersionParseError {
+                    input: tag.to_string(),
+                    error: format!("{} versions can't be dist-tags", name),
+                });
+            }
+        }
+
+        Ok(ToolSpec::from_str_and_version(name, version))
+    }
+
+    /// Fetches and unpacks the tool into the inventory and image directories, without
+    /// changing the active toolchain. Fetching a version that is already unpacked
+    /// does nothing.
+    pub fn fetch(&self, session: &mut Session) -> Fallible<()> {
+        match self {
+            ToolSpec::Node(version) => {
+                session.fetch_node(version)?;
+            }
+            ToolSpec::Yarn(version) => {
+                session.fetch_yarn(version)?;
+            }
+            ToolSpec::Npm(version) => {
+                session.fetch_npm(version)?;
+            }
+            ToolSpec::Package(name, version) => {
+                session.fetch_package(name.to_string(), version)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads the tool's archive into the inventory, without unpacking it. A later
+    /// fetch or install of the same exact version unpacks it instead of downloading it
+    /// again. Node and Yarn versions are then provisioned without using the network,
+    /// but packages still fetch their registry metadata to resolve the version.
+    pub fn fetch_archive(&self, session: &mut Session) -> Fallible<()> {
+        session.fetch_archive(self)?;
+        Ok(())
+    }
+
     pub fn install(&self, session: &mut Session) -> Fallible<()> {
         match self {
             ToolSpec::Node(version) => session.install_node(&version)?,
             ToolSpec::Yarn(version) => session.install_yarn(&version)?,
-            // ISSUE(#292): Implement install for npm
-            ToolSpec::Npm(_version) => unimplemented!("Installing npm is not supported yet"),
+            ToolSpec::Npm(version) => session.install_npm(&version)?,
             ToolSpec::Package(name, version) => {
                 session.install_package(name.to_string(), &version)?;
             }
@@ -69,18 +160,44 @@ impl Debug for ToolSpec {
     }
 }
 
+/// Formats a tool specifier as `<name>@<version>`, e.g. `node@10.15.3` or
+/// `ember-cli@3.7.1`, the same form that `ToolSpec::parse` accepts. Error messages
+/// depend on this format, so it should be kept stable.
 impl Display for ToolSpec {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
         let s = match self {
-            &ToolSpec::Node(ref version) => format!("node version {}", version),
-            &ToolSpec::Yarn(ref version) => format!("yarn version {}", version),
-            &ToolSpec::Npm(ref version) => format!("npm version {}", version),
-            &ToolSpec::Package(ref name, ref version) => format!("{} version {}", name, version),
+            // an unspecified version is left out, rather than shown as `node@`
+            &ToolSpec::Node(VersionSpec::None) => "node".to_string(),
+            &ToolSpec::Yarn(VersionSpec::None) => "yarn".to_string(),
+            &ToolSpec::Npm(VersionSpec::None) => "npm".to_string(),
+            &ToolSpec::Package(ref name, VersionSpec::None) => name.to_string(),
+            &ToolSpec::Node(ref version) => format!("node@{}", version),
+            &ToolSpec::Yarn(ref version) => format!("yarn@{}", version),
+            &ToolSpec::Npm(ref version) => format!("npm@{}", version),
+            &ToolSpec::Package(ref name, ref version) => format!("{}@{}", name, version),
         };
         f.write_str(&s)
     }
 }
 
+/// Checks whether a name is valid for a tool: one of the built-in tools or
+/// an npm package name (optionally scoped).
+fn is_valid_tool_name(name: &str) -> bool {
+    lazy_static! {
+        static ref TOOL_NAME: Regex = Regex::new(
+            r"(?x)
+            ^
+            (@[a-z0-9~-][a-z0-9._~-]*/)? # optional scope
+            [a-z0-9~-][a-z0-9._~-]*      # package name
+            $
+            ",
+        )
+        .unwrap();
+    }
+
+    TOOL_NAME.is_match(name)
+}
+
 fn binary_exec_error(error: &io::Error) -> ErrorDetails {
     if let Some(inner_err) = error.get_ref() {
         ErrorDetails::BinaryExecError {
@@ -138,10 +255,26 @@ pub trait Tool: Sized {
     }
 }
 
+/// The name of the shim executable, which every tool name is linked to.
+const SHIM_NAME: &str = "shim";
+
 fn get_tool_name(args: &mut ArgsOs) -> Fallible<OsString> {
-    args.nth(0)
+    let name = args
+        .nth(0)
         .and_then(|arg0| Path::new(&arg0).file_name().map(tool_name_from_file_name))
-        .ok_or(ErrorDetails::CouldNotDetermineTool.into())
+        .ok_or(ErrorDetails::CouldNotDetermineTool)?;
+
+    if is_shim_name(&name) {
+        throw!(ErrorDetails::ShimInvokedDirectly);
+    }
+
+    Ok(name)
+}
+
+/// Determines whether a tool name is that of the shim executable itself, which
+/// means the shim was run directly instead of through a link named after a tool.
+fn is_shim_name(name: &OsStr) -> bool {
+    name == SHIM_NAME
 }
 
 #[cfg(unix)]
@@ -170,3 +303,144 @@ fn intercept_global_installs() -> bool {
     // We should only intercept global installs if the NOTION_UNSAFE_GLOBAL variable is not set
     env::var_os(UNSAFE_GLOBAL).is_none()
 }
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::{is_shim_name, tool_name_from_file_name, ToolKind, ToolSpec};
+    use crate::version::VersionSpec;
+    use semver::{Version, VersionReq};
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(ToolSpec::Node(VersionSpec::Latest).kind(), ToolKind::Node);
+        assert_eq!(ToolSpec::Yarn(VersionSpec::Latest).kind(), ToolKind::Yarn);
+        assert_eq!(ToolSpec::Npm(VersionSpec::Latest).kind(), ToolKind::Npm);
+        assert_eq!(
+            ToolSpec::Package("ember-cli".to_string(), VersionSpec::Latest).kind(),
+            ToolKind::Package
+        );
+    }
+
+    #[test]
+    fn test_is_shim_name() {
+        let tool_name = |file_name| tool_name_from_file_name(OsStr::new(file_name));
+        assert!(is_shim_name(&tool_name("shim")));
+        assert!(!is_shim_name(&tool_name("node")));
+        assert!(!is_shim_name(&tool_name("shimmer")));
+    }
+
+    #[test]
+    fn test_display() {
+        let version = Version::parse("1.2.3").unwrap();
+        assert_eq!(
+            ToolSpec::Node(VersionSpec::Exact(version.clone())).to_string(),
+            "node@1.2.3"
+        );
+        assert_eq!(
+            ToolSpec::Yarn(VersionSpec::Exact(version.clone())).to_string(),
+            "yarn@1.2.3"
+        );
+        assert_eq!(ToolSpec::Npm(VersionSpec::Lts).to_string(), "npm@lts");
+        assert_eq!(
+            ToolSpec::Package(
+                "ember-cli".to_string(),
+                VersionSpec::Exact(Version::parse("3.7.1").unwrap())
+            )
+            .to_string(),
+            "ember-cli@3.7.1"
+        );
+        assert_eq!(
+            ToolSpec::Package("@angular/cli".to_string(), VersionSpec::Latest).to_string(),
+            "@angular/cli@latest"
+        );
+        assert_eq!(ToolSpec::Node(VersionSpec::None).to_string(), "node");
+    }
+
+    #[test]
+    fn test_parse_bare_name() {
+        match ToolSpec::parse("node").expect("could not parse tool spec") {
+            ToolSpec::Node(VersionSpec::None) => {}
+            other => panic!("unexpected tool spec: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_name_and_version() {
+        match ToolSpec::parse("node@16").expect("could not parse tool spec") {
+            ToolSpec::Node(VersionSpec::Semver(req)) => {
+                assert_eq!(req, VersionReq::parse("=16").unwrap())
+            }
+            other => panic!("unexpected tool spec: {}", other),
+        }
+
+        match ToolSpec::parse("yarn@1.22").expect("could not parse tool spec") {
+            ToolSpec::Yarn(VersionSpec::Semver(req)) => {
+                assert_eq!(req, VersionReq::parse("=1.22").unwrap())
+            }
+            other => panic!("unexpected tool spec: {}", other),
+        }
+
+        match ToolSpec::parse("npm@latest").expect("could not parse tool spec") {
+            ToolSpec::Npm(VersionSpec::Latest) => {}
+            other => panic!("unexpected tool spec: {}", other),
+        }
+
+        match ToolSpec::parse("node@10.15.3").expect("could not parse tool spec") {
+            ToolSpec::Node(VersionSpec::Exact(version)) => {
+                assert_eq!(version, Version::parse("10.15.3").unwrap())
+            }
+            other => panic!("unexpected tool spec: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_packages() {
+        match ToolSpec::parse("ember-cli@3.7.1").expect("could not parse tool spec") {
+            ToolSpec::Package(name, VersionSpec::Exact(version)) => {
+                assert_eq!(name, "ember-cli");
+                assert_eq!(version, Version::parse("3.7.1").unwrap());
+            }
+            other => panic!("unexpected tool spec: {}", other),
+        }
+
+        match ToolSpec::parse("@angular/cli").expect("could not parse tool spec") {
+            ToolSpec::Package(name, VersionSpec::None) => assert_eq!(name, "@angular/cli"),
+            other => panic!("unexpected tool spec: {}", other),
+        }
+
+        match ToolSpec::parse("@angular/cli@^7").expect("could not parse tool spec") {
+            ToolSpec::Package(name, VersionSpec::Semver(req)) => {
+                assert_eq!(name, "@angular/cli");
+                assert_eq!(req, VersionReq::parse("^7").unwrap());
+            }
+            other => panic!("unexpected tool spec: {}", other),
+        }
+
+        match ToolSpec::parse("typescript@next").expect("could not parse tool spec") {
+            ToolSpec::Package(name, VersionSpec::Tag(tag)) => {
+                assert_eq!(name, "typescript");
+                assert_eq!(tag, "next");
+            }
+            other => panic!("unexpected tool spec: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ToolSpec::parse("").is_err());
+        assert!(ToolSpec::parse("@16").is_err());
+        assert!(ToolSpec::parse("not a tool").is_err());
+        assert!(ToolSpec::parse("node@not-a-version").is_err());
+        assert!(ToolSpec::parse("yarn@next").is_err());
+    }
+
+    #[test]
+    fn test_parse_npm_tag() {
+        match ToolSpec::parse("npm@next").expect("could not parse tool spec") {
+            ToolSpec::Npm(VersionSpec::Tag(tag)) => assert_eq!(tag, "next"),
+            other => panic!("unexpected tool spec: {}", other),
+        }
+    }
+}
@@ -24,7 +24,7 @@ impl Tool for Npx {
             // npx was only included with npm 5.2.0 and higher. If the npm version is less than that, we
             // should include a helpful error message
             let required_npm = VersionSpec::parse_version("5.2.0")?;
-            if image.node.npm >= required_npm {
+            if *image.node.npm() >= required_npm {
                 Ok(Self::from_components(
                     OsStr::new("npx"),
                     args,
@@ -32,7 +32,7 @@ impl Tool for Npx {
                 ))
             } else {
                 throw!(ErrorDetails::NpxNotAvailable {
-                    version: image.node.npm.to_string()
+                    version: image.node.npm().to_string()
                 });
             }
         } else {
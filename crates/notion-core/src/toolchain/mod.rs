@@ -1,19 +1,433 @@
-use std::fs::File;
-use std::io::Write;
+use std::fmt;
+use std::fs::{
+    copy, read_link, read_to_string, remove_dir, remove_file, rename, File, OpenOptions,
+};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use fs2::{lock_contended_error, FileExt};
 use lazycell::LazyCell;
 use readext::ReadExt;
 use semver::Version;
 
-use crate::distro::node::NodeVersion;
-use crate::fs::touch;
-use crate::path::user_platform_file;
+use crate::distro::node::{load_default_npm_version, NodeVersion};
+use crate::error::ErrorDetails;
+use crate::fs::{ensure_containing_dir_exists, touch_with_default, trace_fs};
+use crate::path::{
+    create_dir_symlink, node_image_current_link, node_image_dir, npm_image_dir,
+    user_platform_backup_file, user_platform_file, user_platform_history_file,
+    user_platform_lock_file, yarn_image_dir,
+};
 use crate::platform::PlatformSpec;
 
-use notion_fail::{Fallible, ResultExt};
+use notion_fail::{throw, FailExt, Fallible, ResultExt};
 
 pub(crate) mod serial;
 
+/// How long to wait for another process to release the toolchain lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to sleep between attempts to acquire the toolchain lock.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How many replaced platforms the platform history keeps for `undo`.
+const HISTORY_LIMIT: usize = 20;
+
+/// An exclusive advisory lock on the user platform file, which is released when dropped.
+struct PlatformLock {
+    file: File,
+}
+
+impl PlatformLock {
+    /// Acquires the lock, waiting up to `LOCK_TIMEOUT` for any other holder to release it.
+    fn acquire() -> Fallible<Self> {
+        PlatformLock::acquire_within(LOCK_TIMEOUT)
+    }
+
+    /// Acquires the lock, waiting up to `timeout` for any other holder to release it.
+    fn acquire_within(timeout: Duration) -> Fallible<Self> {
+        let path = user_platform_lock_file()?;
+        ensure_containing_dir_exists(&path)?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(&path)
+            .unknown()?;
+        let started = Instant::now();
+
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(PlatformLock { file }),
+                Err(ref error) if error.raw_os_error() == lock_contended_error().raw_os_error() => {
+                    if started.elapsed() >= timeout {
+                        throw!(ErrorDetails::ToolchainLocked {
+                            path: path.to_string_lossy().to_string(),
+                        });
+                    }
+                    thread::sleep(LOCK_RETRY_INTERVAL);
+                }
+                Err(error) => throw!(error.unknown()),
+            }
+        }
+    }
+}
+
+impl Drop for PlatformLock {
+    fn drop(&mut self) {
+        // the lock is also released by the OS when the file is closed,
+        // so a failure to unlock explicitly is not worth reporting
+        let _ = self.file.unlock();
+    }
+}
+
+/// Checks that the user platform file, if it exists, is a regular file that can be
+/// read and written, rather than e.g. a directory.
+fn ensure_platform_file_regular(path: &Path) -> Fallible<()> {
+    if path.exists() && !path.is_file() {
+        throw!(ErrorDetails::PlatformFileNotRegular {
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Reads a platform from the platform file at the given path, creating an empty one
+/// if it doesn't exist.
+fn read_platform_file(path: &Path) -> Fallible<Option<PlatformSpec>> {
+    ensure_platform_file_regular(path)?;
+    let src = touch_with_default(path, b"{}")?
+        .read_into_string()
+        .unknown()?;
+    serial::Platform::from_json(src)?.into_image()
+}
+
+/// Reads the user platform from disk.
+fn read_platform() -> Fallible<Option<PlatformSpec>> {
+    read_platform_file(&user_platform_file()?)
+}
+
+/// Points the `current` link in the Node image directory at the image of the given
+/// platform's Node version, or removes the link if there is no platform. `previous`
+/// is the platform being replaced, which decides whether to warn about a pinned npm.
+fn update_current_node_link(
+    previous: Option<&PlatformSpec>,
+    platform: Option<&PlatformSpec>,
+) -> Fallible<()> {
+    let link = node_image_current_link()?;
+    let platform = match platform {
+        Some(platform) => platform,
+        None => return link_updated(&link, remove_link(&link)),
+    };
+
+    // the Node image is keyed by its bundled npm, even if the platform pins another
+    let npm = load_default_npm_version(&platform.node_runtime)?;
+    if let Some(pinned) = newly_ignored_npm_pin(previous, platform, &npm) {
+        warn_npm_pin_ignored(&pinned, &npm);
+    }
+    let image = node_image_dir(&platform.node_runtime.to_string(), &npm.to_string())?;
+    ensure_containing_dir_exists(&link)?;
+    link_updated(&link, replace_dir_link(&image, &link))
+}
+
+/// Checks the result of updating the `current` link. Creating a link to a directory
+/// on Windows needs Developer Mode or an elevated prompt, so there the link is only
+/// updated on a best-effort basis, with a warning, rather than failing the save of
+/// the platform itself.
+fn link_updated(link: &Path, result: io::Result<()>) -> Fallible<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(ref error) if cfg!(windows) => {
+            eprintln!(
+                "Warning: could not update the link at {}: {}",
+                link.display(),
+                error
+            );
+            Ok(())
+        }
+        Err(error) => Err(error).with_context(symlink_error),
+    }
+}
+
+/// Returns the pinned npm version that the `current` link ignores in favor of the
+/// bundled npm, if there is one and either it or the Node version has changed from
+/// the `previous` platform. Otherwise the user has already been warned about it.
+fn newly_ignored_npm_pin(
+    previous: Option<&PlatformSpec>,
+    platform: &PlatformSpec,
+    bundled_npm: &Version,
+) -> Option<Version> {
+    let pinned = platform.standalone_npm(bundled_npm)?;
+    match previous {
+        Some(previous)
+            if previous.node_runtime == platform.node_runtime
+                && previous.npm.as_ref() == Some(&pinned) =>
+        {
+            None
+        }
+        _ => Some(pinned),
+    }
+}
+
+/// Warns that tools run through the `current` link, rather than through Notion's
+/// shims, use the npm bundled with Node instead of the pinned one, since the link
+/// can only point at the Node image.
+fn warn_npm_pin_ignored(pinned: &Version, bundled: &Version) {
+    eprintln!(
+        "Warning: npm v{} is pinned, but tools that use the 'current' Node image without Notion will run its bundled npm v{}",
+        pinned, bundled
+    );
+}
+
+fn symlink_error(error: &io::Error) -> ErrorDetails {
+    ErrorDetails::SymlinkError {
+        error: error.to_string(),
+    }
+}
+
+/// Makes `link` a link to the directory `target`. The new link is created beside
+/// the old one and renamed over it, so that `link` always points at some image.
+fn replace_dir_link(target: &Path, link: &Path) -> io::Result<()> {
+    if read_link(link).ok().as_ref().map(PathBuf::as_path) == Some(target) {
+        return Ok(());
+    }
+
+    let temp = link.with_extension("tmp");
+    remove_link(&temp)?;
+    create_dir_symlink(target.to_path_buf(), temp.clone())?;
+
+    // Windows can't rename over a link to a directory
+    if cfg!(windows) {
+        remove_link(link)?;
+    }
+    rename(&temp, link)
+}
+
+/// Removes a link, whether it's a link to a file or (on Windows) to a directory, if
+/// it exists.
+fn remove_link(link: &Path) -> io::Result<()> {
+    if link.symlink_metadata().is_err() {
+        return Ok(());
+    }
+    remove_file(link).or_else(|_| remove_dir(link))
+}
+
+/// Copies the platform file at `path` to `backup`, replacing any earlier backup, so
+/// that a bad save can be undone. Nothing is copied unless the file holds a valid
+/// platform with a Node version, so that a good backup isn't replaced by a useless one.
+fn backup_platform(path: &Path, backup: &Path) -> Fallible<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    let src = read_to_string(path).unknown()?;
+    match serial::Platform::from_json(src).and_then(serial::Platform::into_image) {
+        Ok(Some(_)) => {
+            let result = copy(path, backup);
+            trace_fs("copy", backup, result).unknown()?;
+            Ok(())
+        }
+        Ok(None) | Err(_) => Ok(()),
+    }
+}
+
+/// Replaces the platform file at `path` with its backup at `backup`.
+fn restore_platform(path: &Path, backup: &Path) -> Fallible<()> {
+    if !backup.is_file() {
+        throw!(ErrorDetails::NoPlatformBackup {
+            path: backup.to_string_lossy().to_string(),
+        });
+    }
+    ensure_platform_file_regular(path)?;
+    let result = copy(backup, path);
+    trace_fs("copy", path, result).unknown()?;
+    Ok(())
+}
+
+/// Reads the entries of the platform history file at `history`, oldest first. Lines
+/// that can't be parsed (e.g. from an interrupted write) are left out.
+fn read_history(history: &Path) -> Fallible<Vec<String>> {
+    if !history.is_file() {
+        return Ok(Vec::new());
+    }
+    let src = read_to_string(history).unknown()?;
+    Ok(src
+        .lines()
+        .filter(|line| serial::HistoryEntry::from_json(line).is_ok())
+        .map(str::to_string)
+        .collect())
+}
+
+fn write_history(history: &Path, entries: &[String]) -> Fallible<()> {
+    ensure_containing_dir_exists(&history)?;
+    let src: String = entries.iter().map(|entry| format!("{}\n", entry)).collect();
+    let result = File::create(history).and_then(|mut file| file.write_all(src.as_bytes()));
+    trace_fs("write", history, result).unknown()
+}
+
+/// Appends the platform in the platform file at `path` to the history at `history`,
+/// so that `undo` can return to it, and drops the oldest entries beyond
+/// `HISTORY_LIMIT`. Like `backup_platform`, nothing is recorded for a platform file
+/// that can't be parsed.
+fn record_history(path: &Path, history: &Path, timestamp: u64) -> Fallible<()> {
+    let src = if path.is_file() {
+        read_to_string(path).unknown()?
+    } else {
+        String::new()
+    };
+    let platform = match serial::Platform::from_json(src) {
+        Ok(platform) => platform,
+        Err(_) => return Ok(()),
+    };
+
+    let entry = serial::HistoryEntry {
+        timestamp,
+        platform,
+    };
+    let mut entries = read_history(history)?;
+    entries.push(entry.to_json()?);
+    let excess = entries.len().saturating_sub(HISTORY_LIMIT);
+    write_history(history, &entries[excess..])
+}
+
+/// Removes the newest entry from the history at `history`, and produces the platform
+/// it recorded.
+fn pop_history(history: &Path) -> Fallible<Option<PlatformSpec>> {
+    let mut entries = read_history(history)?;
+    let entry = match entries.pop() {
+        Some(entry) => serial::HistoryEntry::from_json(&entry)?,
+        None => throw!(ErrorDetails::NoPlatformHistory {
+            path: history.to_string_lossy().to_string(),
+        }),
+    };
+    write_history(history, &entries)?;
+    entry.platform.into_image()
+}
+
+/// The current time in seconds since the Unix epoch, for timestamping history entries.
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Sets the Npm version of a platform, returning whether it changed. Npm makes no
+/// sense without Node, so this fails if there is no platform.
+fn set_npm(platform: &mut Option<PlatformSpec>, npm_version: Version) -> Fallible<bool> {
+    match platform {
+        Some(platform) => {
+            if platform.npm.as_ref() == Some(&npm_version) {
+                return Ok(false);
+            }
+            platform.npm = Some(npm_version);
+            Ok(true)
+        }
+        None => throw!(ErrorDetails::NoUserNodeVersion),
+    }
+}
+
+/// Sets the Yarn version of a platform, returning whether it changed. Yarn can't be
+/// used without Node, so this fails if there is no platform.
+fn set_yarn(platform: &mut Option<PlatformSpec>, yarn_version: Version) -> Fallible<bool> {
+    match platform {
+        Some(platform) => {
+            if platform.yarn.as_ref() == Some(&yarn_version) {
+                return Ok(false);
+            }
+            platform.yarn = Some(yarn_version);
+            Ok(true)
+        }
+        None => throw!(ErrorDetails::NoUserNodeVersion),
+    }
+}
+
+/// A problem found in the user toolchain by `Toolchain::validate_and_repair`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RepairAction {
+    /// The pinned Node version has no image directory, so it must be fetched again.
+    MissingNodeImage(Version),
+    /// The pinned npm version is neither bundled with the pinned Node nor fetched
+    /// separately, so it must be fetched again.
+    MissingNpmImage(Version),
+    /// The pinned Yarn version has no image directory, so it must be fetched again.
+    MissingYarnImage(Version),
+}
+
+impl fmt::Display for RepairAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepairAction::MissingNodeImage(version) => {
+                write!(f, "Node v{} is pinned but not installed", version)
+            }
+            RepairAction::MissingNpmImage(version) => {
+                write!(f, "npm v{} is pinned but not installed", version)
+            }
+            RepairAction::MissingYarnImage(version) => {
+                write!(f, "Yarn v{} is pinned but not installed", version)
+            }
+        }
+    }
+}
+
+/// A change to the version of one tool between two platforms, as produced by
+/// `Toolchain::diff`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ToolChange {
+    /// The tool is only pinned in the new platform.
+    Added {
+        tool: &'static str,
+        version: Version,
+    },
+    /// The tool is only pinned in the old platform.
+    Removed {
+        tool: &'static str,
+        version: Version,
+    },
+    /// The tool is pinned to different versions in the two platforms.
+    Changed {
+        tool: &'static str,
+        old: Version,
+        new: Version,
+    },
+}
+
+impl fmt::Display for ToolChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ToolChange::Added { tool, version } => write!(f, "{} v{} (added)", tool, version),
+            ToolChange::Removed { tool, version } => {
+                write!(f, "{} v{} (removed)", tool, version)
+            }
+            ToolChange::Changed { tool, old, new } => write!(f, "{} v{} → v{}", tool, old, new),
+        }
+    }
+}
+
+/// Produces the change, if any, to the version of one tool.
+fn diff_tool(
+    tool: &'static str,
+    old: Option<&Version>,
+    new: Option<&Version>,
+) -> Option<ToolChange> {
+    match (old, new) {
+        (None, Some(new)) => Some(ToolChange::Added {
+            tool,
+            version: new.clone(),
+        }),
+        (Some(old), None) => Some(ToolChange::Removed {
+            tool,
+            version: old.clone(),
+        }),
+        (Some(old), Some(new)) if old != new => Some(ToolChange::Changed {
+            tool,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => None,
+    }
+}
+
 /// Lazily loaded toolchain
 pub struct LazyToolchain {
     toolchain: LazyCell<Toolchain>,
@@ -36,6 +450,12 @@ impl LazyToolchain {
     pub fn get_mut(&mut self) -> Fallible<&mut Toolchain> {
         self.toolchain.try_borrow_mut_with(|| Toolchain::current())
     }
+
+    /// Discards the loaded toolchain, if any, so that the next call to `get` or
+    /// `get_mut` reloads it from disk. Any unsaved in-memory changes are lost.
+    pub fn invalidate(&mut self) {
+        self.toolchain = LazyCell::new();
+    }
 }
 
 pub struct Toolchain {
@@ -44,95 +464,645 @@ pub struct Toolchain {
 
 impl Toolchain {
     fn current() -> Fallible<Toolchain> {
-        let path = user_platform_file()?;
-        let src = touch(&path)?.read_into_string().unknown()?;
+        Toolchain::from_file(&user_platform_file()?)
+    }
+
+    /// Loads a toolchain from the platform file at the given path, rather than from
+    /// the user platform file, e.g. for a project-scoped platform.
+    pub fn from_file(path: &Path) -> Fallible<Toolchain> {
         Ok(Toolchain {
-            platform: serial::Platform::from_json(src)?.into_image()?,
+            platform: read_platform_file(path)?,
         })
     }
 
+    /// Re-reads the user platform file, replacing the in-memory platform. This picks
+    /// up changes made by other processes; any unsaved in-memory changes are discarded.
+    pub fn reload(&mut self) -> Fallible<()> {
+        self.platform = read_platform()?;
+        Ok(())
+    }
+
     pub fn platform_ref(&self) -> Option<&PlatformSpec> {
         self.platform.as_ref()
     }
 
-    /// Set the active Node version in the user platform file.
-    pub fn set_active_node(&mut self, node_version: NodeVersion) -> Fallible<()> {
-        let mut dirty = false;
+    /// Produces the changes to each tool (Node, npm, and Yarn) that replacing the user
+    /// platform with `other` would make. If there is no user platform, every tool
+    /// pinned in `other` is reported as added.
+    pub fn diff(&self, other: &PlatformSpec) -> Vec<ToolChange> {
+        let current = self.platform.as_ref();
+        vec![
+            diff_tool(
+                "node",
+                current.map(|platform| &platform.node_runtime),
+                Some(&other.node_runtime),
+            ),
+            diff_tool(
+                "npm",
+                current.and_then(|platform| platform.npm.as_ref()),
+                other.npm.as_ref(),
+            ),
+            diff_tool(
+                "yarn",
+                current.and_then(|platform| platform.yarn.as_ref()),
+                other.yarn.as_ref(),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Checks that every tool pinned in the user platform has an image directory, and
+    /// returns the actions needed to repair the toolchain. The Node image is the one
+    /// keyed by its bundled npm, whether or not the platform pins another npm. This
+    /// neither modifies the platform file nor fetches anything; the caller decides
+    /// whether to re-fetch the missing images.
+    pub fn validate_and_repair(&self) -> Fallible<Vec<RepairAction>> {
+        let mut actions = Vec::new();
 
-        if let Some(ref mut platform) = self.platform {
-            if platform.node_runtime != node_version.runtime {
-                platform.node_runtime = node_version.runtime;
-                dirty = true;
+        if let Some(ref platform) = self.platform {
+            let node_str = platform.node_runtime.to_string();
+            // the bundled npm can't be determined without the npm version file or the
+            // unpacked image, so either way the image must be fetched again
+            let node_image = match load_default_npm_version(&platform.node_runtime) {
+                Ok(bundled) => Some(node_image_dir(&node_str, &bundled.to_string())?),
+                Err(_) => None,
+            };
+            if !node_image.map_or(false, |dir| dir.is_dir()) {
+                actions.push(RepairAction::MissingNodeImage(
+                    platform.node_runtime.clone(),
+                ));
             }
 
-            if platform.npm != Some(node_version.npm.clone()) {
-                platform.npm = Some(node_version.npm);
-                dirty = true;
+            if let Some(ref npm) = platform.npm {
+                let npm_str = npm.to_string();
+                if !node_image_dir(&node_str, &npm_str)?.is_dir()
+                    && !npm_image_dir(&npm_str)?.is_dir()
+                {
+                    actions.push(RepairAction::MissingNpmImage(npm.clone()));
+                }
+            }
+
+            if let Some(ref yarn) = platform.yarn {
+                if !yarn_image_dir(&yarn.to_string())?.is_dir() {
+                    actions.push(RepairAction::MissingYarnImage(yarn.clone()));
+                }
             }
-        } else {
-            self.platform = Some(PlatformSpec {
-                node_runtime: node_version.runtime,
-                npm: Some(node_version.npm),
-                yarn: None,
-            });
-            dirty = true;
         }
 
-        if dirty {
-            self.save()?;
+        Ok(actions)
+    }
+
+    /// Set the active Node version in the user platform file.
+    pub fn set_active_node(&mut self, node_version: NodeVersion) -> Fallible<()> {
+        let lock = PlatformLock::acquire()?;
+        // another process may have modified the platform since it was loaded
+        self.platform = read_platform()?;
+
+        let current = self.platform.as_ref().and_then(PlatformSpec::node_version);
+        if current.as_ref() != Some(&node_version) {
+            let yarn = self
+                .platform
+                .as_ref()
+                .and_then(|platform| platform.yarn.clone());
+            let (node_runtime, npm) = node_version.into_parts();
+            self.platform = Some(PlatformSpec {
+                node_runtime,
+                npm: Some(npm),
+                yarn,
+            });
+            self.save(&lock)?;
         }
 
         Ok(())
     }
 
-    /// Set the active Yarn version in the user platform file.
+    /// Set the active Yarn version in the user platform file. Yarn can only be set
+    /// once a Node version is.
     pub fn set_active_yarn(&mut self, yarn_version: Version) -> Fallible<()> {
-        let mut dirty = false;
+        let lock = PlatformLock::acquire()?;
+        self.platform = read_platform()?;
 
-        if let &mut Some(ref mut platform) = &mut self.platform {
-            if platform.yarn != Some(yarn_version.clone()) {
-                platform.yarn = Some(yarn_version);
-                dirty = true;
-            }
-        }
-
-        if dirty {
-            self.save()?;
+        if set_yarn(&mut self.platform, yarn_version)? {
+            self.save(&lock)?;
         }
 
         Ok(())
     }
 
-    /// Set the active Npm version in the user platform file.
+    /// Set the active Npm version in the user platform file. Like Yarn, Npm can only
+    /// be set once a Node version is.
     pub fn set_active_npm(&mut self, npm_version: Version) -> Fallible<()> {
-        let mut dirty = false;
+        let lock = PlatformLock::acquire()?;
+        self.platform = read_platform()?;
 
-        if let &mut Some(ref mut platform) = &mut self.platform {
-            if platform.npm != Some(npm_version.clone()) {
-                platform.npm = Some(npm_version);
-                dirty = true;
-            }
+        if set_npm(&mut self.platform, npm_version)? {
+            self.save(&lock)?;
         }
 
-        if dirty {
-            self.save()?;
+        Ok(())
+    }
+
+    /// Replace the whole user platform with the given Node (and bundled npm) and
+    /// Yarn versions, writing the platform file once, and return the new platform.
+    pub fn pin(
+        &mut self,
+        node_version: NodeVersion,
+        yarn: Option<Version>,
+    ) -> Fallible<PlatformSpec> {
+        let lock = PlatformLock::acquire()?;
+        self.platform = read_platform()?;
+
+        let (node_runtime, npm) = node_version.into_parts();
+        let platform = PlatformSpec {
+            node_runtime,
+            npm: Some(npm),
+            yarn,
+        };
+
+        if self.platform.as_ref() != Some(&platform) {
+            self.platform = Some(platform.clone());
+            self.save(&lock)?;
         }
 
-        Ok(())
+        Ok(platform)
+    }
+
+    /// Writes the platform to the user platform file, first copying the current file
+    /// to a backup that `restore_backup` can recover, and recording the current
+    /// platform in the history that `undo` steps back through. Only the holder of the
+    /// platform lock may write the file, so the lock is required to call this.
+    fn save(&self, _lock: &PlatformLock) -> Fallible<()> {
+        let path = user_platform_file()?;
+        ensure_platform_file_regular(&path)?;
+        let previous = read_platform_file(&path)?;
+        backup_platform(&path, &user_platform_backup_file()?)?;
+        record_history(&path, &user_platform_history_file()?, unix_timestamp())?;
+        self.save_to(&path)?;
+        update_current_node_link(previous.as_ref(), self.platform.as_ref())
+    }
+
+    /// Writes the platform to the platform file at the given path, the counterpart
+    /// of `from_file`. Unlike `save`, this makes no backup.
+    pub fn save_to(&self, path: &Path) -> Fallible<()> {
+        ensure_platform_file_regular(path)?;
+        ensure_containing_dir_exists(&path)?;
+        let src = match &self.platform {
+            &Some(ref platform) => platform.to_serial().to_json()?,
+            &None => serial::Platform::empty().to_json()?,
+        };
+        let result = File::create(path).and_then(|mut file| file.write_all(src.as_bytes()));
+        trace_fs("write", path, result).unknown()
     }
 
-    pub fn save(&self) -> Fallible<()> {
+    /// Replaces the user platform file with the backup made by the last `save`, and
+    /// reloads the platform from it.
+    pub fn restore_backup(&mut self) -> Fallible<()> {
+        let _lock = PlatformLock::acquire()?;
+        // the file being replaced may well be unreadable, and then is treated as empty
+        let previous = read_platform().unwrap_or(None);
+        restore_platform(&user_platform_file()?, &user_platform_backup_file()?)?;
+        self.reload()?;
+        update_current_node_link(previous.as_ref(), self.platform.as_ref())
+    }
+
+    /// Replaces the user platform with the one the last `save` replaced, as recorded
+    /// in the platform history. Each call steps one more entry back, and the undo
+    /// itself isn't recorded, so it can't be undone except by `restore_backup`.
+    pub fn undo(&mut self) -> Fallible<()> {
+        let _lock = PlatformLock::acquire()?;
         let path = user_platform_file()?;
-        let mut file = File::create(&path).unknown()?;
-        match &self.platform {
-            &Some(ref platform) => {
-                let src = platform.to_serial().to_json()?;
-                file.write_all(src.as_bytes()).unknown()?;
+        ensure_platform_file_regular(&path)?;
+        let previous = read_platform_file(&path).unwrap_or(None);
+        self.platform = pop_history(&user_platform_history_file()?)?;
+        backup_platform(&path, &user_platform_backup_file()?)?;
+        self.save_to(&path)?;
+        update_current_node_link(previous.as_ref(), self.platform.as_ref())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::distro::node::save_default_npm_version;
+    use crate::path::tests::with_temp_home;
+    use std::fs::create_dir_all;
+
+    fn platform(node: &str, npm: Option<&str>, yarn: Option<&str>) -> PlatformSpec {
+        PlatformSpec {
+            node_runtime: Version::parse(node).unwrap(),
+            npm: npm.map(|npm| Version::parse(npm).unwrap()),
+            yarn: yarn.map(|yarn| Version::parse(yarn).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_diff_without_platform() {
+        let toolchain = Toolchain { platform: None };
+        assert_eq!(
+            toolchain.diff(&platform("10.15.3", Some("6.4.1"), None)),
+            vec![
+                ToolChange::Added {
+                    tool: "node",
+                    version: Version::parse("10.15.3").unwrap(),
+                },
+                ToolChange::Added {
+                    tool: "npm",
+                    version: Version::parse("6.4.1").unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let toolchain = Toolchain {
+            platform: Some(platform("10.15.3", Some("6.4.1"), Some("1.12.3"))),
+        };
+        assert_eq!(
+            toolchain.diff(&platform("11.11.0", Some("6.4.1"), None)),
+            vec![
+                ToolChange::Changed {
+                    tool: "node",
+                    old: Version::parse("10.15.3").unwrap(),
+                    new: Version::parse("11.11.0").unwrap(),
+                },
+                ToolChange::Removed {
+                    tool: "yarn",
+                    version: Version::parse("1.12.3").unwrap(),
+                },
+            ]
+        );
+        assert_eq!(
+            toolchain.diff(&platform("10.15.3", Some("6.4.1"), Some("1.12.3"))),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_pin() {
+        with_temp_home(|_| {
+            let node = Version::parse("10.15.3").unwrap();
+            let npm = Version::parse("6.4.1").unwrap();
+            // the `current` link is keyed by the npm bundled with the pinned Node
+            save_default_npm_version(&node, &npm).unwrap();
+
+            let mut toolchain = Toolchain { platform: None };
+            let pinned = toolchain
+                .pin(
+                    NodeVersion::new(node, npm),
+                    Some(Version::parse("1.12.3").unwrap()),
+                )
+                .expect("could not pin platform");
+
+            assert_eq!(pinned, platform("10.15.3", Some("6.4.1"), Some("1.12.3")));
+            assert_eq!(toolchain.platform_ref(), Some(&pinned));
+            assert_eq!(read_platform().unwrap(), Some(pinned));
+        });
+    }
+
+    #[test]
+    fn test_lock_held() {
+        with_temp_home(|_| {
+            let lock_file = user_platform_lock_file().unwrap();
+            let _held = PlatformLock::acquire().expect("could not acquire lock");
+            match PlatformLock::acquire_within(Duration::from_millis(100)) {
+                Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                    Some(ErrorDetails::ToolchainLocked { path }) => {
+                        assert_eq!(path, &lock_file.to_string_lossy().to_string())
+                    }
+                    other => panic!("unexpected error: {:?}", other),
+                },
+                Ok(_) => panic!("acquired a lock that was already held"),
             }
-            &None => {
-                file.write_all(b"{}").unknown()?;
+        });
+    }
+
+    #[test]
+    fn test_writer_waits_for_lock() {
+        with_temp_home(|_| {
+            let node = Version::parse("10.15.3").unwrap();
+            let npm = Version::parse("6.4.1").unwrap();
+            save_default_npm_version(&node, &npm).unwrap();
+
+            // another process holds the lock for a while
+            let started = Instant::now();
+            let held = PlatformLock::acquire().expect("could not acquire lock");
+            let holder = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                drop(held);
+            });
+
+            let mut toolchain = Toolchain { platform: None };
+            toolchain
+                .pin(NodeVersion::new(node, npm), None)
+                .expect("could not pin platform");
+            assert!(started.elapsed() >= Duration::from_millis(200));
+            holder.join().unwrap();
+
+            assert_eq!(
+                read_platform().unwrap(),
+                Some(platform("10.15.3", Some("6.4.1"), None))
+            );
+        });
+    }
+
+    #[test]
+    fn test_reload_and_invalidate() {
+        with_temp_home(|_| {
+            let mut lazy = LazyToolchain::new();
+            let mut toolchain = Toolchain::current().unwrap();
+            assert_eq!(lazy.get().unwrap().platform_ref(), None);
+
+            // another process saves a platform
+            let pinned = platform("10.15.3", Some("6.4.1"), None);
+            Toolchain {
+                platform: Some(pinned.clone()),
             }
+            .save_to(&user_platform_file().unwrap())
+            .unwrap();
+
+            toolchain.reload().expect("could not reload toolchain");
+            assert_eq!(toolchain.platform_ref(), Some(&pinned));
+
+            // a loaded toolchain is kept until it is invalidated
+            assert_eq!(lazy.get().unwrap().platform_ref(), None);
+            lazy.invalidate();
+            assert_eq!(lazy.get().unwrap().platform_ref(), Some(&pinned));
+        });
+    }
+
+    #[test]
+    fn test_validate_and_repair() {
+        with_temp_home(|_| {
+            let validate = |platform: Option<PlatformSpec>| {
+                Toolchain { platform }
+                    .validate_and_repair()
+                    .expect("could not validate toolchain")
+            };
+            let version = |version: &str| Version::parse(version).unwrap();
+            assert_eq!(validate(None), vec![]);
+
+            let unpinned_npm = platform("10.15.3", None, Some("1.12.3"));
+            assert_eq!(
+                validate(Some(unpinned_npm.clone())),
+                vec![
+                    RepairAction::MissingNodeImage(version("10.15.3")),
+                    RepairAction::MissingYarnImage(version("1.12.3")),
+                ]
+            );
+
+            // an image directory for the Node version that lacks the bundled npm's
+            // image is still missing
+            save_default_npm_version(&version("10.15.3"), &version("6.4.1")).unwrap();
+            create_dir_all(node_image_dir("10.15.3", "6.9.0").unwrap()).unwrap();
+            create_dir_all(yarn_image_dir("1.12.3").unwrap()).unwrap();
+            assert_eq!(
+                validate(Some(unpinned_npm.clone())),
+                vec![RepairAction::MissingNodeImage(version("10.15.3"))]
+            );
+
+            create_dir_all(node_image_dir("10.15.3", "6.4.1").unwrap()).unwrap();
+            assert_eq!(validate(Some(unpinned_npm)), vec![]);
+            assert_eq!(
+                validate(Some(platform("10.15.3", Some("6.4.1"), None))),
+                vec![]
+            );
+
+            let pinned_npm = platform("10.15.3", Some("6.7.0"), None);
+            assert_eq!(
+                validate(Some(pinned_npm.clone())),
+                vec![RepairAction::MissingNpmImage(version("6.7.0"))]
+            );
+            create_dir_all(npm_image_dir("6.7.0").unwrap()).unwrap();
+            assert_eq!(validate(Some(pinned_npm)), vec![]);
+        });
+    }
+
+    #[test]
+    fn test_set_npm() {
+        let npm = Version::parse("6.9.0").unwrap();
+
+        let mut without_node = None;
+        match set_npm(&mut without_node, npm.clone()) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::NoUserNodeVersion) => {}
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("set without a Node version"),
         }
-        Ok(())
+
+        let mut with_node = Some(platform("10.15.3", Some("6.4.1"), None));
+        assert!(set_npm(&mut with_node, npm.clone()).unwrap());
+        assert_eq!(with_node, Some(platform("10.15.3", Some("6.9.0"), None)));
+        assert!(!set_npm(&mut with_node, npm).unwrap());
+    }
+
+    #[test]
+    fn test_set_yarn() {
+        let yarn = Version::parse("1.15.2").unwrap();
+
+        let mut without_node = None;
+        match set_yarn(&mut without_node, yarn.clone()) {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::NoUserNodeVersion) => {}
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("set without a Node version"),
+        }
+
+        let mut with_node = Some(platform("10.15.3", Some("6.4.1"), None));
+        assert!(set_yarn(&mut with_node, yarn.clone()).unwrap());
+        assert_eq!(
+            with_node,
+            Some(platform("10.15.3", Some("6.4.1"), Some("1.15.2")))
+        );
+        assert!(!set_yarn(&mut with_node, yarn).unwrap());
+    }
+
+    #[test]
+    fn test_from_file_and_save_to() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+
+        let mut toolchain = Toolchain::from_file(&path).expect("could not load toolchain");
+        assert_eq!(toolchain.platform_ref(), None);
+
+        toolchain.platform = Some(platform("10.15.3", Some("6.4.1"), Some("1.12.3")));
+        toolchain.save_to(&path).expect("could not save toolchain");
+
+        let reloaded = Toolchain::from_file(&path).expect("could not reload toolchain");
+        assert_eq!(
+            reloaded.platform_ref(),
+            Some(&platform("10.15.3", Some("6.4.1"), Some("1.12.3")))
+        );
+    }
+
+    #[test]
+    fn test_platform_file_is_dir() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+        std::fs::create_dir(&path).unwrap();
+
+        let check = |result: Fallible<()>| match result {
+            Err(error) => match error.downcast_ref::<ErrorDetails>() {
+                Some(ErrorDetails::PlatformFileNotRegular { .. }) => {}
+                other => panic!("unexpected error: {:?}", other),
+            },
+            Ok(_) => panic!("used a directory as the platform file"),
+        };
+
+        check(Toolchain::from_file(&path).map(|_| ()));
+        let toolchain = Toolchain {
+            platform: Some(platform("10.15.3", Some("6.4.1"), None)),
+        };
+        check(toolchain.save_to(&path));
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn test_save_to_missing_dir() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("tools").join("user").join("platform.json");
+
+        let toolchain = Toolchain {
+            platform: Some(platform("10.15.3", Some("6.4.1"), None)),
+        };
+        toolchain.save_to(&path).expect("could not save toolchain");
+        assert!(path.is_file());
+    }
+
+    #[test]
+    fn test_backup_platform() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+        let backup = dir.path().join("platform.json.bak");
+
+        // nothing to back up yet
+        backup_platform(&path, &backup).unwrap();
+        assert!(!backup.exists());
+
+        let good = r#"{"schema":1,"node":{"runtime":"10.15.3","npm":"6.4.1"},"yarn":"1.12.3"}"#;
+        std::fs::write(&path, good).unwrap();
+        backup_platform(&path, &backup).unwrap();
+        assert_eq!(read_to_string(&backup).unwrap(), good);
+
+        // an empty or corrupt platform doesn't replace a good backup
+        for bad in &["", "{}", "not json"] {
+            std::fs::write(&path, bad).unwrap();
+            backup_platform(&path, &backup).unwrap();
+            assert_eq!(read_to_string(&backup).unwrap(), good);
+        }
+
+        restore_platform(&path, &backup).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), good);
+    }
+
+    #[test]
+    fn test_platform_history() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+        let history = dir.path().join("platform.history");
+        let save = |platform: Option<PlatformSpec>| {
+            Toolchain { platform }.save_to(&path).unwrap();
+        };
+
+        // a missing platform file is recorded as no platform
+        record_history(&path, &history, 1).unwrap();
+        save(Some(platform("10.15.3", Some("6.4.1"), None)));
+        record_history(&path, &history, 2).unwrap();
+        save(Some(platform("11.11.0", Some("6.7.0"), None)));
+
+        let src = read_to_string(&history).unwrap();
+        assert_eq!(src.lines().count(), 2);
+        let newest = src.lines().last().unwrap();
+        assert!(newest.starts_with(r#"{"timestamp":2,"#));
+
+        assert_eq!(
+            pop_history(&history).unwrap(),
+            Some(platform("10.15.3", Some("6.4.1"), None))
+        );
+        assert_eq!(pop_history(&history).unwrap(), None);
+        assert!(pop_history(&history).is_err());
+    }
+
+    #[test]
+    fn test_platform_history_limit() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+        let history = dir.path().join("platform.history");
+
+        for timestamp in 0..(HISTORY_LIMIT as u64 + 5) {
+            record_history(&path, &history, timestamp).unwrap();
+        }
+
+        let entries = read_history(&history).unwrap();
+        assert_eq!(entries.len(), HISTORY_LIMIT);
+        let oldest = serial::HistoryEntry::from_json(&entries[0]).unwrap();
+        assert_eq!(oldest.timestamp, 5);
+
+        // an unreadable line doesn't lose the rest of the history
+        let mut src = read_to_string(&history).unwrap();
+        src.push_str("{\"timestamp\":\n");
+        std::fs::write(&history, src).unwrap();
+        assert_eq!(read_history(&history).unwrap().len(), HISTORY_LIMIT);
+    }
+
+    #[test]
+    fn test_restore_platform_without_backup() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = dir.path().join("platform.json");
+        assert!(restore_platform(&path, &dir.path().join("platform.json.bak")).is_err());
+    }
+
+    #[test]
+    fn test_newly_ignored_npm_pin() {
+        let bundled = Version::parse("6.4.1").unwrap();
+        let pinned = platform("10.15.3", Some("6.7.0"), None);
+        let v670 = Some(Version::parse("6.7.0").unwrap());
+
+        // a pin of the bundled npm isn't ignored
+        let unpinned = platform("10.15.3", Some("6.4.1"), None);
+        assert_eq!(newly_ignored_npm_pin(None, &unpinned, &bundled), None);
+
+        // a new pin, or a pin kept across a change of Node, is reported
+        assert_eq!(newly_ignored_npm_pin(None, &pinned, &bundled), v670);
+        assert_eq!(
+            newly_ignored_npm_pin(Some(&unpinned), &pinned, &bundled),
+            v670
+        );
+        let other_node = platform("10.16.0", Some("6.7.0"), None);
+        assert_eq!(
+            newly_ignored_npm_pin(Some(&other_node), &pinned, &bundled),
+            v670
+        );
+
+        // an unchanged pin has already been reported, whatever else changed
+        let with_yarn = platform("10.15.3", Some("6.7.0"), Some("1.12.3"));
+        assert_eq!(
+            newly_ignored_npm_pin(Some(&with_yarn), &pinned, &bundled),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_replace_dir_link() {
+        let dir = tempfile::tempdir().expect("could not create temp dir");
+        let old_image = dir.path().join("10.15.3");
+        let new_image = dir.path().join("11.10.1");
+        let link = dir.path().join("current");
+
+        replace_dir_link(&old_image, &link).expect("could not create link");
+        assert_eq!(read_link(&link).unwrap(), old_image);
+
+        replace_dir_link(&new_image, &link).expect("could not replace link");
+        assert_eq!(read_link(&link).unwrap(), new_image);
+        assert!(!dir.path().join("current.tmp").exists());
+
+        remove_link(&link).expect("could not remove link");
+        assert!(link.symlink_metadata().is_err());
+        remove_link(&link).expect("could not remove missing link");
     }
 }
@@ -1,7 +1,8 @@
+use crate::error::ErrorDetails;
 use crate::platform::PlatformSpec;
 
 use crate::version::{option_version_serde, version_serde};
-use notion_fail::{Fallible, ResultExt};
+use notion_fail::{throw, Fallible, ResultExt};
 
 use semver::Version;
 use serde::{Deserialize, Serialize};
@@ -15,8 +16,27 @@ pub struct NodeVersion {
     pub npm: Option<Version>,
 }
 
+/// The version of the platform file format written by this version of Notion.
+pub const CURRENT_SCHEMA: u32 = 1;
+
+/// Platform files written before the format was versioned have no `"schema"` field,
+/// and are treated as the first version.
+fn default_schema() -> u32 {
+    1
+}
+
+/// Just the format version of a platform file, which is read first so that files
+/// written by a newer version of Notion are rejected rather than misparsed.
+#[derive(Deserialize)]
+struct Schema {
+    #[serde(default = "default_schema")]
+    schema: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Platform {
+    #[serde(default = "default_schema")]
+    pub schema: u32,
     #[serde(default)]
     pub node: Option<NodeVersion>,
     #[serde(default)]
@@ -34,12 +54,34 @@ impl Platform {
         }))
     }
 
-    /// Deserialize the input JSON String into a Platform
+    /// Deserialize the input JSON String into a Platform, migrating it to the
+    /// current format version if it was written in an older one.
     pub fn from_json(src: String) -> Fallible<Self> {
-        if src.is_empty() {
-            serde_json::de::from_str("{}").unknown()
-        } else {
-            serde_json::de::from_str(&src).unknown()
+        let src = if src.is_empty() { "{}" } else { &src };
+        let Schema { schema } = serde_json::de::from_str(src).unknown()?;
+        if schema > CURRENT_SCHEMA {
+            throw!(ErrorDetails::PlatformSchemaUnsupported { schema });
+        }
+        let platform: Platform = serde_json::de::from_str(src).unknown()?;
+        Ok(platform.migrate())
+    }
+
+    /// Upgrades a Platform read from an older format version to the current one.
+    fn migrate(self) -> Self {
+        // the first version is also the current one, so there is nothing to upgrade
+        // yet; later versions will add a step here for each older version
+        Platform {
+            schema: CURRENT_SCHEMA,
+            ..self
+        }
+    }
+
+    /// A Platform with no tools pinned.
+    pub fn empty() -> Self {
+        Platform {
+            schema: CURRENT_SCHEMA,
+            node: None,
+            yarn: None,
         }
     }
 
@@ -49,9 +91,31 @@ impl Platform {
     }
 }
 
+/// An entry of the platform history file, which records a platform replaced by a
+/// `save` and when it was replaced (in seconds since the Unix epoch). Each entry is
+/// written as a single line of JSON.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub platform: Platform,
+}
+
+impl HistoryEntry {
+    /// Deserialize a line of the history file into a HistoryEntry.
+    pub fn from_json(src: &str) -> Fallible<Self> {
+        serde_json::de::from_str(src).unknown()
+    }
+
+    /// Serialize the HistoryEntry to a single line of JSON.
+    pub fn to_json(&self) -> Fallible<String> {
+        serde_json::to_string(&self).unknown()
+    }
+}
+
 impl PlatformSpec {
     pub fn to_serial(&self) -> Platform {
         Platform {
+            schema: CURRENT_SCHEMA,
             node: Some(NodeVersion {
                 runtime: self.node_runtime.clone(),
                 npm: self.npm.clone(),
@@ -72,6 +136,7 @@ pub mod tests {
     // so these tests will serialized/deserialize in a predictable order
 
     const BASIC_JSON_STR: &'static str = r#"{
+  "schema": 1,
   "node": {
     "runtime": "4.5.6",
     "npm": "7.8.9"
@@ -84,6 +149,7 @@ pub mod tests {
         let json_str = BASIC_JSON_STR.to_string();
         let platform = Platform::from_json(json_str).expect("could not parse JSON string");
         let expected_platform = Platform {
+            schema: 1,
             yarn: Some(Version::parse("1.2.3").expect("could not parse version")),
             node: Some(NodeVersion {
                 runtime: Version::parse("4.5.6").expect("could not parse version"),
@@ -98,12 +164,34 @@ pub mod tests {
         let json_str = "".to_string();
         let platform = Platform::from_json(json_str).expect("could not parse JSON string");
         let expected_platform = Platform {
+            schema: 1,
             node: None,
             yarn: None,
         };
         assert_eq!(platform, expected_platform);
     }
 
+    #[test]
+    fn test_from_json_without_schema() {
+        let json_str = r#"{ "yarn": "1.2.3" }"#.to_string();
+        let platform = Platform::from_json(json_str).expect("could not parse JSON string");
+        assert_eq!(platform.schema, CURRENT_SCHEMA);
+        assert_eq!(
+            platform.yarn,
+            Some(Version::parse("1.2.3").expect("could not parse version"))
+        );
+    }
+
+    #[test]
+    fn test_from_json_newer_schema() {
+        let json_str = BASIC_JSON_STR.replace(r#""schema": 1"#, r#""schema": 99"#);
+        let error = Platform::from_json(json_str).expect_err("a newer schema should be rejected");
+        match error.downcast_ref::<ErrorDetails>() {
+            Some(ErrorDetails::PlatformSchemaUnsupported { schema: 99 }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_to_json() {
         let platform = platform::PlatformSpec {
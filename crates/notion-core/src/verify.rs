@@ -0,0 +1,341 @@
+//! Provides functions for checking that an installed tool image is intact, so that
+//! a corrupted install can be found without reinstalling the tool.
+
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use semver::Version;
+
+use crate::distro::node::{image_exists, read_default_npm_version};
+use crate::distro::package::executables;
+use crate::error::ErrorDetails;
+use crate::path;
+use crate::platform::PlatformSpec;
+use crate::tool::ToolSpec;
+use crate::version::VersionSpec;
+use notion_fail::{throw, Fallible};
+
+cfg_if::cfg_if! {
+    if #[cfg(windows)] {
+        const NODE_EXECUTABLE: &'static str = "node.exe";
+        const NPM_EXECUTABLE: &'static str = "npm.cmd";
+        const YARN_EXECUTABLE: &'static str = "yarn.cmd";
+
+        /// Every file can be executed on Windows, as long as it has the right extension.
+        fn is_executable(_: &Path) -> bool {
+            true
+        }
+    } else {
+        use std::fs::metadata;
+        use std::os::unix::fs::PermissionsExt;
+
+        const NODE_EXECUTABLE: &'static str = "node";
+        const NPM_EXECUTABLE: &'static str = "npm";
+        const YARN_EXECUTABLE: &'static str = "yarn";
+
+        /// Checks whether any of the execute bits are set on a file.
+        fn is_executable(file: &Path) -> bool {
+            metadata(file)
+                .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        }
+    }
+}
+
+/// A way in which an installed tool image doesn't match what Notion expects.
+#[derive(Debug, PartialEq)]
+pub enum Discrepancy {
+    /// The image directory doesn't exist.
+    MissingImage(PathBuf),
+
+    /// The file recording the version of npm bundled with a Node image is missing.
+    MissingNpmVersion(PathBuf),
+
+    /// The `package.json` of a package image could not be read.
+    UnreadableManifest(PathBuf),
+
+    /// The tool's executable isn't in the image directory.
+    MissingBinary(PathBuf),
+
+    /// The tool's executable is in the image directory, but isn't executable.
+    NotExecutable(PathBuf),
+
+    /// The tool's executable could not be run.
+    FailedToRun { binary: PathBuf, error: String },
+
+    /// The tool's executable reports a different version than the one installed.
+    VersionMismatch { expected: Version, actual: String },
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::MissingImage(dir) => {
+                write!(f, "image directory {} is missing", dir.display())
+            }
+            Discrepancy::MissingNpmVersion(file) => {
+                write!(f, "npm version file {} is missing", file.display())
+            }
+            Discrepancy::UnreadableManifest(file) => {
+                write!(f, "{} could not be read", file.display())
+            }
+            Discrepancy::MissingBinary(binary) => write!(f, "{} is missing", binary.display()),
+            Discrepancy::NotExecutable(binary) => {
+                write!(f, "{} is not executable", binary.display())
+            }
+            Discrepancy::FailedToRun { binary, error } => {
+                write!(f, "{} could not be run: {}", binary.display(), error)
+            }
+            Discrepancy::VersionMismatch { expected, actual } => {
+                write!(f, "expected version v{}, but found {}", expected, actual)
+            }
+        }
+    }
+}
+
+/// The result of checking an installed tool image.
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// The tool that was checked, as `name@version`.
+    pub tool: String,
+
+    /// Every problem found with the image, which is empty if it is intact.
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl VerifyReport {
+    /// Whether the image passed every check.
+    pub fn is_ok(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// The tools of a platform that have images of their own, at their pinned versions.
+/// A pinned npm that is the one bundled with Node is part of the Node image, and if
+/// the bundled version isn't recorded, checking Node reports that instead.
+pub fn platform_tools(platform: &PlatformSpec) -> Fallible<Vec<ToolSpec>> {
+    let mut tools = vec![ToolSpec::Node(VersionSpec::exact(&platform.node_runtime))];
+
+    let bundled_npm = read_default_npm_version(&platform.node_runtime)?;
+    if let Some(npm) = bundled_npm.and_then(|bundled| platform.standalone_npm(&bundled)) {
+        tools.push(ToolSpec::Npm(VersionSpec::exact(&npm)));
+    }
+    if let Some(ref yarn) = platform.yarn {
+        tools.push(ToolSpec::Yarn(VersionSpec::exact(yarn)));
+    }
+    Ok(tools)
+}
+
+/// Checks that the image of a tool version is intact: its image directory exists, its
+/// executables are present (and for Node, npm, and Yarn, executable), and for Node,
+/// that `node --version` reports the installed version. This only reads the image,
+/// and only an exact version of a tool can be checked.
+pub fn verify_image(tool: &ToolSpec) -> Fallible<VerifyReport> {
+    let version = match tool {
+        ToolSpec::Node(VersionSpec::Exact(version))
+        | ToolSpec::Yarn(VersionSpec::Exact(version))
+        | ToolSpec::Npm(VersionSpec::Exact(version))
+        | ToolSpec::Package(_, VersionSpec::Exact(version)) => version,
+        _ => throw!(ErrorDetails::VerifyNeedsExactVersion {
+            tool: tool.to_string(),
+        }),
+    };
+
+    let discrepancies = match tool {
+        ToolSpec::Node(_) => verify_node(version)?,
+        ToolSpec::Yarn(_) => {
+            let version = version.to_string();
+            let binary = path::yarn_image_bin_dir(&version)?.join(YARN_EXECUTABLE);
+            verify_dir(&path::yarn_image_dir(&version)?)
+                .or_else(|| verify_binary(&binary))
+                .into_iter()
+                .collect()
+        }
+        ToolSpec::Npm(_) => {
+            let version = version.to_string();
+            let binary = path::npm_image_bin_dir(&version)?.join(NPM_EXECUTABLE);
+            verify_dir(&path::npm_image_dir(&version)?)
+                .or_else(|| verify_binary(&binary))
+                .into_iter()
+                .collect()
+        }
+        ToolSpec::Package(name, _) => {
+            verify_package(&path::package_image_dir(name, &version.to_string())?)
+        }
+    };
+
+    Ok(VerifyReport {
+        tool: tool.to_string(),
+        discrepancies,
+    })
+}
+
+/// Checks the image of a Node version, including that its `node` executable runs
+/// and reports that version.
+fn verify_node(version: &Version) -> Fallible<Vec<Discrepancy>> {
+    if !image_exists(version)? {
+        let dir = path::node_image_version_dir(&version.to_string())?;
+        return Ok(vec![Discrepancy::MissingImage(dir)]);
+    }
+
+    let npm = match read_default_npm_version(version)? {
+        Some(npm) => npm,
+        None => {
+            let file = path::node_npm_version_file(&version.to_string())?;
+            return Ok(vec![Discrepancy::MissingNpmVersion(file)]);
+        }
+    };
+    let binary =
+        path::node_image_bin_dir(&version.to_string(), &npm.to_string())?.join(NODE_EXECUTABLE);
+    if let Some(discrepancy) = verify_binary(&binary) {
+        return Ok(vec![discrepancy]);
+    }
+
+    let output = match Command::new(&binary).arg("--version").output() {
+        Ok(output) => output,
+        Err(error) => {
+            return Ok(vec![Discrepancy::FailedToRun {
+                binary,
+                error: error.to_string(),
+            }]);
+        }
+    };
+
+    if !output.status.success() {
+        return Ok(vec![Discrepancy::FailedToRun {
+            binary,
+            error: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }]);
+    }
+
+    let actual = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(check_version_output(version, actual).into_iter().collect())
+}
+
+/// Compares the output of `node --version` (e.g. `v10.15.3`) to the expected version.
+pub(crate) fn check_version_output(expected: &Version, actual: String) -> Option<Discrepancy> {
+    if actual.trim_start_matches('v') == expected.to_string() {
+        None
+    } else {
+        Some(Discrepancy::VersionMismatch {
+            expected: expected.clone(),
+            actual,
+        })
+    }
+}
+
+/// Checks the image of a package, including that each executable named in its
+/// `package.json` is present.
+fn verify_package(dir: &Path) -> Vec<Discrepancy> {
+    if let Some(discrepancy) = verify_dir(dir) {
+        return vec![discrepancy];
+    }
+
+    match executables(dir) {
+        Ok(bins) => bins
+            .into_iter()
+            .map(|bin| dir.join(bin.path))
+            .filter(|binary| !binary.is_file())
+            .map(Discrepancy::MissingBinary)
+            .collect(),
+        Err(_) => vec![Discrepancy::UnreadableManifest(dir.join("package.json"))],
+    }
+}
+
+/// Checks that an image directory exists.
+fn verify_dir(dir: &Path) -> Option<Discrepancy> {
+    if dir.is_dir() {
+        None
+    } else {
+        Some(Discrepancy::MissingImage(dir.to_path_buf()))
+    }
+}
+
+/// Checks that a tool's executable exists and can be executed.
+fn verify_binary(binary: &Path) -> Option<Discrepancy> {
+    if !binary.is_file() {
+        Some(Discrepancy::MissingBinary(binary.to_path_buf()))
+    } else if !is_executable(binary) {
+        Some(Discrepancy::NotExecutable(binary.to_path_buf()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check_version_output() {
+        let version = Version::parse("10.15.3").unwrap();
+        assert_eq!(check_version_output(&version, "v10.15.3".to_string()), None);
+        assert_eq!(
+            check_version_output(&version, "v8.15.1".to_string()),
+            Some(Discrepancy::VersionMismatch {
+                expected: version.clone(),
+                actual: "v8.15.1".to_string(),
+            })
+        );
+        assert!(check_version_output(&version, "".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_verify_binary() {
+        let dir = tempdir().expect("could not create temp dir");
+        let binary = dir.path().join("node");
+
+        assert_eq!(
+            verify_binary(&binary),
+            Some(Discrepancy::MissingBinary(binary.clone()))
+        );
+
+        File::create(&binary).unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            verify_binary(&binary),
+            Some(Discrepancy::NotExecutable(binary.clone()))
+        );
+    }
+
+    #[test]
+    fn test_verify_package() {
+        let dir = tempdir().expect("could not create temp dir");
+        let image = dir.path().join("cowsay");
+
+        assert_eq!(
+            verify_package(&image),
+            vec![Discrepancy::MissingImage(image.clone())]
+        );
+
+        fs::create_dir(&image).unwrap();
+        assert_eq!(
+            verify_package(&image),
+            vec![Discrepancy::UnreadableManifest(image.join("package.json"))]
+        );
+
+        fs::write(
+            image.join("package.json"),
+            r#"{"name": "cowsay", "version": "1.4.0", "bin": {"cowsay": "./cli.js", "cowthink": "./cli.js"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_package(&image),
+            vec![
+                Discrepancy::MissingBinary(image.join("./cli.js")),
+                Discrepancy::MissingBinary(image.join("./cli.js")),
+            ]
+        );
+
+        File::create(image.join("cli.js")).unwrap();
+        assert_eq!(verify_package(&image), vec![]);
+    }
+
+    #[test]
+    fn test_verify_needs_exact_version() {
+        assert!(verify_image(&ToolSpec::Node(VersionSpec::Latest)).is_err());
+    }
+}
@@ -3,33 +3,45 @@ pub(crate) mod serial;
 use std::fmt;
 use std::str::FromStr;
 
-use semver::{ReqParseError, SemVerError, Version, VersionReq};
+use semver::{ReqParseError, Version, VersionReq};
 
+use crate::config::config;
 use crate::error::ErrorDetails;
 use notion_fail::{Fallible, ResultExt};
 
 use self::serial::parse_requirements;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum VersionSpec {
+    /// No version was given. Each command decides what that means, e.g. by using the
+    /// latest version, so it is never confused with an explicit `latest`.
+    None,
     Latest,
+    Lts,
     Semver(VersionReq),
     Exact(Version),
+    /// A named dist-tag published to the npm registry, such as `next` or `beta`.
+    Tag(String),
 }
 
+/// Displays a spec in a form that `VersionSpec::parse` parses back to the same spec.
+/// An unspecified version has no such form, and displays as nothing.
 impl fmt::Display for VersionSpec {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match *self {
+            VersionSpec::None => Ok(()),
             VersionSpec::Latest => write!(f, "latest"),
+            VersionSpec::Lts => write!(f, "lts"),
             VersionSpec::Semver(ref req) => req.fmt(f),
             VersionSpec::Exact(ref version) => version.fmt(f),
+            VersionSpec::Tag(ref tag) => tag.fmt(f),
         }
     }
 }
 
 impl Default for VersionSpec {
     fn default() -> Self {
-        VersionSpec::Latest
+        VersionSpec::None
     }
 }
 
@@ -40,15 +52,17 @@ impl VersionSpec {
 
     pub fn parse(s: impl AsRef<str>) -> Fallible<Self> {
         let s = s.as_ref();
-        s.parse().with_context(version_req_parse_error)
+        s.parse().with_context(version_parse_error(s))
     }
 
     pub fn parse_requirements(s: impl AsRef<str>) -> Fallible<VersionReq> {
-        parse_requirements(s.as_ref()).with_context(version_req_parse_error)
+        let s = s.as_ref();
+        parse_requirements(s).with_context(version_parse_error(s))
     }
 
     pub fn parse_version(s: impl AsRef<str>) -> Fallible<Version> {
-        Version::parse(s.as_ref()).with_context(version_parse_error)
+        let s = s.as_ref();
+        Version::parse(s).with_context(version_parse_error(s))
     }
 }
 
@@ -60,26 +74,86 @@ impl FromStr for VersionSpec {
             return Ok(VersionSpec::Latest);
         }
 
+        if s == "lts" {
+            return Ok(VersionSpec::Lts);
+        }
+
         if let Ok(ref exact) = VersionSpec::parse_version(s) {
-            Ok(VersionSpec::exact(exact))
-        } else {
-            Ok(VersionSpec::Semver(parse_requirements(s)?))
+            return Ok(VersionSpec::exact(exact));
+        }
+
+        match parse_requirements(s) {
+            Ok(requirement) => Ok(VersionSpec::Semver(requirement)),
+            Err(_) if is_tag(s) => Ok(VersionSpec::Tag(s.to_string())),
+            Err(error) => Err(error),
         }
     }
 }
 
-fn version_req_parse_error(error: &ReqParseError) -> ErrorDetails {
-    ErrorDetails::VersionParseError {
-        error: error.to_string(),
+// dist-tags start with a letter and can't themselves be valid versions or ranges
+fn is_tag(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        }
+        _ => false,
     }
 }
 
-fn version_parse_error(error: &SemVerError) -> ErrorDetails {
-    ErrorDetails::VersionParseError {
+/// Produces the error for a version or requirement that couldn't be parsed, naming
+/// the input as given so that the user can tell which one it was.
+fn version_parse_error<'a, E>(input: &'a str) -> impl FnOnce(&E) -> ErrorDetails + 'a
+where
+    E: fmt::Display,
+{
+    move |error| ErrorDetails::VersionParseError {
+        input: input.to_string(),
         error: error.to_string(),
     }
 }
 
+/// Whether version ranges may be matched by prerelease versions, as requested with
+/// `--pre`. By default they can't be.
+pub fn include_prereleases() -> bool {
+    // A shim doesn't validate the settings up front, and an invalid one (e.g. NOTION_ARCH)
+    // is reported by whatever reads it, so version matching falls back to releases only.
+    config().map(|config| config.prerelease()).unwrap_or(false)
+}
+
+/// Checks whether `version` satisfies the range `requirement`.
+///
+/// Following semver, a prerelease only satisfies a range that names a prerelease of
+/// the same version, so e.g. `^1.0.0` is never satisfied by `2.0.0-beta` or
+/// `1.1.0-beta`. When `include_prereleases` is set, a prerelease also satisfies any
+/// range that the release it precedes satisfies, so `^1.0.0` is satisfied by
+/// `1.1.0-beta` but still not by `2.0.0-beta`.
+pub fn matches(requirement: &VersionReq, version: &Version, include_prereleases: bool) -> bool {
+    if requirement.matches(version) {
+        return true;
+    }
+
+    if include_prereleases && version.is_prerelease() {
+        let mut release = version.clone();
+        release.pre.clear();
+        return requirement.matches(&release);
+    }
+
+    false
+}
+
+/// Sorts versions from oldest to newest by semver precedence, so e.g. `9.0.0` comes
+/// before `10.0.0`, and a prerelease like `1.0.0-alpha` comes before `1.0.0`.
+pub fn sort_asc(versions: &mut [Version]) {
+    versions.sort();
+}
+
+/// Sorts versions from newest to oldest by semver precedence.
+pub fn sort_desc(versions: &mut [Version]) {
+    versions.sort();
+    versions.reverse();
+}
+
 // remove the leading 'v' from the version string, if present
 fn trim_version(s: &str) -> &str {
     let s = s.trim();
@@ -163,3 +237,152 @@ pub mod option_version_serde {
     }
 
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::{matches, sort_asc, sort_desc, VersionSpec};
+    use semver::{Version, VersionReq};
+
+    fn versions(srcs: &[&str]) -> Vec<Version> {
+        srcs.iter()
+            .map(|src| Version::parse(src).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut sorted = versions(&[
+            "10.0.0",
+            "1.0.0",
+            "9.0.0",
+            "1.0.0-beta",
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+        ]);
+
+        sort_asc(&mut sorted);
+        assert_eq!(
+            sorted,
+            versions(&[
+                "1.0.0-alpha",
+                "1.0.0-alpha.1",
+                "1.0.0-beta",
+                "1.0.0",
+                "9.0.0",
+                "10.0.0",
+            ])
+        );
+
+        sort_desc(&mut sorted);
+        assert_eq!(
+            sorted,
+            versions(&[
+                "10.0.0",
+                "9.0.0",
+                "1.0.0",
+                "1.0.0-beta",
+                "1.0.0-alpha.1",
+                "1.0.0-alpha",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_matches_excludes_prereleases() {
+        let caret = VersionReq::parse("^1.0.0").unwrap();
+        let version = |src| Version::parse(src).unwrap();
+
+        assert!(matches(&caret, &version("1.2.0"), false));
+        assert!(!matches(&caret, &version("1.2.0-beta"), false));
+        assert!(!matches(&caret, &version("2.0.0-beta"), false));
+
+        // a range naming a prerelease is matched by later prereleases of that version
+        let pre = VersionReq::parse(">=2.0.0-beta.1").unwrap();
+        assert!(matches(&pre, &version("2.0.0-beta.2"), false));
+        assert!(!matches(&pre, &version("2.1.0-beta.1"), false));
+
+        // an exactly specified prerelease is always allowed
+        let exact = VersionReq::parse("=2.0.0-beta.1").unwrap();
+        assert!(matches(&exact, &version("2.0.0-beta.1"), false));
+        assert_eq!(
+            VersionSpec::parse("2.0.0-beta.1").unwrap(),
+            VersionSpec::Exact(version("2.0.0-beta.1"))
+        );
+    }
+
+    #[test]
+    fn test_matches_including_prereleases() {
+        let caret = VersionReq::parse("^1.0.0").unwrap();
+        let version = |src| Version::parse(src).unwrap();
+
+        assert!(matches(&caret, &version("1.2.0"), true));
+        assert!(matches(&caret, &version("1.2.0-beta"), true));
+        assert!(!matches(&caret, &version("2.0.0-beta"), true));
+        assert!(!matches(&caret, &version("0.9.0-beta"), true));
+
+        let exact = VersionReq::parse("=2.0.0-beta.1").unwrap();
+        assert!(matches(&exact, &version("2.0.0-beta.1"), true));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let specs = [
+            "latest",
+            "lts",
+            "1.2.3",
+            "10.0.0-rc.1",
+            "^1.2.3",
+            "~1.2",
+            "=1.2.3",
+            ">=1.2.3",
+            ">=8.0.0 <11.0.0",
+            "<2",
+            "*",
+            "next",
+            "beta-2",
+        ];
+
+        for src in specs.iter() {
+            let spec = VersionSpec::parse(src).expect("could not parse spec");
+            let displayed = spec.to_string();
+            assert_eq!(
+                VersionSpec::parse(&displayed).expect("could not re-parse displayed spec"),
+                spec,
+                "{} displayed as {}",
+                src,
+                displayed
+            );
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(VersionSpec::parse("latest").unwrap().to_string(), "latest");
+        assert_eq!(VersionSpec::parse("1.2.3").unwrap().to_string(), "1.2.3");
+        assert_eq!(VersionSpec::parse("next").unwrap().to_string(), "next");
+        assert_eq!(
+            VersionSpec::parse(">=1.2.3").unwrap().to_string(),
+            ">= 1.2.3"
+        );
+        assert_eq!(
+            VersionSpec::parse(">= 1.2.3").unwrap(),
+            VersionSpec::parse(">=1.2.3").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unspecified_is_not_latest() {
+        assert_eq!(VersionSpec::default(), VersionSpec::None);
+        assert_ne!(VersionSpec::default(), VersionSpec::Latest);
+        assert_eq!(VersionSpec::None.to_string(), "");
+    }
+
+    #[test]
+    fn test_parse_error_names_input() {
+        let error = VersionSpec::parse_version("v1.2").unwrap_err();
+        assert!(error.to_string().contains("'v1.2'"));
+
+        let error = VersionSpec::parse("1.2.3.4.5").unwrap_err();
+        assert!(error.to_string().contains("'1.2.3.4.5'"));
+    }
+}
@@ -2,22 +2,60 @@ use semver::{ReqParseError, VersionReq};
 
 pub fn parse_requirements(src: &str) -> Result<VersionReq, ReqParseError> {
     let src = src.trim();
-    if src.len() > 0 && src.chars().next().unwrap().is_digit(10) {
-        let defaulted = format!("={}", src);
-        VersionReq::parse(&defaulted)
-    } else if src.len() > 0 && src.chars().next().unwrap() == 'v' {
-        let defaulted = src.replacen("v", "=", 1);
-        VersionReq::parse(&defaulted)
-    } else {
-        VersionReq::parse(src)
+    if !src.contains("||") && !src.contains(" - ") {
+        let clauses = split_clauses(src);
+        if clauses.len() > 1 {
+            let clauses: Vec<String> = clauses
+                .iter()
+                .map(|clause| default_to_exact(clause))
+                .collect();
+            return VersionReq::parse(&clauses.join(", "));
+        }
     }
+
+    VersionReq::parse(&default_to_exact(src))
+}
+
+/// Makes a bare version (`1.2.3` or `v1.2.3`) require exactly that version, as it
+/// does in `package.json`, rather than the caret range `semver` would read it as.
+fn default_to_exact(clause: &str) -> String {
+    match clause.chars().next() {
+        Some(first) if first.is_digit(10) => format!("={}", clause),
+        Some('v') => clause.replacen("v", "=", 1),
+        _ => clause.to_string(),
+    }
+}
+
+/// Splits a requirement into its comparator clauses, which can be separated by commas
+/// (`>=1.2.0, <2.0.0`) or, as in `package.json` files, by spaces (`>=1.2.0 <2.0.0`).
+/// An operator separated from its version by a space (`>= 1.2.0`) stays one clause.
+fn split_clauses(src: &str) -> Vec<String> {
+    let mut clauses = Vec::new();
+    let mut operator = String::new();
+    let tokens = src
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty());
+
+    for token in tokens {
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            operator.push_str(token);
+        } else {
+            clauses.push(format!("{}{}", operator, token));
+            operator.clear();
+        }
+    }
+
+    if !operator.is_empty() {
+        clauses.push(operator);
+    }
+    clauses
 }
 
 #[cfg(test)]
 pub mod tests {
 
     use crate::version::serial::parse_requirements;
-    use semver::VersionReq;
+    use semver::{Version, VersionReq};
 
     #[test]
     fn test_parse_requirements() {
@@ -42,4 +80,37 @@ pub mod tests {
             VersionReq::parse(">=1.4").unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_requirements_bounded_range() {
+        let expected = VersionReq::parse(">=1.2.0, <2.0.0").unwrap();
+        assert_eq!(parse_requirements(">=1.2.0 <2.0.0").unwrap(), expected);
+        assert_eq!(parse_requirements(">=1.2.0, <2.0.0").unwrap(), expected);
+        assert_eq!(parse_requirements(">= 1.2.0 < 2.0.0").unwrap(), expected);
+
+        let requirement = parse_requirements(">=1.2.0 <2.0.0").unwrap();
+        assert!(requirement.matches(&Version::parse("1.9.3").unwrap()));
+        assert!(!requirement.matches(&Version::parse("2.0.0").unwrap()));
+        assert!(!requirement.matches(&Version::parse("1.1.9").unwrap()));
+    }
+
+    #[test]
+    fn test_parse_requirements_bare_version_clause() {
+        assert_eq!(
+            parse_requirements(">=1.0 1.2.3").unwrap(),
+            VersionReq::parse(">=1.0, =1.2.3").unwrap()
+        );
+        assert_eq!(
+            parse_requirements("1.2.3, >=1.0").unwrap(),
+            VersionReq::parse("=1.2.3, >=1.0").unwrap()
+        );
+        assert_eq!(
+            parse_requirements(">=1.0 v1.2.3").unwrap(),
+            VersionReq::parse(">=1.0, =1.2.3").unwrap()
+        );
+
+        let requirement = parse_requirements(">=1.0 1.2.3").unwrap();
+        assert!(requirement.matches(&Version::parse("1.2.3").unwrap()));
+        assert!(!requirement.matches(&Version::parse("1.2.4").unwrap()));
+    }
 }
@@ -14,6 +14,7 @@ use syn::{DeriveInput, Lit, NestedMeta};
 pub fn notion_fail(token_stream: TokenStream) -> TokenStream {
     let input: DeriveInput = syn::parse(token_stream).unwrap();
     let name = &input.ident;
+    let kind = name.to_string();
 
     let mut code = Ident::new("UnknownError", Span::call_site());
     let mut code_set = false;
@@ -73,6 +74,10 @@ pub fn notion_fail(token_stream: TokenStream) -> TokenStream {
             fn is_user_friendly(&self) -> bool {
                 #is_friendly
             }
+
+            fn kind(&self) -> String {
+                #kind.to_string()
+            }
         }
     };
 
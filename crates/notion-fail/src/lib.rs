@@ -23,6 +23,12 @@
 //! The `NotionFail::exit_code()` method allows each error type to indicate what the
 //! process exit code should be if the error is the reason for exiting Notion.
 //!
+//! ## Structured output
+//!
+//! The `NotionFail::kind()` method names the kind of an error (by default, the name of
+//! its type or enum variant), which `NotionError::to_json()` includes alongside the
+//! exit code and message so that scripts can consume errors without parsing the text.
+//!
 //! # The `NotionError` type and `Fallible` functions
 //!
 //! The main error type provided by this crate is `NotionError`. This acts more
@@ -247,7 +253,10 @@ macro_rules! throw {
 }
 
 /// Exit codes supported by the NotionFail trait.
-#[derive(Copy, Clone, Debug, Serialize)]
+///
+/// The numeric value of each exit code is part of Notion's public interface, so
+/// existing assignments must not change; new exit codes get new values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
 pub enum ExitCode {
     /// No error occurred.
     Success = 0,
@@ -276,6 +285,12 @@ pub enum ExitCode {
     /// The command or feature is not yet implemented.
     NotYetImplemented = 9,
 
+    /// A downloaded or unpacked file was corrupt or incomplete.
+    IntegrityError = 10,
+
+    /// Another Notion process held a lock for too long; retrying later may succeed.
+    Locked = 11,
+
     /// The requested executable could not be run.
     ExecutionFailure = 126,
 
@@ -284,8 +299,61 @@ pub enum ExitCode {
 }
 
 impl ExitCode {
+    /// Returns the numeric value of this exit code.
+    pub fn as_i32(self) -> i32 {
+        self as i32
+    }
+
+    /// Returns the exit code with the given numeric value, if any.
+    pub fn from_i32(code: i32) -> Option<ExitCode> {
+        match code {
+            0 => Some(ExitCode::Success),
+            1 => Some(ExitCode::UnknownError),
+            3 => Some(ExitCode::InvalidArguments),
+            4 => Some(ExitCode::NoVersionMatch),
+            5 => Some(ExitCode::NetworkError),
+            6 => Some(ExitCode::EnvironmentError),
+            7 => Some(ExitCode::FileSystemError),
+            8 => Some(ExitCode::ConfigurationError),
+            9 => Some(ExitCode::NotYetImplemented),
+            10 => Some(ExitCode::IntegrityError),
+            11 => Some(ExitCode::Locked),
+            126 => Some(ExitCode::ExecutionFailure),
+            127 => Some(ExitCode::ExecutableNotFound),
+            _ => None,
+        }
+    }
+
+    /// Returns a one-line explanation of what this exit code means, for users and
+    /// for authors of scripts that run Notion.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ExitCode::Success => "The command completed successfully.",
+            ExitCode::UnknownError => "An unexpected error occurred.",
+            ExitCode::InvalidArguments => {
+                "The command-line arguments were invalid or could not be combined."
+            }
+            ExitCode::NoVersionMatch => "No available version matched the requested version.",
+            ExitCode::NetworkError => "A network request failed or returned an unusable response.",
+            ExitCode::EnvironmentError => {
+                "A required environment variable was unset or had an invalid value."
+            }
+            ExitCode::FileSystemError => "A file or directory could not be read or written.",
+            ExitCode::ConfigurationError => {
+                "A package.json or Notion configuration file is missing or incorrect."
+            }
+            ExitCode::NotYetImplemented => "The command or feature is not yet implemented.",
+            ExitCode::IntegrityError => "A downloaded or unpacked file was corrupt or incomplete.",
+            ExitCode::Locked => {
+                "Another Notion process held a lock for too long; retrying later may succeed."
+            }
+            ExitCode::ExecutionFailure => "The requested executable could not be run.",
+            ExitCode::ExecutableNotFound => "The requested executable could not be found.",
+        }
+    }
+
     pub fn exit(self) -> ! {
-        exit(self as i32);
+        exit(self.as_i32());
     }
 }
 
@@ -296,6 +364,25 @@ pub trait NotionFail: Fail {
 
     /// Returns the process exit code that should be returned if the process exits with this error.
     fn exit_code(&self) -> ExitCode;
+
+    /// Returns the name of this kind of error, for machine-readable error reporting.
+    ///
+    /// The default implementation takes the leading identifier of the `Debug` output,
+    /// which is the variant name for a derived `Debug` on an enum.
+    fn kind(&self) -> String {
+        format!("{:?}", self)
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect()
+    }
+}
+
+/// The structured form of an error, as produced by `NotionError::to_json()`.
+#[derive(Serialize)]
+struct JsonError<'a> {
+    code: i32,
+    kind: &'a str,
+    message: String,
 }
 
 /// The `NotionError` type, which can contain any Notion failure.
@@ -309,6 +396,9 @@ pub struct NotionError {
 
     /// The result of `error.exit_code()`.
     exit_code: ExitCode,
+
+    /// The result of `error.kind()`.
+    kind: String,
 }
 
 impl Fail for NotionError {
@@ -361,16 +451,36 @@ impl NotionError {
     pub fn exit_code(&self) -> ExitCode {
         self.exit_code
     }
+
+    /// Returns the name of the kind of this error.
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Produces a JSON representation of this error, with its exit code, kind, and
+    /// message, e.g. `{"code":1,"kind":"NodeVersionNotFound","message":"..."}`.
+    pub fn to_json(&self) -> String {
+        let json = JsonError {
+            code: self.exit_code.as_i32(),
+            kind: &self.kind,
+            message: self.to_string(),
+        };
+
+        // Serializing a struct of numbers and strings can't fail.
+        serde_json::to_string(&json).unwrap()
+    }
 }
 
 impl<T: NotionFail> From<T> for NotionError {
     fn from(failure: T) -> Self {
         let user_friendly = failure.is_user_friendly();
         let exit_code = failure.exit_code();
+        let kind = failure.kind();
         NotionError {
             error: failure.into(),
             user_friendly,
             exit_code,
+            kind,
         }
     }
 }
@@ -474,7 +584,80 @@ impl<D: NotionFail> NotionFail for failure::Context<D> {
     fn exit_code(&self) -> ExitCode {
         self.get_context().exit_code()
     }
+
+    fn kind(&self) -> String {
+        self.get_context().kind()
+    }
 }
 
 /// A convenient shorthand for `Result` types that produce `NotionError`s.
 pub type Fallible<T> = Result<T, NotionError>;
+
+#[cfg(test)]
+pub mod tests {
+    use super::{ExitCode, FailExt};
+    use std::io;
+
+    #[test]
+    fn test_exit_code_round_trip() {
+        let codes = [
+            ExitCode::Success,
+            ExitCode::UnknownError,
+            ExitCode::InvalidArguments,
+            ExitCode::NoVersionMatch,
+            ExitCode::NetworkError,
+            ExitCode::EnvironmentError,
+            ExitCode::FileSystemError,
+            ExitCode::ConfigurationError,
+            ExitCode::NotYetImplemented,
+            ExitCode::IntegrityError,
+            ExitCode::Locked,
+            ExitCode::ExecutionFailure,
+            ExitCode::ExecutableNotFound,
+        ];
+
+        for &code in codes.iter() {
+            assert_eq!(ExitCode::from_i32(code.as_i32()), Some(code));
+        }
+    }
+
+    #[test]
+    fn test_exit_code_describe() {
+        // `describe` matches exhaustively, so every code has a description; this
+        // checks that each one is a distinct, single line.
+        let mut descriptions = Vec::new();
+        for code in (0..256).filter_map(ExitCode::from_i32) {
+            let description = code.describe();
+            assert!(!description.is_empty(), "{:?} has no description", code);
+            assert!(!description.contains('\n'), "{:?} spans lines", code);
+            assert!(
+                !descriptions.contains(&description),
+                "{:?} repeats a description",
+                code
+            );
+            descriptions.push(description);
+        }
+        assert_eq!(descriptions.len(), 13);
+    }
+
+    #[test]
+    fn test_exit_code_locked_value() {
+        assert_eq!(ExitCode::Locked.as_i32(), 11);
+    }
+
+    #[test]
+    fn test_exit_code_unknown_value() {
+        assert_eq!(ExitCode::from_i32(2), None);
+        assert_eq!(ExitCode::from_i32(-1), None);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let error = io::Error::new(io::ErrorKind::Other, "disk on fire").unknown();
+        let json: serde_json::Value = serde_json::from_str(&error.to_json()).unwrap();
+
+        assert_eq!(json["code"], ExitCode::UnknownError.as_i32());
+        assert_eq!(json["kind"], "UnknownNotionError");
+        assert_eq!(json["message"], "An unknown error has occurred");
+    }
+}
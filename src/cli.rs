@@ -1,7 +1,12 @@
+use std::env;
+
 use structopt::StructOpt;
 
 use crate::command::{self, Command};
+use notion_core::config::init_config;
+use notion_core::env::{ARCH_OVERRIDE, FORCE_FETCH, PRERELEASE, QUIET};
 use notion_core::session::Session;
+use notion_core::style::NOTION_VERBOSE;
 use notion_fail::{ExitCode, Fallible};
 
 #[derive(StructOpt)]
@@ -24,11 +29,44 @@ pub(crate) struct Notion {
     #[structopt(subcommand)]
     pub(crate) command: Option<Subcommand>,
 
-    // not yet implemented!
     #[structopt(long = "verbose", help = "Enables verbose diagnostics", global = true)]
-    #[allow(dead_code)]
     pub(crate) verbose: bool,
 
+    #[structopt(
+        long = "quiet",
+        help = "Prevents progress and informational output, printing only errors",
+        global = true
+    )]
+    pub(crate) quiet: bool,
+
+    #[structopt(
+        long = "force-fetch",
+        help = "Ignores cached registry indexes and fetches them again",
+        global = true
+    )]
+    pub(crate) force_fetch: bool,
+
+    #[structopt(
+        long = "pre",
+        help = "Allows version ranges to match prerelease versions, e.g. 2.0.0-beta.1",
+        global = true
+    )]
+    pub(crate) pre: bool,
+
+    #[structopt(
+        long = "arch",
+        help = "Fetches Node distributions for the given architecture, e.g. x64",
+        global = true
+    )]
+    pub(crate) arch: Option<String>,
+
+    #[structopt(
+        long = "json",
+        help = "Reports errors as JSON, for use by scripts",
+        global = true
+    )]
+    pub(crate) json: bool,
+
     #[structopt(
         short = "v",
         long = "version",
@@ -39,6 +77,32 @@ pub(crate) struct Notion {
 
 impl Notion {
     pub(crate) fn run(self, session: &mut Session) -> Fallible<ExitCode> {
+        let mut flags = Vec::new();
+
+        if self.force_fetch {
+            flags.push((FORCE_FETCH, "1".to_string()));
+        }
+
+        if self.pre {
+            flags.push((PRERELEASE, "1".to_string()));
+        }
+
+        if let Some(arch) = self.arch {
+            flags.push((ARCH_OVERRIDE, arch));
+        }
+
+        if self.verbose {
+            // errors are displayed after `run` returns, so record the flag for the error reporter
+            env::set_var(NOTION_VERBOSE, "1");
+        }
+
+        if self.quiet {
+            flags.push((QUIET, "1".to_string()));
+        }
+
+        // load the settings now, so that invalid values are reported before any work is done
+        init_config(&flags)?;
+
         if self.version {
             println!("{}", env!("CARGO_PKG_VERSION"));
             Ok(ExitCode::Success)
@@ -60,6 +124,10 @@ pub(crate) enum Subcommand {
     #[structopt(name = "install", author = "", version = "")]
     Install(command::Install),
 
+    /// Uninstalls a package from your toolchain
+    #[structopt(name = "uninstall", author = "", version = "")]
+    Uninstall(command::Uninstall),
+
     /// Pins your project's runtime or package manager
     #[structopt(name = "pin", author = "", version = "")]
     Pin(command::Pin),
@@ -112,6 +180,10 @@ otherwise, they will be written to `stdout`.
     #[structopt(name = "which", author = "", version = "")]
     Which(command::Which),
 
+    /// Checks that installed tools are intact
+    #[structopt(name = "verify", author = "", version = "")]
+    Verify(command::Verify),
+
     #[structopt(
         name = "use",
         author = "",
@@ -130,6 +202,7 @@ impl Subcommand {
         match self {
             Subcommand::Fetch(fetch) => fetch.run(session),
             Subcommand::Install(install) => install.run(session),
+            Subcommand::Uninstall(uninstall) => uninstall.run(session),
             Subcommand::Pin(pin) => pin.run(session),
             Subcommand::Config(config) => config.run(session),
             Subcommand::Current(current) => current.run(session),
@@ -137,6 +210,7 @@ impl Subcommand {
             Subcommand::Activate(activate) => activate.run(session),
             Subcommand::Completions(completions) => completions.run(session),
             Subcommand::Which(which) => which.run(session),
+            Subcommand::Verify(verify) => verify.run(session),
             Subcommand::Use(r#use) => r#use.run(session),
         }
     }
@@ -9,11 +9,17 @@ use crate::command::Command;
 
 #[derive(StructOpt)]
 pub(crate) struct Fetch {
-    /// The tool to install, e.g. `node` or `npm` or `yarn`
+    /// The tool to fetch, e.g. `node` or `npm` or `yarn`
     tool: String,
 
-    /// The version of the tool to install, e.g. `1.2.3` or `latest`
+    /// The version of the tool to fetch, e.g. `1.2.3` or `latest`
     version: String,
+
+    /// Only download the archive into the inventory, without unpacking it. A later
+    /// `notion fetch` or `notion install` of the same exact version unpacks it instead
+    /// of downloading it again. Packages still look up their registry metadata then.
+    #[structopt(long = "no-unpack")]
+    no_unpack: bool,
 }
 
 impl Command for Fetch {
@@ -23,20 +29,10 @@ impl Command for Fetch {
         let version = VersionSpec::parse(&self.version)?;
         let tool = ToolSpec::from_str_and_version(&self.tool, version);
 
-        match tool {
-            ToolSpec::Node(version) => {
-                session.fetch_node(&version)?;
-            }
-            ToolSpec::Yarn(version) => {
-                session.fetch_yarn(&version)?;
-            }
-            ToolSpec::Npm(_version) => {
-                // ISSUE(#292): Implement install for npm
-                unimplemented!("Fetching npm is not supported yet");
-            }
-            ToolSpec::Package(name, version) => {
-                session.fetch_package(name.to_string(), &version)?;
-            }
+        if self.no_unpack {
+            tool.fetch_archive(session)?;
+        } else {
+            tool.fetch(session)?;
         }
         session.add_event_end(ActivityKind::Fetch, ExitCode::Success);
         Ok(ExitCode::Success)
@@ -1,9 +1,10 @@
 use structopt::StructOpt;
 
+use notion_core::error::ErrorDetails;
 use notion_core::session::{ActivityKind, Session};
 use notion_core::tool::ToolSpec;
 use notion_core::version::VersionSpec;
-use notion_fail::{ExitCode, Fallible};
+use notion_fail::{throw, ExitCode, Fallible};
 
 use crate::command::Command;
 
@@ -14,19 +15,39 @@ pub(crate) struct Install {
 
     /// The version of the tool to install, e.g. `1.2.3` or `latest`
     version: Option<String>,
+
+    /// Shows what installing a package would do, without installing it
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
 }
 
 impl Command for Install {
     fn run(self, session: &mut Session) -> Fallible<ExitCode> {
         session.add_event_start(ActivityKind::Install);
 
+        // With no version given, install the Node version the project asks for, or
+        // else the latest version of the tool.
         let version = match self.version {
-            Some(version_string) => VersionSpec::parse(version_string).unwrap_or_default(),
-            None => VersionSpec::default(),
+            Some(version_string) => VersionSpec::parse(version_string)?,
+            None if self.tool == "node" => {
+                session.project_node_spec()?.unwrap_or(VersionSpec::Latest)
+            }
+            None => VersionSpec::Latest,
         };
         let tool = ToolSpec::from_str_and_version(&self.tool, version);
 
-        tool.install(session)?;
+        if self.dry_run {
+            match tool {
+                ToolSpec::Package(name, version) => {
+                    println!("{}", session.plan_package_install(name, &version)?);
+                }
+                _ => throw!(ErrorDetails::CommandNotImplemented {
+                    command_name: "install --dry-run".to_string(),
+                }),
+            }
+        } else {
+            tool.install(session)?;
+        }
 
         session.add_event_end(ActivityKind::Install, ExitCode::Success);
         Ok(ExitCode::Success)
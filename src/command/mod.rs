@@ -6,8 +6,10 @@ pub(crate) mod deactivate;
 pub(crate) mod fetch;
 pub(crate) mod install;
 pub(crate) mod pin;
+pub(crate) mod uninstall;
 #[macro_use]
 pub(crate) mod r#use;
+pub(crate) mod verify;
 pub(crate) mod which;
 
 pub(crate) use self::which::Which;
@@ -20,6 +22,8 @@ pub(crate) use fetch::Fetch;
 pub(crate) use install::Install;
 pub(crate) use pin::Pin;
 pub(crate) use r#use::Use;
+pub(crate) use uninstall::Uninstall;
+pub(crate) use verify::Verify;
 
 use notion_core::session::Session;
 use notion_fail::{ExitCode, Fallible};
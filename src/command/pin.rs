@@ -21,9 +21,15 @@ impl Command for Pin {
     fn run(self, session: &mut Session) -> Fallible<ExitCode> {
         session.add_event_start(ActivityKind::Pin);
 
+        // A pin is written into package.json, so it is never guessed: with no version
+        // given, only the Node version the project already asks for can be pinned.
         let version = match self.version {
             Some(version_string) => VersionSpec::parse(&version_string)?,
-            None => VersionSpec::default(),
+            None if self.tool == "node" => match session.project_node_spec()? {
+                Some(spec) => spec,
+                None => throw!(ErrorDetails::VersionNotSpecified { tool: self.tool }),
+            },
+            None => throw!(ErrorDetails::VersionNotSpecified { tool: self.tool }),
         };
 
         let tool = ToolSpec::from_str_and_version(&self.tool, version);
@@ -0,0 +1,23 @@
+use structopt::StructOpt;
+
+use notion_core::session::{ActivityKind, Session};
+use notion_fail::{ExitCode, Fallible};
+
+use crate::command::Command;
+
+#[derive(StructOpt)]
+pub(crate) struct Uninstall {
+    /// The package to uninstall, e.g. `ember-cli`
+    package: String,
+}
+
+impl Command for Uninstall {
+    fn run(self, session: &mut Session) -> Fallible<ExitCode> {
+        session.add_event_start(ActivityKind::Uninstall);
+
+        session.uninstall_package(&self.package)?;
+
+        session.add_event_end(ActivityKind::Uninstall, ExitCode::Success);
+        Ok(ExitCode::Success)
+    }
+}
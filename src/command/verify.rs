@@ -0,0 +1,61 @@
+use structopt::StructOpt;
+
+use notion_core::error::ErrorDetails;
+use notion_core::session::{ActivityKind, Session};
+use notion_core::tool::ToolSpec;
+use notion_core::verify::{platform_tools, verify_image};
+use notion_fail::{throw, ExitCode, Fallible};
+
+use crate::command::Command;
+
+#[derive(StructOpt)]
+pub(crate) struct Verify {
+    /// The tools to verify, e.g. `node@10.15.3` or `yarn@1.12.3`. If none are given,
+    /// the tools of the current platform are verified.
+    tools: Vec<String>,
+}
+
+impl Command for Verify {
+    fn run(self, session: &mut Session) -> Fallible<ExitCode> {
+        session.add_event_start(ActivityKind::Verify);
+
+        let tools = if self.tools.is_empty() {
+            current_tools(session)?
+        } else {
+            self.tools
+                .iter()
+                .map(|tool| ToolSpec::parse(tool))
+                .collect::<Fallible<Vec<_>>>()?
+        };
+
+        let mut intact = true;
+        for tool in &tools {
+            let report = verify_image(tool)?;
+            if report.is_ok() {
+                println!("{}: ok", report.tool);
+            } else {
+                intact = false;
+                println!("{}: failed", report.tool);
+                for discrepancy in &report.discrepancies {
+                    println!("    {}", discrepancy);
+                }
+            }
+        }
+
+        let exit_code = if intact {
+            ExitCode::Success
+        } else {
+            ExitCode::IntegrityError
+        };
+        session.add_event_end(ActivityKind::Verify, exit_code);
+        Ok(exit_code)
+    }
+}
+
+/// The tools pinned in the current platform, at their pinned versions.
+fn current_tools(session: &Session) -> Fallible<Vec<ToolSpec>> {
+    match session.current_platform()? {
+        Some(platform) => platform_tools(&platform),
+        None => throw!(ErrorDetails::NoVersionsFound),
+    }
+}
@@ -9,13 +9,21 @@ use notion_core::style::{display_error, ErrorContext};
 
 /// The entry point for the `notion` CLI.
 pub fn main() {
+    #[cfg(feature = "trace-fs")]
+    notion_core::init_trace_logger();
+
     let mut session = Session::new();
 
     session.add_event_start(ActivityKind::Notion);
 
     let notion = cli::Notion::from_args();
+    let json = notion.json;
     let exit_code = notion.run(&mut session).unwrap_or_else(|err| {
-        display_error(ErrorContext::Notion, &err);
+        if json {
+            eprintln!("{}", err.to_json());
+        } else {
+            display_error(ErrorContext::Notion, &err);
+        }
         session.add_event_error(ActivityKind::Notion, &err);
         err.exit_code()
     });
@@ -5,6 +5,9 @@ use notion_core::tool::execute_tool;
 use notion_fail::ExitCode;
 
 pub fn main() {
+    #[cfg(feature = "trace-fs")]
+    notion_core::init_trace_logger();
+
     let mut session = Session::new();
 
     session.add_event_start(ActivityKind::Tool);
@@ -5,4 +5,5 @@ mod support;
 mod intercept_global_installs;
 mod notion_current;
 mod notion_deactivate;
+mod notion_fetch;
 mod notion_pin;
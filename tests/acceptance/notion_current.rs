@@ -22,6 +22,12 @@ fn package_json_with_pinned_node(node: &str, npm: &str) -> String {
     )
 }
 
+const NODE_VERSION_INFO: &'static str = r#"[
+{"version":"v10.99.1040","npm":"6.2.26","files":["linux-x64","osx-x64-tar","win-x64-zip","win-x86-zip"]},
+{"version":"v9.27.6","npm":"5.6.17","files":["linux-x64","osx-x64-tar","win-x64-zip","win-x86-zip"]}
+]
+"#;
+
 #[test]
 fn pinned_project() {
     let s = sandbox()
@@ -104,3 +110,37 @@ fn no_project_with_user_node_default() {
             .with_stdout_contains("user: v9.11.2 (active)")
     );
 }
+
+#[test]
+fn unpinned_project_with_nvmrc() {
+    let s = sandbox()
+        .package_json(BASIC_PACKAGE_JSON)
+        .nvmrc("9\n")
+        .node_available_versions(NODE_VERSION_INFO)
+        .platform(r#"{"node":{"runtime":"10.99.1040","npm":"6.2.26"}}"#)
+        .build();
+
+    assert_that!(
+        s.notion("current"),
+        execs()
+            .with_status(0)
+            .with_stdout_contains("project: v9.27.6 (active)")
+            .with_stdout_contains("user: v10.99.1040")
+    );
+}
+
+#[test]
+fn pinned_project_ignores_nvmrc() {
+    let s = sandbox()
+        .package_json(&package_json_with_pinned_node("4.1.0", "2.14.3"))
+        .nvmrc("9\n")
+        .node_available_versions(NODE_VERSION_INFO)
+        .build();
+
+    assert_that!(
+        s.notion("current"),
+        execs()
+            .with_status(0)
+            .with_stdout_contains("project: v4.1.0 (active)")
+    );
+}
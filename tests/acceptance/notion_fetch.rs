@@ -0,0 +1,42 @@
+use crate::support::sandbox::{sandbox, DistroMetadata, YarnFixture};
+use hamcrest2::assert_that;
+use hamcrest2::prelude::*;
+use test_support::matchers::execs;
+
+const YARN_VERSION_FIXTURES: [DistroMetadata; 1] = [DistroMetadata {
+    version: "1.12.99",
+    compressed_size: 178,
+    uncompressed_size: Some(0x00280000),
+}];
+
+#[test]
+fn fetch_yarn_no_unpack() {
+    let s = sandbox()
+        .distro_mocks::<YarnFixture>(&YARN_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("fetch yarn 1.12.99 --no-unpack"),
+        execs().with_status(0)
+    );
+
+    assert!(s.yarn_archive_exists("1.12.99"));
+    assert!(!s.yarn_image_exists("1.12.99"));
+}
+
+#[test]
+fn fetch_yarn_unpacks_cached_archive_offline() {
+    let mut s = sandbox()
+        .distro_mocks::<YarnFixture>(&YARN_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("fetch yarn 1.12.99 --no-unpack"),
+        execs().with_status(0)
+    );
+
+    s.go_offline();
+
+    assert_that!(s.notion("fetch yarn 1.12.99"), execs().with_status(0));
+    assert!(s.yarn_image_exists("1.12.99"));
+}
@@ -245,17 +245,92 @@ fn pin_node_no_version() {
 
     assert_that!(
         s.notion("pin node"),
-        execs().with_status(0).with_stdout_contains(
-            "Pinned node version 10.99.1040 (with npm 6.2.26) in package.json"
-        )
+        execs()
+            .with_status(ExitCode::InvalidArguments as i32)
+            .with_stderr_contains("error: No version of node was specified.")
+    );
+
+    assert_eq!(s.read_package_json(), BASIC_PACKAGE_JSON,)
+}
+
+#[test]
+fn pin_node_no_version_json() {
+    let s = sandbox()
+        .package_json(BASIC_PACKAGE_JSON)
+        .node_available_versions(NODE_VERSION_INFO)
+        .distro_mocks::<NodeFixture>(&NODE_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("pin node --json"),
+        execs()
+            .with_status(ExitCode::InvalidArguments as i32)
+            .with_stderr_contains(&format!(
+                r#"{{"code":{},"kind":"VersionNotSpecified","message":"[..]No version of node was specified.[..]"}}"#,
+                ExitCode::InvalidArguments as i32
+            ))
+    );
+}
+
+#[test]
+fn pin_node_no_version_uses_nvmrc() {
+    let s = sandbox()
+        .package_json(BASIC_PACKAGE_JSON)
+        .nvmrc("v8\n")
+        .node_available_versions(NODE_VERSION_INFO)
+        .distro_mocks::<NodeFixture>(&NODE_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("pin node"),
+        execs()
+            .with_status(0)
+            .with_stdout_contains("Pinned node version 8.9.10 (with npm 5.6.7) in package.json")
     );
 
     assert_eq!(
         s.read_package_json(),
-        package_json_with_pinned_node("10.99.1040"),
+        package_json_with_pinned_node("8.9.10"),
     )
 }
 
+#[test]
+fn pin_node_unsupported_arch() {
+    let s = sandbox()
+        .package_json(BASIC_PACKAGE_JSON)
+        .node_available_versions(NODE_VERSION_INFO)
+        .distro_mocks::<NodeFixture>(&NODE_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("pin node 8 --arch sparc"),
+        execs()
+            .with_status(ExitCode::InvalidArguments as i32)
+            .with_stderr_contains("Unsupported architecture: 'sparc'")
+    );
+
+    assert_eq!(s.read_package_json(), BASIC_PACKAGE_JSON,)
+}
+
+#[test]
+fn pin_node_arch_without_build() {
+    // the index only lists x64 (and Windows x86) builds, so there is nothing to fetch for arm64
+    let s = sandbox()
+        .package_json(BASIC_PACKAGE_JSON)
+        .node_available_versions(NODE_VERSION_INFO)
+        .distro_mocks::<NodeFixture>(&NODE_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("pin node 8 --arch arm64"),
+        execs()
+            .with_status(ExitCode::InvalidArguments as i32)
+            .with_stderr_contains("Unsupported architecture: 'arm64'")
+    );
+
+    assert_eq!(s.read_package_json(), BASIC_PACKAGE_JSON,)
+}
+
 #[test]
 fn pin_node_removes_npm() {
     // Pinning Node will set the pinned version of npm to the default for that version, so it will be omitted
@@ -317,6 +392,27 @@ fn pin_yarn() {
     )
 }
 
+#[test]
+fn pin_yarn_quiet() {
+    let s = sandbox()
+        .package_json(&package_json_with_pinned_node("1.2.3"))
+        .yarn_available_versions(YARN_VERSION_INFO)
+        .distro_mocks::<YarnFixture>(&YARN_VERSION_FIXTURES)
+        .build();
+
+    assert_that!(
+        s.notion("pin yarn 1.4 --quiet"),
+        execs()
+            .with_status(0)
+            .with_stdout_does_not_contain("Pinned yarn version")
+    );
+
+    assert_eq!(
+        s.read_package_json(),
+        package_json_with_pinned_node_yarn("1.2.3", "1.4.159"),
+    )
+}
+
 #[test]
 fn pin_yarn_latest() {
     let s = sandbox()
@@ -349,13 +445,13 @@ fn pin_yarn_no_version() {
     assert_that!(
         s.notion("pin yarn"),
         execs()
-            .with_status(0)
-            .with_stdout_contains("Pinned yarn version 1.2.42 in package.json")
+            .with_status(ExitCode::InvalidArguments as i32)
+            .with_stderr_contains("error: No version of yarn was specified.")
     );
 
     assert_eq!(
         s.read_package_json(),
-        package_json_with_pinned_node_yarn("1.2.3", "1.2.42"),
+        package_json_with_pinned_node("1.2.3"),
     )
 }
 
@@ -370,7 +466,7 @@ fn pin_yarn_missing_release() {
         s.notion("pin yarn 1.3.1"),
         execs()
             .with_status(4)
-            .with_stderr_contains("error: yarn version 1.3.1 not found")
+            .with_stderr_contains("error: yarn@1.3.1 not found")
     );
 
     assert_eq!(
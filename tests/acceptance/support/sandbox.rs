@@ -228,6 +228,13 @@ impl SandboxBuilder {
         self
     }
 
+    /// Set the .nvmrc for the sandbox (chainable)
+    pub fn nvmrc(mut self, contents: &str) -> Self {
+        let nvmrc_file = self.root().join(".nvmrc");
+        self.files.push(FileBuilder::new(nvmrc_file, contents));
+        self
+    }
+
     /// Set the platform.json for the sandbox (chainable)
     pub fn platform(mut self, contents: &str) -> Self {
         self.files
@@ -438,6 +445,12 @@ fn node_index_file() -> PathBuf {
 fn node_index_expiry_file() -> PathBuf {
     node_cache_dir().join("index.json.expires")
 }
+fn yarn_distro_file(version: &str) -> PathBuf {
+    yarn_inventory_dir().join(format!("yarn-v{}.tar.gz", version))
+}
+fn yarn_image_dir(version: &str) -> PathBuf {
+    notion_tools_dir().join("image").join("yarn").join(version)
+}
 fn package_json_file(mut root: PathBuf) -> PathBuf {
     root.push("package.json");
     root
@@ -472,6 +485,8 @@ impl Sandbox {
             .env("NOTION_HOME", notion_home())
             .env("PATH", &self.path)
             .env("NOTION_POSTSCRIPT", notion_postscript())
+            // the fixture Node archives don't contain a runnable `node`
+            .env("NOTION_SKIP_SMOKE_TEST", "1")
             .env_remove("NOTION_SHELL")
             .env_remove("MSYSTEM"); // assume cmd.exe everywhere on windows
 
@@ -530,6 +545,19 @@ impl Sandbox {
         let postscript_file = notion_postscript();
         read_file_to_string(postscript_file)
     }
+
+    /// Removes all the mocks, so that any later request to the mock server fails.
+    pub fn go_offline(&mut self) {
+        self.mocks.clear();
+    }
+
+    pub fn yarn_archive_exists(&self, version: &str) -> bool {
+        yarn_distro_file(version).is_file()
+    }
+
+    pub fn yarn_image_exists(&self, version: &str) -> bool {
+        yarn_image_dir(version).is_dir()
+    }
 }
 
 impl Drop for Sandbox {
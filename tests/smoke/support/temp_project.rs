@@ -196,7 +196,8 @@ impl TempProject {
 
     /// Verify that the input Node version has been fetched.
     pub fn node_version_is_fetched(&self, version: &str) -> bool {
-        let distro_file_name = path::node_distro_file_name(version);
+        let arch = ok_or_panic! { path::arch() };
+        let distro_file_name = ok_or_panic! { path::node_distro_file_name(version, arch) };
         let inventory_dir = ok_or_panic! { path::node_inventory_dir() };
         inventory_dir.join(distro_file_name).exists()
     }
@@ -249,8 +250,7 @@ impl TempProject {
 
     /// Verify that the input Npm version has been unpacked.
     pub fn npm_version_is_unpacked(&self, version: &str) -> bool {
-        // ISSUE(#292): This is maybe the wrong place to unpack npm?
-        let unpack_dir = ok_or_panic! { path::package_image_dir("npm", version) };
+        let unpack_dir = ok_or_panic! { path::npm_image_dir(version) };
         unpack_dir.exists()
     }
 